@@ -0,0 +1,24 @@
+#![no_main]
+
+use dns_resolver::message::{
+    label::{parse_label_bytes, resolve_labels},
+    name::Name,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the first two bytes pick where the name starts in the rest
+    let Some((offset, msg)) = data.split_first_chunk::<2>() else {
+        return;
+    };
+    let offset = u16::from_be_bytes(*offset) as usize;
+    let Ok((read, mut labels)) = parse_label_bytes(msg, offset) else {
+        return;
+    };
+    assert!(offset + read <= msg.len());
+    if resolve_labels(msg, offset, &mut labels).is_ok() {
+        let name = Name::from_labels(&labels).unwrap();
+        let (_, parsed) = Name::parse(msg, offset).unwrap();
+        assert_eq!(parsed, name);
+    }
+});
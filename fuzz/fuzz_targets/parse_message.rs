@@ -0,0 +1,19 @@
+#![no_main]
+
+use dns_resolver::message::{view::MessageRef, Message};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let parsed = Message::parse(data);
+    if let Ok((read, msg)) = &parsed {
+        assert_eq!(*read, data.len());
+        let _ = msg.to_string();
+    }
+
+    // the view checks the sections lazily, but accepts whatever the owned
+    // parser accepts and decodes it the same way
+    let view = MessageRef::parse(data).and_then(MessageRef::to_message);
+    if let Ok((_, msg)) = parsed {
+        assert_eq!(view, Ok(msg));
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use dns_resolver::{errors::DnsError, message::Message};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok((_, msg)) = Message::parse(data) else {
+        return;
+    };
+    // expanding names the input compressed differently can outgrow the buffer
+    let mut buf = vec![0u8; u16::MAX as usize];
+    let n = match msg.write(&mut buf) {
+        Ok(n) => n,
+        Err(DnsError::BufferTooSmall { .. }) => return,
+        Err(e) => panic!("cannot write a parsed message: {}", e),
+    };
+    let (_, parsed) = Message::parse(&buf[..n]).expect("cannot parse a written message");
+    assert_eq!(parsed, msg);
+});
@@ -1,16 +1,374 @@
-use std::error::Error;
+use std::{error::Error, fmt, io, net::SocketAddr};
 
+use crate::message::{header::ResponseCode, name::Name, types::RecordType};
+
+/// Errors from reading and writing DNS data. Wire format errors carry the
+/// byte offset into the message at which the problem was found, so a
+/// truncated packet can be told apart from a compression loop.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DnsError {
     Generic(String),
+    /// malformed wire data not covered by one of the variants below.
     ParseError(String),
+    /// data that cannot be represented on the wire.
     MarshalError(String),
+    /// malformed presentation format text, such as a zone file, with the
+    /// 1-based position of the offending input.
+    SyntaxError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// the message ends before the `needed` bytes at `offset` could be read,
+    /// only `available` remain.
+    Truncated {
+        needed: usize,
+        available: usize,
+        offset: usize,
+    },
+    /// the name at `offset` follows too many compression pointers, or a
+    /// pointer back into a part of the name already read.
+    PointerLoop {
+        offset: usize,
+    },
+    /// the compression pointer at `offset` points to `target`, which is not
+    /// earlier in the message or falls inside the header.
+    InvalidPointer {
+        target: usize,
+        offset: usize,
+    },
+    /// a label of `len` octets at `offset`, labels are limited to 63.
+    LabelTooLong {
+        len: usize,
+        offset: usize,
+    },
+    /// a name of `len` octets at `offset`, names are limited to 255.
+    NameTooLong {
+        len: usize,
+        offset: usize,
+    },
+    /// the rdata of a `rtype` record at `offset` does not match the format of
+    /// the type.
+    InvalidRdata {
+        rtype: RecordType,
+        offset: usize,
+        reason: String,
+    },
+    /// the header declares `count` entries for `section` but the message
+    /// ends after `found`.
+    CountMismatch {
+        section: &'static str,
+        count: u16,
+        found: u16,
+    },
+    /// `len` bytes follow the last section, starting at `offset`.
+    TrailingBytes {
+        len: usize,
+        offset: usize,
+    },
+    /// writing `needed` bytes at `offset` does not fit, the buffer only has
+    /// `available` bytes left.
+    BufferTooSmall {
+        needed: usize,
+        available: usize,
+        offset: usize,
+    },
+    /// a message failed TSIG verification, with the TSIG error describing
+    /// why, RFC 8945 5.2. NOTAUTH means the message was not signed.
+    TsigFailed {
+        error: ResponseCode,
+    },
+    /// an RRset failed DNSSEC verification.
+    Validation(ValidationError),
 }
 
-impl std::fmt::Display for DnsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Generic(s) | Self::ParseError(s) | Self::MarshalError(s) => write!(f, "{}", s),
+            Self::SyntaxError {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            Self::Truncated {
+                needed,
+                available,
+                offset,
+            } => write!(
+                f,
+                "truncated message: require {} bytes at offset {}, found {}",
+                needed, offset, available
+            ),
+            Self::PointerLoop { offset } => {
+                write!(f, "compression pointer loop in name at offset {}", offset)
+            }
+            Self::InvalidPointer { target, offset } => write!(
+                f,
+                "compression pointer at offset {} to {} must point to an earlier name",
+                offset, target
+            ),
+            Self::LabelTooLong { len, offset } => write!(
+                f,
+                "label of {} octets at offset {} exceeds 63 octets",
+                len, offset
+            ),
+            Self::NameTooLong { len, offset } => write!(
+                f,
+                "name of {} octets at offset {} exceeds 255 octets",
+                len, offset
+            ),
+            Self::InvalidRdata {
+                rtype,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "invalid {} rdata at offset {}: {}",
+                rtype, offset, reason
+            ),
+            Self::CountMismatch {
+                section,
+                count,
+                found,
+            } => write!(
+                f,
+                "header declares {} {} entries, message holds {}",
+                count, section, found
+            ),
+            Self::TrailingBytes { len, offset } => {
+                write!(f, "{} trailing bytes at offset {}", len, offset)
+            }
+            Self::BufferTooSmall {
+                needed,
+                available,
+                offset,
+            } => write!(
+                f,
+                "buffer too small: require {} bytes at offset {}, found {}",
+                needed, offset, available
+            ),
+            Self::TsigFailed { error } => write!(f, "TSIG verification failed: {}", error),
+            Self::Validation(e) => write!(f, "{}", e),
+        }
     }
 }
 
 impl Error for DnsError {}
+
+/// Why a signature over an RRset does not verify, RFC 4035 5.3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// there are no records to sign or verify.
+    EmptyRrset,
+    /// the DNSKEY public key is malformed for its algorithm.
+    InvalidKey,
+    UnsupportedAlgorithm(u8),
+    /// the signature does not match the data under the key.
+    BadSignature,
+    /// the RRSIG signer is not `zone`, or `name` is not in it.
+    WrongSigner {
+        name: Name,
+        zone: Name,
+    },
+    /// the RRSIG covers `covered` rather than the type of the RRset.
+    WrongType {
+        covered: RecordType,
+        rtype: RecordType,
+    },
+    /// the RRSIG label count exceeds the labels of the owner `name`.
+    TooManyLabels {
+        labels: u8,
+        name: Name,
+    },
+    /// the RRSIG for `name` `rtype` has expired or is not yet valid.
+    OutsideValidity {
+        name: Name,
+        rtype: RecordType,
+    },
+    /// no DNSKEY of `zone` verifies the RRSIG for `name` `rtype`.
+    NoVerifyingKey {
+        zone: Name,
+        name: Name,
+        rtype: RecordType,
+    },
+    /// the NSEC or NSEC3 records do not prove that `name`, or its `rtype`
+    /// records if given, do not exist.
+    DenialNotProven {
+        name: Name,
+        rtype: Option<RecordType>,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyRrset => write!(f, "empty RRset"),
+            Self::InvalidKey => write!(f, "invalid DNSKEY public key"),
+            Self::UnsupportedAlgorithm(alg) => write!(f, "unsupported DNSSEC algorithm {}", alg),
+            Self::BadSignature => write!(f, "signature does not verify"),
+            Self::WrongSigner { name, zone } => {
+                write!(f, "{} is not signed by zone {}", name, zone)
+            }
+            Self::WrongType { covered, rtype } => {
+                write!(f, "RRSIG covers {}, not {}", covered, rtype)
+            }
+            Self::TooManyLabels { labels, name } => {
+                write!(f, "RRSIG label count {} exceeds owner {}", labels, name)
+            }
+            Self::OutsideValidity { name, rtype } => write!(
+                f,
+                "RRSIG for {} {} is outside its validity period",
+                name, rtype
+            ),
+            Self::NoVerifyingKey { zone, name, rtype } => write!(
+                f,
+                "no key of {} verifies the RRSIG for {} {}",
+                zone, name, rtype
+            ),
+            Self::DenialNotProven {
+                name,
+                rtype: Some(rtype),
+                reason,
+            } => write!(f, "denial of {} {} not proven: {}", name, rtype, reason),
+            Self::DenialNotProven {
+                name,
+                rtype: None,
+                reason,
+            } => write!(f, "denial of {} not proven: {}", name, reason),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl From<ValidationError> for DnsError {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+/// Errors from resolving a name, as opposed to errors in the messages
+/// exchanged while doing so.
+#[derive(Debug)]
+pub enum ResolveError {
+    /// `server` did not respond before the timeout.
+    Timeout {
+        server: SocketAddr,
+    },
+    /// `server` responded SERVFAIL.
+    ServerFailure {
+        server: SocketAddr,
+    },
+    /// `server` responded with an error other than SERVFAIL or NXDOMAIN.
+    ErrorResponse {
+        server: SocketAddr,
+        rcode: ResponseCode,
+    },
+    /// `server` was delegated `name` but neither answered authoritatively nor
+    /// referred to servers closer to it, RFC 4697 2.1.
+    LameDelegation {
+        server: SocketAddr,
+        name: Name,
+    },
+    /// `name` does not exist, an authoritative NXDOMAIN response.
+    NoSuchName {
+        name: Name,
+    },
+    /// the CNAME chain through `name` is longer than the resolver follows.
+    CnameChainTooLong {
+        name: Name,
+    },
+    /// the referral chain for `name` is longer than the resolver follows.
+    TooManyReferrals {
+        name: Name,
+    },
+    /// the answer for the `rtype` records of `name` failed DNSSEC
+    /// validation.
+    Bogus {
+        name: Name,
+        rtype: RecordType,
+    },
+    /// `server` rejected an update because a prerequisite did not hold,
+    /// YXDOMAIN, YXRRSET, NXDOMAIN or NXRRSET, RFC 2136 3.2.5.
+    PrerequisiteFailed {
+        server: SocketAddr,
+        rcode: ResponseCode,
+    },
+    /// `server` refused an update, with NOTAUTH or NOTZONE if it is not
+    /// authoritative for the zone.
+    UpdateRefused {
+        server: SocketAddr,
+        rcode: ResponseCode,
+    },
+    /// the response from `server` could not be parsed.
+    InvalidResponse {
+        server: SocketAddr,
+        error: DnsError,
+    },
+    /// the TLS connection to `server` failed, for instance because its
+    /// certificate could not be authenticated.
+    Tls {
+        server: SocketAddr,
+        error: rustls::Error,
+    },
+    /// the HTTP exchange with `server` failed or its response was not a DNS
+    /// message.
+    Http {
+        server: SocketAddr,
+        reason: String,
+    },
+    /// the query could not be built or written.
+    Query(DnsError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout { server } => write!(f, "{} timed out", server),
+            Self::ServerFailure { server } => write!(f, "{} responded SERVFAIL", server),
+            Self::ErrorResponse { server, rcode } => write!(f, "{} responded {}", server, rcode),
+            Self::LameDelegation { server, name } => {
+                write!(f, "lame delegation for {} at {}", name, server)
+            }
+            Self::NoSuchName { name } => write!(f, "{} does not exist", name),
+            Self::CnameChainTooLong { name } => write!(f, "CNAME chain too long at {}", name),
+            Self::TooManyReferrals { name } => write!(f, "too many referrals for {}", name),
+            Self::Bogus { name, rtype } => {
+                write!(f, "DNSSEC validation failed for {} {}", name, rtype)
+            }
+            Self::PrerequisiteFailed { server, rcode } => {
+                write!(f, "update prerequisite failed at {}: {}", server, rcode)
+            }
+            Self::UpdateRefused { server, rcode } => {
+                write!(f, "{} refused the update: {}", server, rcode)
+            }
+            Self::InvalidResponse { server, error } => {
+                write!(f, "invalid response from {}: {}", server, error)
+            }
+            Self::Tls { server, error } => write!(f, "TLS with {} failed: {}", server, error),
+            Self::Http { server, reason } => write!(f, "HTTP with {} failed: {}", server, reason),
+            Self::Query(e) => write!(f, "invalid query: {}", e),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ResolveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidResponse { error, .. } | Self::Query(error) => Some(error),
+            Self::Tls { error, .. } => Some(error),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
@@ -0,0 +1,10 @@
+//! An iterative DNS resolver, along with the DNS message types it uses to
+//! talk to name servers and DNSSEC validation of their responses.
+
+pub mod errors;
+pub mod message;
+pub mod resolver;
+pub mod transport;
+pub mod validator;
+
+pub use resolver::Resolver;
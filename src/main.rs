@@ -1,143 +1,117 @@
 use std::{
     env,
-    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
-    time,
+    net::{IpAddr, SocketAddr},
 };
 
-use crate::message::label::{parse_label_bytes, resolve_labels};
-
-mod errors;
-mod message;
+use dns_resolver::{
+    message::{name::Name, types::RecordType},
+    transport::{
+        https::HttpsTransport,
+        tls::{TlsTransport, DOT_PORT},
+        TcpTransport, Transport,
+    },
+    Resolver,
+};
 
 fn main() -> std::io::Result<()> {
-    {
-        let args: Vec<String> = env::args().collect();
-        let mut domain = "dns.google.com";
-        if args.len() == 2 {
-            domain = &args[1];
+    let mut domain = "dns.google.com".to_string();
+    let mut dnssec = false;
+    let mut tcp = false;
+    let mut forward = None;
+    let mut dot = None;
+    let mut doh = None;
+    for arg in env::args().skip(1) {
+        if arg == "--dnssec" {
+            dnssec = true;
+        } else if arg == "--tcp" {
+            tcp = true;
+        } else if let Some(addr) = arg.strip_prefix("--forward=") {
+            forward = Some(addr.to_string());
+        } else if let Some(server) = arg.strip_prefix("--dot=") {
+            dot = Some(server.to_string());
+        } else if let Some(server) = arg.strip_prefix("--doh=") {
+            doh = Some(server.to_string());
+        } else {
+            domain = arg;
         }
+    }
 
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
-        let well_known: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(198, 41, 0, 4), 53);
-        let result = resolve_dns(0, domain, &socket, well_known);
-        if let Some(result) = result {
-            println!("Found {}", result);
-        } else {
-            println!("Not found");
+    let domain: Name = match domain.parse() {
+        Ok(domain) => domain,
+        Err(e) => {
+            println!("Invalid domain {}: {}", domain, e);
+            return Ok(());
+        }
+    };
+    let forward = match forward.map(|addr| addr.parse::<IpAddr>()) {
+        Some(Ok(addr)) => Some((addr, 53).into()),
+        Some(Err(e)) => {
+            println!("Invalid --forward address: {}", e);
+            return Ok(());
         }
+        None => None,
+    };
+
+    if let Some(server) = dot {
+        // NAME@ADDRESS, the name authenticates the server
+        let Some((server_name, addr)) = upstream("--dot", &server) else {
+            return Ok(());
+        };
+        let resolver = Resolver::with_transport(TlsTransport::new(server_name)?)
+            .forward((addr, DOT_PORT).into());
+        query(resolver, &domain, dnssec);
+    } else if let Some(server) = doh {
+        // URI@ADDRESS, the host of the URI authenticates the server
+        let Some((uri, addr)) = upstream("--doh", &server) else {
+            return Ok(());
+        };
+        let resolver =
+            Resolver::with_transport(HttpsTransport::new(uri)?).forward((addr, 443).into());
+        query(resolver, &domain, dnssec);
+    } else if tcp {
+        let resolver = Resolver::with_transport(TcpTransport::new());
+        query(forward_to(resolver, forward), &domain, dnssec);
+    } else {
+        query(forward_to(Resolver::new()?, forward), &domain, dnssec);
     }
     Ok(())
 }
 
-fn do_query(
-    domain: &str,
-    socket: &std::net::UdpSocket,
-    saddr: SocketAddrV4,
-) -> std::io::Result<(message::Message, [u8; 1600])> {
-    println!("Querying {} for {}", saddr, domain);
-
-    let query_msg = message::Message::new_query(domain, 1, 1, false).unwrap();
-    let mut qb = [0u8; 1600];
-    let mut rb = [0u8; 1600];
-
-    let w = query_msg.write(&mut qb[..]).unwrap();
-    let qb = &qb[..w];
-
-    socket.set_read_timeout(Some(time::Duration::from_secs(5)))?;
-
-    socket.send_to(&qb, saddr)?;
-    let r = socket.recv(&mut rb[..])?;
-    let (_, msg) = message::Message::parse(&rb[..r]).unwrap();
-    return Ok((msg, rb));
-}
-
-fn resolve_dns_inner(
-    depth: usize,
-    domain: &str,
-    socket: &std::net::UdpSocket,
-    saddr: SocketAddrV4,
-    ns_map: &mut std::collections::HashMap<String, Ipv4Addr>,
-) -> Option<Ipv4Addr> {
-    if depth > 3 {
+/// splits the NAME@ADDRESS argument of `flag`, printing what is wrong with
+/// it.
+fn upstream<'a>(flag: &str, arg: &'a str) -> Option<(&'a str, IpAddr)> {
+    let Some((name, addr)) = arg.rsplit_once('@') else {
+        println!("{} expects NAME@ADDRESS", flag);
         return None;
-    }
-
-    let well_known: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(198, 41, 0, 4), 53);
-    let (msg, raw) = do_query(domain, &socket, saddr).unwrap();
-    if msg.hdr.rcode != message::header::ResponseCode::NoError {
-        println!("Error when querying {}: {:?}", saddr, msg.hdr.rcode);
-        return None;
-    }
-    if msg.hdr.ancount > 0 {
-        println!("Found answer for domain: {}", domain);
-        for answer in &msg.an {
-            // TODO: Handle AAAA records
-            if answer.t != 1 {
-                continue;
-            }
-            let rdata = &answer.rdata;
-            let addr = std::net::Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]);
-            return Some(addr);
+    };
+    match addr.parse() {
+        Ok(addr) => Some((name, addr)),
+        Err(e) => {
+            println!("Invalid {} address {}: {}", flag, addr, e);
+            None
         }
     }
+}
 
-    for ns in &msg.ns {
-        if ns.t == 1 && ns.class == 1 {
-            let mut name = ns.name.clone();
-            let domain = resolve_labels(&raw[..], &mut name).unwrap();
-            let rdata = &ns.rdata;
-            let addr = std::net::Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]);
-
-            ns_map.insert(domain, addr);
-        }
+fn forward_to<T: Transport>(resolver: Resolver<T>, upstream: Option<SocketAddr>) -> Resolver<T> {
+    match upstream {
+        Some(upstream) => resolver.forward(upstream),
+        None => resolver,
     }
+}
 
-    for ar in &msg.ar {
-        if ar.t == 2 {
-            let rdata = &ar.rdata;
-            let (_, mut labels) = parse_label_bytes(rdata.as_slice()).unwrap();
-            let ar_domain = resolve_labels(&raw[..], &mut labels).unwrap();
-            if domain != ar_domain {
-                let addr = ns_map.get(&ar_domain);
-
-                // resolve_dns(depth + 1, &ar_domain, &socket, well_known);
-                if let Some(addr) = addr {
-                    if let Some(result) = resolve_dns_inner(
-                        depth + 1,
-                        domain,
-                        socket,
-                        SocketAddrV4::new(*addr, 53),
-                        ns_map,
-                    ) {
-                        return Some(result);
-                    }
-                }
-
-                if let Some(addr) =
-                    resolve_dns_inner(depth + 1, &ar_domain, socket, well_known, ns_map)
-                {
-                    if let Some(result) = resolve_dns_inner(
-                        depth + 1,
-                        domain,
-                        socket,
-                        SocketAddrV4::new(addr, 53),
-                        ns_map,
-                    ) {
-                        return Some(result);
-                    }
-                }
-            }
+fn query<T: Transport>(resolver: Resolver<T>, domain: &Name, dnssec: bool) {
+    let resolver = resolver.dnssec(dnssec);
+    println!("Querying {} for {}", domain.to_unicode(), RecordType::A);
+    if dnssec {
+        match resolver.lookup_secure(domain, RecordType::A) {
+            Ok((msg, security)) => println!("\n{}\nDNSSEC: {:?}", msg, security),
+            Err(e) => println!("Not found: {}", e),
         }
+        return;
+    }
+    match resolver.lookup(domain, RecordType::A) {
+        Ok(msg) => println!("\n{}", msg),
+        Err(e) => println!("Not found: {}", e),
     }
-    return None;
-}
-
-fn resolve_dns(
-    depth: usize,
-    domain: &str,
-    socket: &std::net::UdpSocket,
-    saddr: SocketAddrV4,
-) -> Option<Ipv4Addr> {
-    let mut ns_map = std::collections::HashMap::new();
-    return resolve_dns_inner(depth, domain, socket, saddr, &mut ns_map);
 }
@@ -0,0 +1,271 @@
+// fluent construction of messages
+
+use crate::errors::DnsError;
+
+use super::{
+    edns::Edns,
+    header::{Header, Opcode, ResponseCode},
+    name::Name,
+    question::Question,
+    rr::ResourceRecord,
+    types::{DnsClass, RecordType},
+    Message,
+};
+
+/// Builds a message section by section. The header counts are derived from
+/// the sections when the message is built, so they always match.
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    msg: Message,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBuilder {
+    /// starts a standard query with a random ID and no flags set.
+    pub fn new() -> Self {
+        Self {
+            msg: Message {
+                hdr: Header {
+                    id: rand::random(),
+                    opcode: Opcode::StandardQuery,
+                    ..Default::default()
+                },
+                qd: vec![],
+                an: vec![],
+                ns: vec![],
+                ar: vec![],
+                edns: None,
+            },
+        }
+    }
+
+    /// starts from `Message::response_to(query)`.
+    pub fn response_to(query: &Message) -> Self {
+        Self {
+            msg: Message::response_to(query),
+        }
+    }
+
+    pub fn id(mut self, id: u16) -> Self {
+        self.msg.hdr.id = id;
+        self
+    }
+
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.msg.hdr.opcode = opcode;
+        self
+    }
+
+    /// sets the full response code, codes above 15 need `edns` as well.
+    pub fn rcode(mut self, rcode: ResponseCode) -> Self {
+        self.msg.hdr.rcode = rcode;
+        self
+    }
+
+    /// sets QR, marking the message as a response.
+    pub fn response(mut self, response: bool) -> Self {
+        self.msg.hdr.qr = response;
+        self
+    }
+
+    pub fn authoritative(mut self, aa: bool) -> Self {
+        self.msg.hdr.aa = aa;
+        self
+    }
+
+    pub fn truncated(mut self, tc: bool) -> Self {
+        self.msg.hdr.tc = tc;
+        self
+    }
+
+    pub fn recursion_desired(mut self, rd: bool) -> Self {
+        self.msg.hdr.rd = rd;
+        self
+    }
+
+    pub fn recursion_available(mut self, ra: bool) -> Self {
+        self.msg.hdr.ra = ra;
+        self
+    }
+
+    pub fn authentic_data(mut self, ad: bool) -> Self {
+        self.msg.hdr.ad = ad;
+        self
+    }
+
+    pub fn checking_disabled(mut self, cd: bool) -> Self {
+        self.msg.hdr.cd = cd;
+        self
+    }
+
+    pub fn question(mut self, qname: Name, qtype: RecordType, qclass: DnsClass) -> Self {
+        self.msg.qd.push(Question {
+            qname,
+            qtype,
+            qclass,
+        });
+        self
+    }
+
+    pub fn answer(mut self, rr: ResourceRecord) -> Self {
+        self.msg.an.push(rr);
+        self
+    }
+
+    pub fn authority(mut self, rr: ResourceRecord) -> Self {
+        self.msg.ns.push(rr);
+        self
+    }
+
+    /// adds a record to the additional section. EDNS is set with `edns`
+    /// rather than as an OPT record here.
+    pub fn additional(mut self, rr: ResourceRecord) -> Self {
+        self.msg.ar.push(rr);
+        self
+    }
+
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.msg.edns = Some(edns);
+        self
+    }
+
+    /// Finishes the message, filling in the section counts and splitting the
+    /// response code between the header and EDNS. Fails if a section holds
+    /// more than 65535 entries, or the response code needs EDNS but none was
+    /// set.
+    pub fn build(self) -> Result<Message, DnsError> {
+        let mut msg = self.msg;
+        let rcode = msg.hdr.rcode;
+        msg.set_rcode(rcode)?;
+        let count = |n: usize, section: &str| {
+            u16::try_from(n).map_err(|_| {
+                DnsError::MarshalError(format!(
+                    "build: {} section cannot hold more than {} entries",
+                    section,
+                    u16::MAX
+                ))
+            })
+        };
+        msg.hdr.qdcount = count(msg.qd.len(), "question")?;
+        msg.hdr.ancount = count(msg.an.len(), "answer")?;
+        msg.hdr.nscount = count(msg.ns.len(), "authority")?;
+        msg.hdr.arcount = count(msg.ar.len() + msg.edns.is_some() as usize, "additional")?;
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::MessageBuilder;
+    use crate::message::{
+        edns::Edns,
+        header::{Opcode, ResponseCode},
+        name::Name,
+        rdata::RData,
+        rr::ResourceRecord,
+        types::{DnsClass, RecordType},
+        Message,
+    };
+
+    #[test]
+    fn test_build_message() {
+        let qname: Name = "www.example.com".parse().unwrap();
+        let a = ResourceRecord {
+            name: qname.clone(),
+            t: RecordType::A,
+            class: DnsClass::IN,
+            ttl: 300,
+            rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        };
+        let msg = MessageBuilder::new()
+            .id(7)
+            .opcode(Opcode::StatusRequest)
+            .authoritative(true)
+            .question(qname.clone(), RecordType::A, DnsClass::IN)
+            .question(qname.clone(), RecordType::AAAA, DnsClass::IN)
+            .answer(a.clone())
+            .additional(a.clone())
+            .edns(Edns::default())
+            .build()
+            .unwrap();
+        assert_eq!(msg.hdr.id, 7);
+        assert_eq!(msg.hdr.opcode, Opcode::StatusRequest);
+        assert!(msg.hdr.aa && !msg.hdr.qr);
+        let counts = (
+            msg.hdr.qdcount,
+            msg.hdr.ancount,
+            msg.hdr.nscount,
+            msg.hdr.arcount,
+        );
+        assert_eq!(counts, (2, 1, 0, 2));
+
+        // the counts match the wire form
+        let mut buf = [0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        assert_eq!(Message::parse(&buf[..n]).unwrap().1, msg);
+    }
+
+    #[test]
+    fn test_response_to() {
+        let query = Message::new_query(
+            &"example.com".parse().unwrap(),
+            RecordType::MX,
+            DnsClass::IN,
+            true,
+            Some(Edns::default()),
+        )
+        .unwrap();
+        let response = Message::response_to(&query);
+        assert_eq!(response.hdr.id, query.hdr.id);
+        assert!(response.hdr.qr && response.hdr.rd);
+        assert_eq!(response.qd, query.qd);
+        assert_eq!(response.hdr.qdcount, 1);
+        assert_eq!(response.hdr.arcount, 0);
+        assert_eq!(response.edns, None);
+
+        let refused = MessageBuilder::response_to(&query)
+            .rcode(ResponseCode::Refused)
+            .build()
+            .unwrap();
+        assert_eq!(refused.hdr.rcode, ResponseCode::Refused);
+    }
+
+    #[test]
+    fn test_extended_rcode() {
+        let query = Message::new_query(
+            &"example.com".parse().unwrap(),
+            RecordType::A,
+            DnsClass::IN,
+            true,
+            None,
+        )
+        .unwrap();
+        MessageBuilder::response_to(&query)
+            .rcode(ResponseCode::BadCookie)
+            .build()
+            .unwrap_err();
+
+        let msg = MessageBuilder::response_to(&query)
+            .rcode(ResponseCode::BadCookie)
+            .authentic_data(true)
+            .edns(Edns::default())
+            .build()
+            .unwrap();
+        assert_eq!(msg.hdr.rcode, ResponseCode::YxRrset);
+        assert_eq!(msg.edns.as_ref().unwrap().extended_rcode, 1);
+
+        let mut buf = [0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        let (_, parsed) = Message::parse(&buf[..n]).unwrap();
+        assert_eq!(parsed.rcode(), ResponseCode::BadCookie);
+        assert!(parsed.hdr.ad && !parsed.hdr.cd);
+        assert!(parsed.to_string().contains("status: BADCOOKIE"));
+    }
+}
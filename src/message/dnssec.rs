@@ -0,0 +1,438 @@
+// DNSSEC record data, RFC 4034 and RFC 5155
+
+use std::fmt;
+
+use data_encoding::{BASE32HEX_NOPAD, BASE64, HEXUPPER};
+
+use crate::errors::DnsError;
+
+use super::{name::Name, types::RecordType};
+
+/// DNSKEY flag marking a zone key, RFC 4034 2.1.1
+pub const DNSKEY_FLAG_ZONE: u16 = 0x0100;
+/// DNSKEY flag marking a secure entry point (KSK), RFC 4034 2.1.1
+pub const DNSKEY_FLAG_SEP: u16 = 0x0001;
+/// NSEC3 opt-out flag, RFC 5155 3.1.2.1
+pub const NSEC3_FLAG_OPT_OUT: u8 = 0x01;
+
+/// DNS public key, RFC 4034 2
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Dnskey {
+    pub flags: u16,
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+
+/// Resource record signature, RFC 4034 3
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rrsig {
+    pub type_covered: RecordType,
+    pub algorithm: u8,
+    pub labels: u8,
+    pub original_ttl: u32,
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer_name: Name,
+    pub signature: Vec<u8>,
+}
+
+/// Delegation signer, RFC 4034 5
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Ds {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+/// Next secure, RFC 4034 4
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Nsec {
+    pub next_domain: Name,
+    pub types: Vec<RecordType>,
+}
+
+/// Hashed next secure, RFC 5155 3
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Nsec3 {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    pub next_hashed_owner: Vec<u8>,
+    pub types: Vec<RecordType>,
+}
+
+/// NSEC3 parameters, RFC 5155 4
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Nsec3Param {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+
+impl Dnskey {
+    pub fn is_zone_key(&self) -> bool {
+        self.flags & DNSKEY_FLAG_ZONE != 0
+    }
+
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags & DNSKEY_FLAG_SEP != 0
+    }
+
+    /// the wire form of the rdata, as used for key tags and DS digests.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(4 + self.public_key.len());
+        b.extend_from_slice(&self.flags.to_be_bytes());
+        b.push(self.protocol);
+        b.push(self.algorithm);
+        b.extend_from_slice(&self.public_key);
+        b
+    }
+
+    /// Key tag as per RFC 4034 Appendix B.
+    pub fn key_tag(&self) -> u16 {
+        if self.algorithm == 1 {
+            // RSA/MD5 uses the third to last and second to last octets of
+            // the modulus
+            let k = &self.public_key;
+            if k.len() < 3 {
+                return 0;
+            }
+            return u16::from_be_bytes([k[k.len() - 3], k[k.len() - 2]]);
+        }
+        let mut ac: u32 = 0;
+        for (i, b) in self.to_bytes().iter().enumerate() {
+            ac += if i & 1 == 1 {
+                *b as u32
+            } else {
+                (*b as u32) << 8
+            };
+        }
+        ac += (ac >> 16) & 0xFFFF;
+        (ac & 0xFFFF) as u16
+    }
+}
+
+impl Nsec3 {
+    pub fn opt_out(&self) -> bool {
+        self.flags & NSEC3_FLAG_OPT_OUT != 0
+    }
+}
+
+/// Decodes the type bitmap `b` of a `rtype` record, NSEC or NSEC3, RFC 4034
+/// 4.1.2. `offset` is where `b` starts in the message, for errors.
+pub fn decode_type_bitmap(
+    b: &[u8],
+    rtype: RecordType,
+    offset: usize,
+) -> Result<Vec<RecordType>, DnsError> {
+    let invalid = |idx: usize, reason: String| DnsError::InvalidRdata {
+        rtype,
+        offset: offset + idx,
+        reason,
+    };
+    let mut types = vec![];
+    let mut idx = 0;
+    let mut last_window = None;
+    while idx < b.len() {
+        if idx + 2 > b.len() {
+            return Err(invalid(idx, "truncated type bitmap window".to_string()));
+        }
+        let window = b[idx];
+        let len = b[idx + 1] as usize;
+        if last_window.is_some_and(|last| last >= window) {
+            return Err(invalid(idx, "type bitmap windows out of order".to_string()));
+        }
+        if len == 0 || len > 32 || idx + 2 + len > b.len() {
+            return Err(invalid(
+                idx + 1,
+                format!("invalid type bitmap length {}", len),
+            ));
+        }
+        idx += 2;
+        for (i, octet) in b[idx..idx + len].iter().enumerate() {
+            for bit in 0..8 {
+                if octet & (0x80 >> bit) != 0 {
+                    let t = (window as u16) << 8 | (i * 8 + bit) as u16;
+                    types.push(RecordType::from(t));
+                }
+            }
+        }
+        idx += len;
+        last_window = Some(window);
+    }
+    Ok(types)
+}
+
+/// Encodes types into an NSEC/NSEC3 type bitmap, RFC 4034 4.1.2.
+pub fn encode_type_bitmap(types: &[RecordType]) -> Vec<u8> {
+    let mut types = types.iter().map(|t| u16::from(*t)).collect::<Vec<_>>();
+    types.sort_unstable();
+    types.dedup();
+
+    let mut b = vec![];
+    let mut idx = 0;
+    while idx < types.len() {
+        let window = (types[idx] >> 8) as u8;
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        while idx < types.len() && (types[idx] >> 8) as u8 == window {
+            let low = (types[idx] & 0xFF) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            len = low / 8 + 1;
+            idx += 1;
+        }
+        b.push(window);
+        b.push(len as u8);
+        b.extend_from_slice(&bitmap[..len]);
+    }
+    b
+}
+
+/// Formats a signature timestamp as YYYYMMDDHHmmSS in UTC, RFC 4034 3.2.
+pub fn format_timestamp(t: u32) -> String {
+    let secs = t as i64;
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parses a signature timestamp, either YYYYMMDDHHmmSS in UTC or seconds
+/// since the epoch, RFC 4034 3.2.
+pub fn parse_timestamp(s: &str) -> Option<u32> {
+    if s.len() != 14 {
+        return s.parse().ok();
+    }
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| s[range].parse::<i64>().unwrap_or(0);
+    let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    // days from civil, the inverse of format_timestamp
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    u32::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+fn write_types(f: &mut fmt::Formatter<'_>, types: &[RecordType]) -> fmt::Result {
+    for t in types {
+        write!(f, " {}", t)?;
+    }
+    Ok(())
+}
+
+fn salt_to_string(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        return "-".to_string();
+    }
+    HEXUPPER.encode(salt)
+}
+
+impl fmt::Display for Dnskey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.flags,
+            self.protocol,
+            self.algorithm,
+            BASE64.encode(&self.public_key)
+        )
+    }
+}
+
+impl fmt::Display for Rrsig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {}",
+            self.type_covered,
+            self.algorithm,
+            self.labels,
+            self.original_ttl,
+            format_timestamp(self.expiration),
+            format_timestamp(self.inception),
+            self.key_tag,
+            self.signer_name,
+            BASE64.encode(&self.signature)
+        )
+    }
+}
+
+impl fmt::Display for Ds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.key_tag,
+            self.algorithm,
+            self.digest_type,
+            HEXUPPER.encode(&self.digest)
+        )
+    }
+}
+
+impl fmt::Display for Nsec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.next_domain)?;
+        write_types(f, &self.types)
+    }
+}
+
+impl fmt::Display for Nsec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.hash_algorithm,
+            self.flags,
+            self.iterations,
+            salt_to_string(&self.salt),
+            BASE32HEX_NOPAD.encode(&self.next_hashed_owner)
+        )?;
+        write_types(f, &self.types)
+    }
+}
+
+impl fmt::Display for Nsec3Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.hash_algorithm,
+            self.flags,
+            self.iterations,
+            salt_to_string(&self.salt)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use data_encoding::BASE64;
+
+    use super::{
+        decode_type_bitmap, encode_type_bitmap, format_timestamp, parse_timestamp, Dnskey, Ds,
+        Nsec3Param,
+    };
+    use crate::{errors::DnsError, message::types::RecordType};
+
+    #[test]
+    fn test_type_bitmap() {
+        // example from RFC 4034 4.3
+        let b = [
+            0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 0x04, 0x1b, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20,
+        ];
+        let types = decode_type_bitmap(&b, RecordType::NSEC, 0).unwrap();
+        assert_eq!(
+            types,
+            vec![
+                RecordType::A,
+                RecordType::MX,
+                RecordType::RRSIG,
+                RecordType::NSEC,
+                RecordType::Unknown(1234),
+            ]
+        );
+        assert_eq!(encode_type_bitmap(&types), b.to_vec());
+
+        assert_eq!(
+            decode_type_bitmap(&[0x00, 0x00], RecordType::NSEC, 20),
+            Err(DnsError::InvalidRdata {
+                rtype: RecordType::NSEC,
+                offset: 21,
+                reason: "invalid type bitmap length 0".to_string(),
+            })
+        );
+        assert_eq!(
+            decode_type_bitmap(&[0x01, 0x01, 0x80, 0x00, 0x01, 0x80], RecordType::NSEC3, 20),
+            Err(DnsError::InvalidRdata {
+                rtype: RecordType::NSEC3,
+                offset: 23,
+                reason: "type bitmap windows out of order".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_key_tag() {
+        // the 2010 root KSK has key tag 19036
+        let key = BASE64
+            .decode(
+                b"AwEAAagAIKlVZrpC6Ia7gEzahOR+9W29euxhJhVVLOyQbSEW0O8gcCjFFVQUTf6v58fLjwBd0YI0EzrAcQqBGCzh/RStIoO8g0NfnfL2MTJRkxoXbfDaUeVPQuYEhg37NZWAJQ9VnMVDxP/VHL496M/QZxkjf5/Efucp2gaDX6RS6CXpoY68LsvPVjR0ZSwzz1apAzvN9dlzEheX7ICJBBtuA6G3LQpzW5hOA2hzCTMjJPJ8LbqF6dsV6DoBQzgul0sGIcGOYl7OyQdXfZ57relSQageu+ipAdTTJ25AsRTAoub8ONGcLmqrAmRLKBP1dfwhYB4N7knNnulqQxA+Uk1ihz0="
+                    .as_slice(),
+            )
+            .unwrap();
+        let dnskey = Dnskey {
+            flags: 257,
+            protocol: 3,
+            algorithm: 8,
+            public_key: key,
+        };
+        assert_eq!(dnskey.key_tag(), 19036);
+        assert!(dnskey.is_zone_key());
+        assert!(dnskey.is_secure_entry_point());
+    }
+
+    #[test]
+    fn test_presentation() {
+        assert_eq!(format_timestamp(0), "19700101000000");
+        assert_eq!(format_timestamp(1709251199), "20240229235959");
+        assert_eq!(parse_timestamp("20240229235959"), Some(1709251199));
+        assert_eq!(parse_timestamp("19700101000000"), Some(0));
+        assert_eq!(parse_timestamp("1709251199"), Some(1709251199));
+        assert_eq!(parse_timestamp("20241301000000"), None);
+
+        let ds = Ds {
+            key_tag: 20326,
+            algorithm: 8,
+            digest_type: 2,
+            digest: vec![0xE0, 0x6D, 0x44],
+        };
+        assert_eq!(ds.to_string(), "20326 8 2 E06D44");
+
+        let param = Nsec3Param {
+            hash_algorithm: 1,
+            ..Default::default()
+        };
+        assert_eq!(param.to_string(), "1 0 0 -");
+    }
+}
@@ -0,0 +1,414 @@
+// EDNS(0) OPT pseudo-record, RFC 6891
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use data_encoding::{HEXLOWER, HEXUPPER};
+
+use crate::errors::DnsError;
+
+use super::{
+    name::Name,
+    rdata::RData,
+    rr::ResourceRecord,
+    types::{DnsClass, RecordType},
+};
+
+/// payload size advertised by default, as recommended by DNS flag day 2020.
+pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// the DO bit, RFC 3225.
+pub const FLAG_DNSSEC_OK: u16 = 0x8000;
+
+pub const OPTION_NSID: u16 = 3;
+pub const OPTION_CLIENT_SUBNET: u16 = 8;
+pub const OPTION_COOKIE: u16 = 10;
+pub const OPTION_TCP_KEEPALIVE: u16 = 11;
+pub const OPTION_PADDING: u16 = 12;
+pub const OPTION_EXTENDED_ERROR: u16 = 15;
+
+/// Client subnet, RFC 7871 6
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSubnet {
+    pub family: u16,
+    pub source_prefix: u8,
+    pub scope_prefix: u8,
+    pub address: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    /// name server identifier, RFC 5001
+    Nsid(Vec<u8>),
+    ClientSubnet(ClientSubnet),
+    /// client and optional server cookie, RFC 7873
+    Cookie {
+        client: [u8; 8],
+        server: Vec<u8>,
+    },
+    /// idle timeout in units of 100ms, RFC 7828
+    TcpKeepalive(Option<u16>),
+    /// number of zero octets of padding, RFC 7830
+    Padding(u16),
+    /// extended DNS error, RFC 8914
+    ExtendedError {
+        info_code: u16,
+        extra_text: String,
+    },
+    Unknown(u16, Vec<u8>),
+}
+
+/// EDNS(0) information carried by the OPT record in the additional section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    /// upper 8 bits of the 12 bit response code.
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub flags: u16,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Self {
+            udp_payload_size: DEFAULT_UDP_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            flags: 0,
+            options: vec![],
+        }
+    }
+}
+
+impl EdnsOption {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Nsid(_) => OPTION_NSID,
+            Self::ClientSubnet(_) => OPTION_CLIENT_SUBNET,
+            Self::Cookie { .. } => OPTION_COOKIE,
+            Self::TcpKeepalive(_) => OPTION_TCP_KEEPALIVE,
+            Self::Padding(_) => OPTION_PADDING,
+            Self::ExtendedError { .. } => OPTION_EXTENDED_ERROR,
+            Self::Unknown(code, _) => *code,
+        }
+    }
+
+    /// parses the data `b` of an option with code `code`, where the option
+    /// starts at `offset`.
+    fn parse(code: u16, b: &[u8], offset: usize) -> Result<Self, DnsError> {
+        let invalid = || DnsError::InvalidRdata {
+            rtype: RecordType::OPT,
+            offset,
+            reason: format!("invalid EDNS option {} of {} bytes", code, b.len()),
+        };
+        let option = match code {
+            OPTION_NSID => Self::Nsid(b.to_vec()),
+            OPTION_CLIENT_SUBNET => {
+                if b.len() < 4 {
+                    return Err(invalid());
+                }
+                Self::ClientSubnet(ClientSubnet {
+                    family: u16::from_be_bytes([b[0], b[1]]),
+                    source_prefix: b[2],
+                    scope_prefix: b[3],
+                    address: b[4..].to_vec(),
+                })
+            }
+            OPTION_COOKIE => {
+                // the server cookie is absent or 8 to 32 octets
+                if b.len() != 8 && !(16..=40).contains(&b.len()) {
+                    return Err(invalid());
+                }
+                let mut client = [0u8; 8];
+                client.copy_from_slice(&b[..8]);
+                Self::Cookie {
+                    client,
+                    server: b[8..].to_vec(),
+                }
+            }
+            OPTION_TCP_KEEPALIVE => match b.len() {
+                0 => Self::TcpKeepalive(None),
+                2 => Self::TcpKeepalive(Some(u16::from_be_bytes([b[0], b[1]]))),
+                _ => return Err(invalid()),
+            },
+            OPTION_PADDING => Self::Padding(b.len() as u16),
+            OPTION_EXTENDED_ERROR => {
+                if b.len() < 2 {
+                    return Err(invalid());
+                }
+                Self::ExtendedError {
+                    info_code: u16::from_be_bytes([b[0], b[1]]),
+                    extra_text: String::from_utf8_lossy(&b[2..]).into_owned(),
+                }
+            }
+            _ => Self::Unknown(code, b.to_vec()),
+        };
+        Ok(option)
+    }
+
+    fn write(&self, dest: &mut Vec<u8>) {
+        let start = dest.len();
+        dest.extend_from_slice(&self.code().to_be_bytes());
+        dest.extend_from_slice(&[0, 0]);
+        match self {
+            Self::Nsid(b) | Self::Unknown(_, b) => dest.extend_from_slice(b),
+            Self::ClientSubnet(ecs) => {
+                dest.extend_from_slice(&ecs.family.to_be_bytes());
+                dest.push(ecs.source_prefix);
+                dest.push(ecs.scope_prefix);
+                dest.extend_from_slice(&ecs.address);
+            }
+            Self::Cookie { client, server } => {
+                dest.extend_from_slice(client);
+                dest.extend_from_slice(server);
+            }
+            Self::TcpKeepalive(timeout) => {
+                if let Some(timeout) = timeout {
+                    dest.extend_from_slice(&timeout.to_be_bytes());
+                }
+            }
+            Self::Padding(n) => dest.resize(dest.len() + *n as usize, 0),
+            Self::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                dest.extend_from_slice(&info_code.to_be_bytes());
+                dest.extend_from_slice(extra_text.as_bytes());
+            }
+        }
+        let len = (dest.len() - start - 4) as u16;
+        dest[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+impl Edns {
+    pub fn dnssec_ok(&self) -> bool {
+        self.flags & FLAG_DNSSEC_OK != 0
+    }
+
+    pub fn set_dnssec_ok(&mut self, dnssec_ok: bool) {
+        if dnssec_ok {
+            self.flags |= FLAG_DNSSEC_OK;
+        } else {
+            self.flags &= !FLAG_DNSSEC_OK;
+        }
+    }
+
+    /// Reads the EDNS information out of an OPT record. The record is no
+    /// longer in a message, so offsets in errors count from the start of its
+    /// rdata. `Message::parse` has already checked the options in place.
+    pub fn from_record(rr: &ResourceRecord) -> Result<Self, DnsError> {
+        let invalid = |reason: &str| DnsError::InvalidRdata {
+            rtype: rr.t,
+            offset: 0,
+            reason: reason.to_string(),
+        };
+        if rr.t != RecordType::OPT {
+            return Err(invalid("not an OPT record"));
+        }
+        if !rr.name.is_root() {
+            return Err(invalid("OPT record must be owned by the root"));
+        }
+        let options = match &rr.rdata {
+            RData::Unknown(b) => parse_options(b, 0)?,
+            _ => return Err(invalid("unexpected OPT rdata")),
+        };
+
+        let ttl = rr.ttl.to_be_bytes();
+        Ok(Self {
+            udp_payload_size: u16::from(rr.class),
+            extended_rcode: ttl[0],
+            version: ttl[1],
+            flags: u16::from_be_bytes([ttl[2], ttl[3]]),
+            options,
+        })
+    }
+
+    /// Builds the OPT record carrying this EDNS information.
+    pub fn to_record(&self) -> ResourceRecord {
+        let mut rdata = vec![];
+        for option in &self.options {
+            option.write(&mut rdata);
+        }
+        let flags = self.flags.to_be_bytes();
+        ResourceRecord {
+            name: Name::root(),
+            t: RecordType::OPT,
+            class: DnsClass::from(self.udp_payload_size),
+            ttl: u32::from_be_bytes([self.extended_rcode, self.version, flags[0], flags[1]]),
+            rdata: RData::Unknown(rdata),
+        }
+    }
+}
+
+/// Parses the options in the rdata `b` of an OPT record, where `b` starts at
+/// `offset` in the message.
+pub fn parse_options(b: &[u8], offset: usize) -> Result<Vec<EdnsOption>, DnsError> {
+    let mut options = vec![];
+    let mut idx = 0;
+    while idx < b.len() {
+        if idx + 4 > b.len() {
+            return Err(DnsError::InvalidRdata {
+                rtype: RecordType::OPT,
+                offset: offset + idx,
+                reason: "truncated EDNS option header".to_string(),
+            });
+        }
+        let code = u16::from_be_bytes([b[idx], b[idx + 1]]);
+        let len = u16::from_be_bytes([b[idx + 2], b[idx + 3]]) as usize;
+        if idx + 4 + len > b.len() {
+            return Err(DnsError::InvalidRdata {
+                rtype: RecordType::OPT,
+                offset: offset + idx,
+                reason: format!("EDNS option {} overruns the rdata", code),
+            });
+        }
+        options.push(EdnsOption::parse(
+            code,
+            &b[idx + 4..idx + 4 + len],
+            offset + idx,
+        )?);
+        idx += 4 + len;
+    }
+    Ok(options)
+}
+
+impl fmt::Display for ClientSubnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.family {
+            1 => {
+                let mut b = [0u8; 4];
+                let n = self.address.len().min(4);
+                b[..n].copy_from_slice(&self.address[..n]);
+                write!(f, "{}", Ipv4Addr::from(b))?;
+            }
+            2 => {
+                let mut b = [0u8; 16];
+                let n = self.address.len().min(16);
+                b[..n].copy_from_slice(&self.address[..n]);
+                write!(f, "{}", Ipv6Addr::from(b))?;
+            }
+            _ => write!(f, "{}", HEXUPPER.encode(&self.address))?,
+        }
+        write!(f, "/{}/{}", self.source_prefix, self.scope_prefix)
+    }
+}
+
+/// Renders the option as one line of dig's OPT pseudosection.
+impl fmt::Display for EdnsOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nsid(b) => write!(f, "; NSID: {}", HEXUPPER.encode(b)),
+            Self::ClientSubnet(subnet) => write!(f, "; CLIENT-SUBNET: {}", subnet),
+            Self::Cookie { client, server } => write!(
+                f,
+                "; COOKIE: {}{}",
+                HEXLOWER.encode(client),
+                HEXLOWER.encode(server)
+            ),
+            Self::TcpKeepalive(None) => write!(f, "; TCP-KEEPALIVE"),
+            Self::TcpKeepalive(Some(timeout)) => {
+                write!(f, "; TCP-KEEPALIVE: {}.{} secs", timeout / 10, timeout % 10)
+            }
+            Self::Padding(n) => write!(f, "; PADDING: {}", n),
+            Self::ExtendedError {
+                info_code,
+                extra_text,
+            } if extra_text.is_empty() => write!(f, "; EDE: {}", info_code),
+            Self::ExtendedError {
+                info_code,
+                extra_text,
+            } => write!(f, "; EDE: {} ({})", info_code, extra_text),
+            Self::Unknown(code, b) => write!(f, "; OPT={}: {}", code, HEXUPPER.encode(b)),
+        }
+    }
+}
+
+impl fmt::Display for Edns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "; EDNS: version: {}, flags:", self.version)?;
+        if self.dnssec_ok() {
+            write!(f, " do")?;
+        }
+        write!(f, "; udp: {}", self.udp_payload_size)?;
+        for option in &self.options {
+            write!(f, "\n{}", option)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClientSubnet, Edns, EdnsOption};
+    use crate::{
+        errors::DnsError,
+        message::{rdata::RData, types::RecordType},
+    };
+
+    #[test]
+    fn test_edns_record_round_trip() {
+        let mut edns = Edns {
+            udp_payload_size: 4096,
+            extended_rcode: 1,
+            options: vec![
+                EdnsOption::Nsid(vec![]),
+                EdnsOption::ClientSubnet(ClientSubnet {
+                    family: 1,
+                    source_prefix: 24,
+                    scope_prefix: 0,
+                    address: vec![192, 0, 2],
+                }),
+                EdnsOption::Cookie {
+                    client: [1, 2, 3, 4, 5, 6, 7, 8],
+                    server: vec![],
+                },
+                EdnsOption::TcpKeepalive(Some(300)),
+                EdnsOption::Padding(12),
+                EdnsOption::ExtendedError {
+                    info_code: 18,
+                    extra_text: "prohibited".to_string(),
+                },
+                EdnsOption::Unknown(65001, vec![0xAB]),
+            ],
+            ..Default::default()
+        };
+        edns.set_dnssec_ok(true);
+        assert!(edns.dnssec_ok());
+
+        let rr = edns.to_record();
+        assert_eq!(rr.ttl, 0x0100_8000);
+        assert_eq!(Edns::from_record(&rr).unwrap(), edns);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut rr = Edns::default().to_record();
+        rr.rdata = RData::Unknown(vec![0, 10, 0, 3, 1, 2, 3]);
+        assert_eq!(
+            Edns::from_record(&rr),
+            Err(DnsError::InvalidRdata {
+                rtype: RecordType::OPT,
+                offset: 0,
+                reason: "invalid EDNS option 10 of 3 bytes".to_string(),
+            })
+        );
+        rr.rdata = RData::Unknown(vec![0, 3, 0, 4, 1]);
+        Edns::from_record(&rr).unwrap_err();
+
+        // in a message the error points at the option
+        let mut msg = vec![0xFF; 7];
+        msg.extend_from_slice(&[0, 3, 0, 0, 0, 10, 0, 3, 1, 2, 3]);
+        assert_eq!(
+            RData::parse(&msg, 7, 11, RecordType::OPT),
+            Err(DnsError::InvalidRdata {
+                rtype: RecordType::OPT,
+                offset: 11,
+                reason: "invalid EDNS option 10 of 3 bytes".to_string(),
+            })
+        );
+    }
+}
@@ -1,4 +1,6 @@
 // DNS message header definition
+use std::fmt;
+
 pub const HEADER_LENGTH: usize = 12;
 
 pub use crate::errors::DnsError;
@@ -9,6 +11,10 @@ pub enum Opcode {
     StandardQuery,
     InverseQuery,
     StatusRequest,
+    /// zone change notification, RFC 1996.
+    Notify,
+    /// dynamic update, RFC 2136.
+    Update,
     Reserved(u8),
 }
 
@@ -18,22 +24,29 @@ impl From<u8> for Opcode {
             0 => Self::StandardQuery,
             1 => Self::InverseQuery,
             2 => Self::StatusRequest,
+            4 => Self::Notify,
+            5 => Self::Update,
             _ => Self::Reserved(value),
         }
     }
 }
 
-impl Into<u8> for Opcode {
-    fn into(self) -> u8 {
-        match self {
-            Self::StandardQuery => 0,
-            Self::InverseQuery => 1,
-            Self::StatusRequest => 2,
-            Self::Reserved(v) => v,
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::StandardQuery => 0,
+            Opcode::InverseQuery => 1,
+            Opcode::StatusRequest => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Reserved(v) => v,
         }
     }
 }
 
+/// Response codes, RFC 6895 2.3. Codes above 15 do not fit the header and
+/// need the upper bits carried by EDNS, see `Message::rcode`, or are only
+/// used in the error field of TSIG and TKEY records.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResponseCode {
     #[default]
@@ -43,11 +56,35 @@ pub enum ResponseCode {
     NameError,
     NotImpemented,
     Refused,
-    Reserved(u8),
+    /// a name exists when it should not, RFC 2136.
+    YxDomain,
+    /// an RRset exists when it should not, RFC 2136.
+    YxRrset,
+    /// an RRset that should exist does not, RFC 2136.
+    NxRrset,
+    /// the server is not authoritative for the zone, or the request is not
+    /// authorized, RFC 2136 and RFC 8945.
+    NotAuth,
+    /// a name is not within the zone, RFC 2136.
+    NotZone,
+    /// unsupported EDNS version, RFC 6891.
+    BadVers,
+    /// TSIG signature failure, RFC 8945. It shares code 16 with BADVERS, so
+    /// parsing 16 gives `BadVers`.
+    BadSig,
+    BadKey,
+    BadTime,
+    BadMode,
+    BadName,
+    BadAlg,
+    BadTrunc,
+    /// bad or missing server cookie, RFC 7873.
+    BadCookie,
+    Reserved(u16),
 }
 
-impl From<u8> for ResponseCode {
-    fn from(value: u8) -> Self {
+impl From<u16> for ResponseCode {
+    fn from(value: u16) -> Self {
         match value {
             0 => Self::NoError,
             1 => Self::FormatError,
@@ -55,21 +92,88 @@ impl From<u8> for ResponseCode {
             3 => Self::NameError,
             4 => Self::NotImpemented,
             5 => Self::Refused,
+            6 => Self::YxDomain,
+            7 => Self::YxRrset,
+            8 => Self::NxRrset,
+            9 => Self::NotAuth,
+            10 => Self::NotZone,
+            16 => Self::BadVers,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            19 => Self::BadMode,
+            20 => Self::BadName,
+            21 => Self::BadAlg,
+            22 => Self::BadTrunc,
+            23 => Self::BadCookie,
             _ => Self::Reserved(value),
         }
     }
 }
 
-impl Into<u8> for ResponseCode {
-    fn into(self) -> u8 {
+impl From<ResponseCode> for u16 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
+            ResponseCode::NoError => 0,
+            ResponseCode::FormatError => 1,
+            ResponseCode::ServerFailure => 2,
+            ResponseCode::NameError => 3,
+            ResponseCode::NotImpemented => 4,
+            ResponseCode::Refused => 5,
+            ResponseCode::YxDomain => 6,
+            ResponseCode::YxRrset => 7,
+            ResponseCode::NxRrset => 8,
+            ResponseCode::NotAuth => 9,
+            ResponseCode::NotZone => 10,
+            ResponseCode::BadVers | ResponseCode::BadSig => 16,
+            ResponseCode::BadKey => 17,
+            ResponseCode::BadTime => 18,
+            ResponseCode::BadMode => 19,
+            ResponseCode::BadName => 20,
+            ResponseCode::BadAlg => 21,
+            ResponseCode::BadTrunc => 22,
+            ResponseCode::BadCookie => 23,
+            ResponseCode::Reserved(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::NoError => 0,
-            Self::FormatError => 1,
-            Self::ServerFailure => 2,
-            Self::NameError => 3,
-            Self::NotImpemented => 4,
-            Self::Refused => 5,
-            Self::Reserved(v) => v,
+            Self::StandardQuery => write!(f, "QUERY"),
+            Self::InverseQuery => write!(f, "IQUERY"),
+            Self::StatusRequest => write!(f, "STATUS"),
+            Self::Notify => write!(f, "NOTIFY"),
+            Self::Update => write!(f, "UPDATE"),
+            Self::Reserved(v) => write!(f, "OPCODE{}", v),
+        }
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoError => write!(f, "NOERROR"),
+            Self::FormatError => write!(f, "FORMERR"),
+            Self::ServerFailure => write!(f, "SERVFAIL"),
+            Self::NameError => write!(f, "NXDOMAIN"),
+            Self::NotImpemented => write!(f, "NOTIMP"),
+            Self::Refused => write!(f, "REFUSED"),
+            Self::YxDomain => write!(f, "YXDOMAIN"),
+            Self::YxRrset => write!(f, "YXRRSET"),
+            Self::NxRrset => write!(f, "NXRRSET"),
+            Self::NotAuth => write!(f, "NOTAUTH"),
+            Self::NotZone => write!(f, "NOTZONE"),
+            Self::BadVers => write!(f, "BADVERS"),
+            Self::BadSig => write!(f, "BADSIG"),
+            Self::BadKey => write!(f, "BADKEY"),
+            Self::BadTime => write!(f, "BADTIME"),
+            Self::BadMode => write!(f, "BADMODE"),
+            Self::BadName => write!(f, "BADNAME"),
+            Self::BadAlg => write!(f, "BADALG"),
+            Self::BadTrunc => write!(f, "BADTRUNC"),
+            Self::BadCookie => write!(f, "BADCOOKIE"),
+            Self::Reserved(v) => write!(f, "RCODE{}", v),
         }
     }
 }
@@ -80,7 +184,7 @@ impl Into<u8> for ResponseCode {
 /// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
 /// |                      ID                       |
 /// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-/// |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
+/// |QR|   Opcode  |AA|TC|RD|RA| Z|AD|CD|   RCODE   |
 /// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
 /// |                    QDCOUNT                    |
 /// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
@@ -99,19 +203,30 @@ pub struct Header {
     pub tc: bool,
     pub rd: bool,
     pub ra: bool,
+    /// reserved, must be zero.
+    pub z: bool,
+    /// authentic data, the server validated the answer with DNSSEC,
+    /// RFC 4035 3.2.3.
+    pub ad: bool,
+    /// checking disabled, the client does its own DNSSEC validation,
+    /// RFC 4035 3.2.2.
+    pub cd: bool,
+    /// the lower 4 bits of the response code, the rest are carried by EDNS.
     pub rcode: ResponseCode,
     pub qdcount: u16,
     pub ancount: u16,
-    pub arcount: u16,
     pub nscount: u16,
+    pub arcount: u16,
 }
 
 impl Header {
     pub fn write(&self, dest: &mut [u8]) -> Result<(), DnsError> {
         if dest.len() < 12 {
-            return Err(DnsError::MarshalError(
-                "write: header requires at least 12 bytes".to_string(),
-            ));
+            return Err(DnsError::BufferTooSmall {
+                needed: 12,
+                available: dest.len(),
+                offset: 0,
+            });
         }
 
         // write id
@@ -138,9 +253,14 @@ impl Header {
         // write ra
         dest[3] = if self.ra { 1u8 } else { 0u8 } << 7;
 
+        // write z, ad and cd
+        dest[3] |= if self.z { 1u8 } else { 0u8 } << 6;
+        dest[3] |= if self.ad { 1u8 } else { 0u8 } << 5;
+        dest[3] |= if self.cd { 1u8 } else { 0u8 } << 4;
+
         // write rcode
-        let rcode: u8 = self.rcode.into();
-        dest[3] |= rcode;
+        let rcode: u16 = self.rcode.into();
+        dest[3] |= (rcode & 0x0f) as u8;
 
         // write qdcount
         let b = self.qdcount.to_be_bytes();
@@ -152,13 +272,13 @@ impl Header {
         dest[6] = b[0];
         dest[7] = b[1];
 
-        // write arcount
-        let b = self.arcount.to_be_bytes();
+        // write nscount
+        let b = self.nscount.to_be_bytes();
         dest[8] = b[0];
         dest[9] = b[1];
 
-        // write nscount
-        let b = self.nscount.to_be_bytes();
+        // write arcount
+        let b = self.arcount.to_be_bytes();
         dest[10] = b[0];
         dest[11] = b[1];
         Ok(())
@@ -166,30 +286,66 @@ impl Header {
 
     pub fn parse(src: &[u8]) -> Result<Self, DnsError> {
         if src.len() < 12 {
-            return Err(DnsError::ParseError(
-                "read: header should have 12 bytes".to_string(),
-            ));
+            return Err(DnsError::Truncated {
+                needed: 12,
+                available: src.len(),
+                offset: 0,
+            });
         }
-        let mut hdr = Self::default();
-        hdr.id = u16::from_be_bytes([src[0], src[1]]);
-        hdr.qdcount = u16::from_be_bytes([src[4], src[5]]);
-        hdr.ancount = u16::from_be_bytes([src[6], src[7]]);
-        hdr.arcount = u16::from_be_bytes([src[8], src[9]]);
-        hdr.nscount = u16::from_be_bytes([src[10], src[11]]);
-
-        hdr.qr = src[2] & 0x80 != 0;
-        hdr.aa = src[2] & 0x04 != 0;
-        hdr.tc = src[2] & 0x02 != 0;
-        hdr.rd = src[2] & 0x01 != 0;
-        hdr.ra = src[3] & 0x80 != 0;
-
-        hdr.opcode = (src[2] & 0x78 >> 3).into();
-        hdr.rcode = (src[3] & 0x0f).into();
+        let hdr = Self {
+            id: u16::from_be_bytes([src[0], src[1]]),
+            qdcount: u16::from_be_bytes([src[4], src[5]]),
+            ancount: u16::from_be_bytes([src[6], src[7]]),
+            nscount: u16::from_be_bytes([src[8], src[9]]),
+            arcount: u16::from_be_bytes([src[10], src[11]]),
+
+            qr: src[2] & 0x80 != 0,
+            aa: src[2] & 0x04 != 0,
+            tc: src[2] & 0x02 != 0,
+            rd: src[2] & 0x01 != 0,
+            ra: src[3] & 0x80 != 0,
+            z: src[3] & 0x40 != 0,
+            ad: src[3] & 0x20 != 0,
+            cd: src[3] & 0x10 != 0,
+
+            opcode: ((src[2] & 0x78) >> 3).into(),
+            rcode: ((src[3] & 0x0f) as u16).into(),
+        };
 
         Ok(hdr)
     }
 }
 
+/// Renders the header the way dig prints it, over two lines.
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            self.opcode, self.rcode, self.id
+        )?;
+        write!(f, ";; flags:")?;
+        for (set, flag) in [
+            (self.qr, "qr"),
+            (self.aa, "aa"),
+            (self.tc, "tc"),
+            (self.rd, "rd"),
+            (self.ra, "ra"),
+            (self.ad, "ad"),
+            (self.cd, "cd"),
+        ] {
+            if set {
+                write!(f, " {}", flag)?;
+            }
+        }
+        write!(
+            f,
+            "; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            self.qdcount, self.ancount, self.nscount, self.arcount
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -197,19 +353,24 @@ mod test {
 
     #[test]
     fn test_write_and_parse_header() {
-        let mut initial_header = Header::default();
-        initial_header.id = rand::random::<u16>();
-        initial_header.qr = true;
-        initial_header.tc = true;
-        initial_header.ra = true;
-
-        initial_header.qdcount = 5;
-        initial_header.ancount = 3;
-        initial_header.arcount = 2;
-        initial_header.nscount = 7;
-
-        initial_header.opcode = Opcode::StatusRequest;
-        initial_header.rcode = ResponseCode::NoError;
+        let initial_header = Header {
+            id: rand::random::<u16>(),
+            qr: true,
+            tc: true,
+            ra: true,
+            z: true,
+            ad: true,
+            cd: true,
+
+            qdcount: 5,
+            ancount: 3,
+            arcount: 2,
+            nscount: 7,
+
+            opcode: Opcode::Update,
+            rcode: ResponseCode::NotZone,
+            ..Default::default()
+        };
 
         let mut buf = [0u8; HEADER_LENGTH];
 
@@ -219,4 +380,21 @@ mod test {
 
         assert_eq!(initial_header, parsed_header);
     }
+
+    #[test]
+    fn test_codes() {
+        for v in 0..16u8 {
+            assert_eq!(u8::from(Opcode::from(v)), v);
+        }
+        assert_eq!(Opcode::from(4), Opcode::Notify);
+        assert_eq!(Opcode::Update.to_string(), "UPDATE");
+
+        for v in 0..4096u16 {
+            assert_eq!(u16::from(ResponseCode::from(v)), v);
+        }
+        assert_eq!(ResponseCode::from(9), ResponseCode::NotAuth);
+        assert_eq!(ResponseCode::from(16), ResponseCode::BadVers);
+        assert_eq!(u16::from(ResponseCode::BadSig), 16);
+        assert_eq!(ResponseCode::BadCookie.to_string(), "BADCOOKIE");
+    }
 }
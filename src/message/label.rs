@@ -1,182 +1,312 @@
+use std::collections::HashMap;
+
+use idna::uts46::AsciiDenyList;
+
 use crate::errors::DnsError;
+
+use super::{
+    header::HEADER_LENGTH,
+    name::{Name, MAX_LABEL_LENGTH, MAX_NAME_LENGTH},
+    text::{decode_escapes, split_name},
+};
 // parsing message labels with compression
 
 /// Label can be of the form:
 /// 1. [(length octet (max 63 octets)) (octets)] (0)
 /// 2. (Label 1)(offset pointer)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Label {
-    L(String),
+    L(Vec<u8>),
     P(usize),
 }
 
-pub fn parse_label_bytes(b: &[u8]) -> Result<(usize, Vec<Label>), DnsError> {
+/// Parses the labels of the name at `offset` in `msg` up to the terminating
+/// zero octet or pointer, returning the number of bytes read. Errors carry
+/// offsets into `msg`.
+pub fn parse_label_bytes(msg: &[u8], offset: usize) -> Result<(usize, Vec<Label>), DnsError> {
     let mut result = vec![];
-    let mut idx = 0usize;
-    let mut reached_end = false;
-    while idx < b.len() {
-        // check if pointer
-        if b[idx] & 0xC0 == 0xC0 {
-            let offset_b0 = b[idx] ^ 0xC0;
-            idx += 1;
+    let mut idx = offset;
+    loop {
+        let truncated = |needed| DnsError::Truncated {
+            needed,
+            available: msg.len().saturating_sub(idx),
+            offset: idx,
+        };
+        let count = *msg.get(idx).ok_or_else(|| truncated(1))? as usize;
 
-            if idx >= b.len() {
-                return Err(DnsError::ParseError(format!(
-                    "parse label: require {} bytes for parsing label offset, found {}",
-                    idx + 1,
-                    b.len()
-                )));
+        // check if pointer, which must point to an earlier name
+        if count & 0xC0 == 0xC0 {
+            let low = *msg.get(idx + 1).ok_or_else(|| truncated(2))? as usize;
+            let target = (count & 0x3F) << 8 | low;
+            if target < HEADER_LENGTH || target >= idx {
+                return Err(DnsError::InvalidPointer {
+                    target,
+                    offset: idx,
+                });
             }
-
-            let offset_b1 = b[idx];
-            idx += 1;
-            let offset = u16::from_be_bytes([offset_b0, offset_b1]);
-            result.push(Label::P(offset as usize));
-            reached_end = true;
+            result.push(Label::P(target));
+            idx += 2;
             break;
         }
 
         // check zero octet
-        if b[idx] == 0 {
+        if count == 0 {
             idx += 1;
-            reached_end = true;
             break;
         }
 
-        // read label
-        let count = u8::from_be(b[idx]) as usize;
-        // check length
-        if idx + count >= b.len() {
-            return Err(DnsError::ParseError(format!(
-                "parse: require {} bytes for parsing label, found {}, {:?}",
-                idx + count + 1,
-                b.len(),
-                result,
-            )));
+        // the 0x40 and 0x80 prefixes would make the length exceed 63 octets
+        if count > MAX_LABEL_LENGTH {
+            return Err(DnsError::LabelTooLong {
+                len: count,
+                offset: idx,
+            });
         }
-        result.push(Label::L(
-            String::from_utf8_lossy(&b[idx + 1..idx + count + 1]).into_owned(),
-        ));
+        let label = msg
+            .get(idx + 1..idx + 1 + count)
+            .ok_or_else(|| truncated(count + 1))?;
+        result.push(Label::L(label.to_vec()));
         idx += count + 1;
     }
-
-    if !reached_end {
-        return Err(DnsError::ParseError(format!(
-            "parse: zero-octet or pointer not found"
-        )));
-    }
-    Ok((idx, result))
+    Ok((idx - offset, result))
 }
 
-/// resolves pointers in a label.
-const MAX_LABEL_RESOLVE_DEPTH: usize = 10;
-pub fn resolve_labels(msg: &[u8], labels: &mut Vec<Label>) -> Result<String, DnsError> {
+/// Resolves pointers in the labels of the name at `offset` in `msg`. Each
+/// pointer must lead before the labels read so far, so resolution always
+/// moves towards the start of the message and terminates.
+pub const MAX_LABEL_RESOLVE_DEPTH: usize = 10;
+pub fn resolve_labels(msg: &[u8], offset: usize, labels: &mut Vec<Label>) -> Result<(), DnsError> {
+    let mut start = offset;
     let mut iter_count = 0;
-    while let Some(label) = labels.pop() {
-        if iter_count == MAX_LABEL_RESOLVE_DEPTH {
-            return Err(DnsError::ParseError(format!(
-                "parse: could not resolve labels after a depth of 10, possible cycle"
-            )));
+    loop {
+        // a pointer adds at least the root label
+        let len = labels
+            .iter()
+            .map(|l| match l {
+                Label::L(s) => s.len() + 1,
+                Label::P(_) => 0,
+            })
+            .sum::<usize>()
+            + 1;
+        if len > MAX_NAME_LENGTH {
+            return Err(DnsError::NameTooLong { len, offset });
         }
-        let offset = match label {
-            Label::L(_) => {
-                labels.push(label);
-                break;
-            }
-            Label::P(offset) => offset,
-        };
 
-        let (_, next) = parse_label_bytes(&msg[offset..])?;
-        for label in next {
-            labels.push(label)
+        let Some(&Label::P(target)) = labels.last() else {
+            return Ok(());
+        };
+        if iter_count == MAX_LABEL_RESOLVE_DEPTH || target >= start {
+            return Err(DnsError::PointerLoop { offset });
         }
+        labels.pop();
+        let (_, next) = parse_label_bytes(msg, target)?;
+        labels.extend(next);
+        start = target;
         iter_count += 1;
     }
-    let domain = labels
-        .clone()
-        .into_iter()
-        .filter_map(|l| match l {
-            Label::L(s) => Some(s),
-            _ => None,
-        })
-        .collect::<Vec<_>>()
-        .join(".");
-    Ok(domain)
 }
 
+/// Splits a dotted name as typed by a user into labels, decoding RFC 1035
+/// escapes such as `\.` for a dot inside a label. A name with non-ASCII
+/// characters or `xn--` labels goes through UTS 46 processing as a whole,
+/// which applies the IDNA 2008 mapping, validity and bidi rules and punycode
+/// encodes the non-ASCII labels, so `bücher.de` becomes `xn--bcher-kva.de`.
+/// ASCII labels are kept as written, preserving case and characters such as
+/// `_` that IDNA would reject. Escaped labels are taken as raw octets.
 pub fn domain_to_labels(domain: &str) -> Result<Vec<Label>, DnsError> {
+    let mapped;
+    let domain = if domain.is_ascii() {
+        domain
+    } else {
+        // UTS 46 treats the ideographic full stops as label separators
+        mapped = domain.replace(['\u{3002}', '\u{ff0e}', '\u{ff61}'], ".");
+        &mapped
+    };
+    let invalid = || DnsError::Generic(format!("invalid internationalized name: {}", domain));
+
+    let parts = split_name(domain)?.0;
+    let is_a_label = |part: &str| part.len() >= 4 && part[..4].eq_ignore_ascii_case("xn--");
+    let mut idna = None;
+    if parts
+        .iter()
+        .any(|part| !part.is_ascii() || is_a_label(part))
+    {
+        let plain = parts
+            .iter()
+            .filter(|part| !part.contains('\\'))
+            .copied()
+            .collect::<Vec<_>>();
+        let joined = plain.join(".");
+        let ascii = idna::domain_to_ascii_cow(joined.as_bytes(), AsciiDenyList::EMPTY)
+            .map_err(|_| invalid())?;
+        let ascii = ascii.split('.').map(str::to_string).collect::<Vec<_>>();
+        if ascii.len() != plain.len() || ascii.iter().any(|label| label.is_empty()) {
+            return Err(invalid());
+        }
+        idna = Some(ascii.into_iter());
+    }
+
     let mut result = vec![];
-    for part in domain.split('.') {
-        if part.len() > 63 {
-            return Err(DnsError::Generic(format!(
-                "label cannot have more than 63 octets"
-            )));
+    for part in parts {
+        // the unescaped labels went through UTS 46 in order
+        let ascii = match part.contains('\\') {
+            true => None,
+            false => idna.as_mut().and_then(Iterator::next),
+        };
+        match ascii {
+            Some(ascii) if !part.is_ascii() => result.push(Label::L(ascii.into_bytes())),
+            _ if part.is_ascii() => result.push(Label::L(decode_escapes(part)?)),
+            _ => return Err(invalid()),
         }
-        result.push(Label::L(part.to_string()))
     }
     Ok(result)
 }
 
-pub fn write_labels(labels: &Vec<Label>, dest: &mut [u8]) -> Result<usize, DnsError> {
-    if labels.len() == 0 {
-        return Ok(0);
+/// writes `labels` at `offset` in `dest`, returning the number of bytes
+/// written.
+pub fn write_labels(labels: &[Label], dest: &mut [u8], offset: usize) -> Result<usize, DnsError> {
+    // a pointer ends the name, anything after it is not written
+    let end = labels
+        .iter()
+        .position(|l| matches!(l, Label::P(_)))
+        .map_or(labels.len(), |i| i + 1);
+    let labels = &labels[..end];
+    let needed = labels
+        .iter()
+        .map(|l| match l {
+            Label::L(s) => s.len() + 1,
+            Label::P(_) => 2,
+        })
+        .sum::<usize>()
+        + !matches!(labels.last(), Some(Label::P(_))) as usize;
+    let available = dest.len().saturating_sub(offset);
+    if needed > available {
+        return Err(DnsError::BufferTooSmall {
+            needed,
+            available,
+            offset,
+        });
     }
 
-    let mut idx = 0;
-    let mut wrote_offset = false;
+    let mut idx = offset;
     for label in labels {
-        if dest.len() <= idx {
-            return Err(DnsError::MarshalError(format!(
-                "write: not enough space in destination to write labels"
-            )));
-        }
         match label {
             Label::L(s) => {
-                dest[idx] = s.len() as u8;
-                idx += 1;
-                for b in s.as_bytes() {
-                    if dest.len() <= idx {
-                        return Err(DnsError::MarshalError(format!(
-                            "write: not enough space in destination to write labels"
-                        )));
-                    }
-                    dest[idx] = *b;
-                    idx += 1;
+                if s.len() > MAX_LABEL_LENGTH {
+                    return Err(DnsError::LabelTooLong {
+                        len: s.len(),
+                        offset: idx,
+                    });
                 }
+                dest[idx] = s.len() as u8;
+                dest[idx + 1..idx + 1 + s.len()].copy_from_slice(s);
+                idx += s.len() + 1;
             }
-            Label::P(offset) => {
-                let offset_b = (*offset as u16).to_be_bytes();
-                dest[idx] = 0xC0 | (offset_b[0] as u8);
-                idx += 1;
-                if dest.len() <= idx {
+            Label::P(pointer) => {
+                if *pointer > MAX_POINTER_OFFSET {
                     return Err(DnsError::MarshalError(format!(
-                        "write: not enough space in destination to write labels"
+                        "write: pointer {} at offset {} is out of range",
+                        pointer, idx
                     )));
                 }
-                dest[idx] = offset_b[1];
-                idx += 1;
-                wrote_offset = true;
-                break;
+                dest[idx..idx + 2].copy_from_slice(&(0xC000 | *pointer as u16).to_be_bytes());
+                idx += 2;
             }
         }
     }
-
-    if !wrote_offset {
+    if idx - offset < needed {
         dest[idx] = 0;
         idx += 1;
     }
+    Ok(idx - offset)
+}
+
+/// pointers can only address the first 16K of a message.
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+/// Remembers where name suffixes were written in a message so that later
+/// names can end in a pointer to them instead, as per RFC 1035 4.1.4.
+#[derive(Debug, Default)]
+pub struct CompressionTable {
+    enabled: bool,
+    suffixes: HashMap<Vec<Label>, usize>,
+}
+
+impl CompressionTable {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            suffixes: HashMap::new(),
+        }
+    }
+
+    /// a table that never emits pointers, for writing records outside of a
+    /// message.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// writes `labels` at `offset` in `msg`, replacing the longest suffix
+    /// already present in the message with a pointer. `msg` must start at the
+    /// beginning of the message.
+    pub fn write_labels(
+        &mut self,
+        labels: &[Label],
+        msg: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, DnsError> {
+        let resolved = labels.iter().all(|l| matches!(l, Label::L(_)));
+        if !self.enabled || !resolved {
+            return write_labels(labels, msg, offset);
+        }
+
+        let matched =
+            (0..labels.len()).find_map(|i| self.suffixes.get(&labels[i..]).map(|ptr| (i, *ptr)));
+        let written = match matched {
+            Some((i, ptr)) => {
+                let mut compressed = labels[..i].to_vec();
+                compressed.push(Label::P(ptr));
+                write_labels(&compressed, msg, offset)?
+            }
+            None => write_labels(labels, msg, offset)?,
+        };
+
+        // remember the suffixes that were written out in full
+        let end = matched.map(|(i, _)| i).unwrap_or(labels.len());
+        let mut pos = offset;
+        for i in 0..end {
+            if pos > MAX_POINTER_OFFSET {
+                break;
+            }
+            self.suffixes.entry(labels[i..].to_vec()).or_insert(pos);
+            if let Label::L(s) = &labels[i] {
+                pos += s.len() + 1;
+            }
+        }
+        Ok(written)
+    }
 
-    Ok(idx)
+    /// writes `name` at `offset` in `msg`, see `write_labels`.
+    pub fn write_name(
+        &mut self,
+        name: &Name,
+        msg: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, DnsError> {
+        self.write_labels(&name.to_labels(), msg, offset)
+    }
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::{parse_label_bytes, resolve_labels, write_labels, Label};
+    use crate::errors::DnsError;
+
+    use super::{parse_label_bytes, resolve_labels, write_labels, CompressionTable, Label};
 
     #[test]
     fn test_simple_parse_label() {
-        let b = vec![
+        let b = [
             vec![3u8],
             "dns".as_bytes().to_vec(),
             vec![6u8],
@@ -187,25 +317,28 @@ mod test {
         ]
         .concat();
 
-        let (_, labels) = parse_label_bytes(&b[..]).unwrap();
+        let (_, labels) = parse_label_bytes(&b, 0).unwrap();
         assert_eq!(labels.len(), 3);
-        assert_eq!(labels[0], Label::L(String::from("dns")));
-        assert_eq!(labels[1], Label::L(String::from("google")));
-        assert_eq!(labels[2], Label::L(String::from("com")));
+        assert_eq!(labels[0], Label::L(b"dns".to_vec()));
+        assert_eq!(labels[1], Label::L(b"google".to_vec()));
+        assert_eq!(labels[2], Label::L(b"com".to_vec()));
     }
 
     #[test]
     fn test_parse_with_pointer() {
-        let b = vec![vec![3u8], "dns".as_bytes().to_vec(), vec![0xC0, 0x0F]].concat();
-        let (_, labels) = parse_label_bytes(&b[..]).unwrap();
+        // pointers lead back to a name after the 12 byte header
+        let b = [vec![0u8; 16], b"\x03dns".to_vec(), vec![0xC0, 0x0F]].concat();
+        let (read, labels) = parse_label_bytes(&b, 16).unwrap();
+        assert_eq!(read, 6);
         assert_eq!(labels.len(), 2);
-        assert_eq!(labels[0], Label::L(String::from("dns")));
-        assert_eq!(labels[1], Label::P(0x0F as usize));
+        assert_eq!(labels[0], Label::L(b"dns".to_vec()));
+        assert_eq!(labels[1], Label::P(0x0F_usize));
     }
 
     #[test]
     fn test_resolve_labels() {
-        let b = vec![
+        let b = [
+            vec![0u8; 12],
             vec![3u8],
             "dns".as_bytes().to_vec(),
             vec![6u8],
@@ -213,40 +346,64 @@ mod test {
             vec![3u8],
             "com".as_bytes().to_vec(),
             vec![0u8],
+            vec![4u8],
+            "test".as_bytes().to_vec(),
+            vec![0xC0, 0x10],
         ]
         .concat();
-        let b2 = vec![vec![4u8], "test".as_bytes().to_vec(), vec![0xC0, 0x04]].concat();
 
-        let (_, mut labels) = parse_label_bytes(b2.as_slice()).unwrap();
+        let (_, mut labels) = parse_label_bytes(&b, 28).unwrap();
 
-        resolve_labels(b.as_slice(), &mut labels).unwrap();
+        resolve_labels(&b, 28, &mut labels).unwrap();
 
         assert_eq!(labels.len(), 3);
-        assert_eq!(labels[0], Label::L(String::from("test")));
-        assert_eq!(labels[1], Label::L(String::from("google")));
-        assert_eq!(labels[2], Label::L(String::from("com")));
+        assert_eq!(labels[0], Label::L(b"test".to_vec()));
+        assert_eq!(labels[1], Label::L(b"google".to_vec()));
+        assert_eq!(labels[2], Label::L(b"com".to_vec()));
     }
 
     #[test]
     fn test_resolve_cycle() {
-        let b = vec![
+        let b = [
+            vec![0u8; 12],
             vec![3u8],
             "dns".as_bytes().to_vec(),
             vec![6u8],
             "google".as_bytes().to_vec(),
-            vec![0xC0, 0x00],
+            vec![0xC0, 0x0C],
         ]
         .concat();
-        let (_, mut labels) = parse_label_bytes(b.as_slice()).unwrap();
+        let (_, mut labels) = parse_label_bytes(&b, 12).unwrap();
         assert_eq!(labels.len(), 3);
-        assert_eq!(labels[2], Label::P(0));
+        assert_eq!(labels[2], Label::P(12));
 
-        resolve_labels(b.as_slice(), &mut labels).unwrap_err();
+        assert_eq!(
+            resolve_labels(&b, 12, &mut labels),
+            Err(DnsError::PointerLoop { offset: 12 })
+        );
+
+        // forward pointers and pointers into the header are rejected outright
+        let b = [vec![0u8; 12], vec![0xC0, 0x0E, 0]].concat();
+        assert_eq!(
+            parse_label_bytes(&b, 12),
+            Err(DnsError::InvalidPointer {
+                target: 14,
+                offset: 12
+            })
+        );
+        let b = [vec![0u8; 12], vec![0xC0, 0x02]].concat();
+        assert_eq!(
+            parse_label_bytes(&b, 12),
+            Err(DnsError::InvalidPointer {
+                target: 2,
+                offset: 12
+            })
+        );
     }
 
     #[test]
     fn test_write_and_parse_labels() {
-        let b = vec![
+        let b = [
             vec![3u8],
             "dns".as_bytes().to_vec(),
             vec![6u8],
@@ -257,29 +414,116 @@ mod test {
         ]
         .concat();
 
-        let (_, labels) = parse_label_bytes(&b[..]).unwrap();
+        let (_, labels) = parse_label_bytes(&b, 0).unwrap();
         assert_eq!(labels.len(), 3);
-        assert_eq!(labels[0], Label::L(String::from("dns")));
-        assert_eq!(labels[1], Label::L(String::from("google")));
-        assert_eq!(labels[2], Label::L(String::from("com")));
+        assert_eq!(labels[0], Label::L(b"dns".to_vec()));
+        assert_eq!(labels[1], Label::L(b"google".to_vec()));
+        assert_eq!(labels[2], Label::L(b"com".to_vec()));
 
         let mut d = vec![0u8; 100];
-        let n = write_labels(&labels, d.as_mut_slice()).unwrap();
+        let n = write_labels(&labels, &mut d, 0).unwrap();
         assert_eq!(b.len(), n);
         assert_eq!(b.as_slice(), &d[..n]);
     }
 
     #[test]
     fn test_write_and_parse_labels_with_offset() {
-        let b = vec![vec![3u8], "dns".as_bytes().to_vec(), vec![0xC0, 0x0F]].concat();
-        let (_, labels) = parse_label_bytes(&b[..]).unwrap();
+        let b = [vec![0u8; 16], b"\x03dns".to_vec(), vec![0xC0, 0x0F]].concat();
+        let (_, labels) = parse_label_bytes(&b, 16).unwrap();
         assert_eq!(labels.len(), 2);
-        assert_eq!(labels[0], Label::L(String::from("dns")));
-        assert_eq!(labels[1], Label::P(0x0F as usize));
+        assert_eq!(labels[0], Label::L(b"dns".to_vec()));
+        assert_eq!(labels[1], Label::P(0x0F_usize));
 
         let mut dest = vec![0u8; 100];
-        let n = write_labels(&labels, dest.as_mut_slice()).unwrap();
-        assert_eq!(b.len(), n);
-        assert_eq!(b.as_slice(), &dest[..n]);
+        let n = write_labels(&labels, &mut dest, 16).unwrap();
+        assert_eq!(b.len() - 16, n);
+        assert_eq!(&b[16..], &dest[16..16 + n]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // the second label claims 6 octets but only 3 follow
+        let b = [vec![3u8], b"dns".to_vec(), vec![6u8], b"goo".to_vec()].concat();
+        assert_eq!(
+            parse_label_bytes(&b, 0),
+            Err(DnsError::Truncated {
+                needed: 7,
+                available: 4,
+                offset: 4
+            })
+        );
+        assert_eq!(
+            parse_label_bytes(&[0xC0], 0),
+            Err(DnsError::Truncated {
+                needed: 2,
+                available: 1,
+                offset: 0
+            })
+        );
+        // read as a length, the reserved 0x40 label type exceeds 63 octets
+        assert_eq!(
+            parse_label_bytes(&[1, b'a', 0x41], 0),
+            Err(DnsError::LabelTooLong {
+                len: 0x41,
+                offset: 2
+            })
+        );
+
+        let labels = [b"dns".to_vec(), b"google".to_vec(), b"com".to_vec()].map(Label::L);
+        assert_eq!(
+            write_labels(&labels, &mut [0u8; 10], 2),
+            Err(DnsError::BufferTooSmall {
+                needed: 16,
+                available: 8,
+                offset: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_compression_table() {
+        let labels = |s: &str| {
+            s.split('.')
+                .map(|l| Label::L(l.as_bytes().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        let mut table = CompressionTable::new();
+        let mut msg = vec![0u8; 100];
+
+        // the first name is written in full after a 12 byte header
+        let mut offset = 12;
+        offset += table
+            .write_labels(&labels("dns.google.com"), &mut msg, offset)
+            .unwrap();
+        assert_eq!(offset, 12 + 16);
+
+        // a sibling only writes its first label and a pointer to "google.com"
+        let n = table
+            .write_labels(&labels("mail.google.com"), &mut msg, offset)
+            .unwrap();
+        assert_eq!(n, 5 + 2);
+        assert_eq!(&msg[offset + 5..offset + 7], &[0xC0, 16]);
+        offset += n;
+
+        // an exact repeat is a single pointer
+        let n = table
+            .write_labels(&labels("dns.google.com"), &mut msg, offset)
+            .unwrap();
+        assert_eq!(&msg[offset..offset + n], &[0xC0, 12]);
+        offset += n;
+
+        let (_, mut parsed) = parse_label_bytes(&msg[..offset], 28).unwrap();
+        resolve_labels(&msg, 28, &mut parsed).unwrap();
+        assert_eq!(parsed, labels("mail.google.com"));
+
+        // a disabled table never emits pointers
+        let mut table = CompressionTable::disabled();
+        table
+            .write_labels(&labels("dns.google.com"), &mut msg, 0)
+            .unwrap();
+        let n = table
+            .write_labels(&labels("dns.google.com"), &mut msg, 16)
+            .unwrap();
+        assert_eq!(n, 16);
     }
 }
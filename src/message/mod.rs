@@ -1,117 +1,432 @@
 // message definitions
 
+use std::fmt;
+
 use self::{
-    header::{DnsError, HEADER_LENGTH},
-    label::{domain_to_labels, resolve_labels},
+    builder::MessageBuilder,
+    edns::Edns,
+    header::{DnsError, ResponseCode, HEADER_LENGTH},
+    label::CompressionTable,
+    name::Name,
+    types::{DnsClass, RecordType},
 };
 
+pub mod builder;
+pub mod dnssec;
+pub mod edns;
 pub mod header;
 pub mod label;
+pub mod name;
 pub mod question;
+pub mod rdata;
 pub mod rr;
+pub mod text;
+pub mod tsig;
+pub mod types;
+pub mod update;
+pub mod view;
+pub mod zone;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Message {
     pub hdr: header::Header,
     pub qd: Vec<question::Question>,
     pub an: Vec<rr::ResourceRecord>,
-    pub ar: Vec<rr::ResourceRecord>,
     pub ns: Vec<rr::ResourceRecord>,
+    pub ar: Vec<rr::ResourceRecord>,
+    /// EDNS information from the OPT record, which is kept out of `ar`. The
+    /// header counts still include the OPT record.
+    pub edns: Option<Edns>,
 }
 
 impl Message {
+    /// Parses a whole message, returning the number of bytes read. The
+    /// sections must hold exactly the entries the header counts declare,
+    /// with nothing after them.
     pub fn parse(b: &[u8]) -> Result<(usize, Self), DnsError> {
-        let mut offset = 0;
-        let mut qd = vec![];
-        let mut an = vec![];
-        let mut ar = vec![];
-        let mut ns = vec![];
         let hdr = header::Header::parse(b)?;
-        offset += header::HEADER_LENGTH;
-
-        for _ in 0..hdr.qdcount {
-            let (read, q) = question::Question::parse(&b[offset..])?;
-            offset += read;
-            qd.push(q);
+        let mut offset = HEADER_LENGTH;
+        let qd = parse_section(
+            b,
+            &mut offset,
+            hdr.qdcount,
+            "question",
+            question::Question::parse,
+        )?;
+        let an = parse_section(
+            b,
+            &mut offset,
+            hdr.ancount,
+            "answer",
+            rr::ResourceRecord::parse,
+        )?;
+        let ns = parse_section(
+            b,
+            &mut offset,
+            hdr.nscount,
+            "authority",
+            rr::ResourceRecord::parse,
+        )?;
+        let ar = parse_section(
+            b,
+            &mut offset,
+            hdr.arcount,
+            "additional",
+            rr::ResourceRecord::parse,
+        )?;
+        if offset < b.len() {
+            return Err(DnsError::TrailingBytes {
+                len: b.len() - offset,
+                offset,
+            });
         }
 
-        for _ in 0..hdr.ancount {
-            let (read, r) = rr::ResourceRecord::parse(&b[offset..])?;
-            offset += read;
-            an.push(r);
+        let message = Message::from_sections(hdr, qd, an, ns, ar)?;
+        Ok((offset, message))
+    }
+
+    /// Assembles a message from parsed sections, moving the OPT record out of
+    /// the additional section into `edns`.
+    pub(crate) fn from_sections(
+        hdr: header::Header,
+        qd: Vec<question::Question>,
+        an: Vec<rr::ResourceRecord>,
+        ns: Vec<rr::ResourceRecord>,
+        mut ar: Vec<rr::ResourceRecord>,
+    ) -> Result<Self, DnsError> {
+        // a TSIG must be the last record, RFC 8945 5.1
+        if let Some(idx) = ar.iter().position(|r| r.t == RecordType::TSIG) {
+            if idx != ar.len() - 1 {
+                return Err(DnsError::ParseError(
+                    "parse: TSIG record must be the last additional record".to_string(),
+                ));
+            }
         }
+        let mut edns = None;
+        if let Some(idx) = ar.iter().position(|r| r.t == RecordType::OPT) {
+            let opt = ar.remove(idx);
+            if ar.iter().any(|r| r.t == RecordType::OPT) {
+                return Err(DnsError::ParseError(
+                    "parse: message contains more than one OPT record".to_string(),
+                ));
+            }
+            edns = Some(Edns::from_record(&opt)?);
+        }
+        Ok(Message {
+            hdr,
+            qd,
+            an,
+            ns,
+            ar,
+            edns,
+        })
+    }
+
+    /// The full response code, combining the header RCODE with the upper
+    /// bits carried in EDNS.
+    pub fn rcode(&self) -> ResponseCode {
+        let upper = self.edns.as_ref().map(|e| e.extended_rcode).unwrap_or(0);
+        ((upper as u16) << 4 | u16::from(self.hdr.rcode) & 0x0f).into()
+    }
 
-        for _ in 0..hdr.arcount {
-            let (read, r) = rr::ResourceRecord::parse(&b[offset..])?;
-            offset += read;
-            ar.push(r);
+    /// Sets the response code, putting the bits above the 4 that fit the
+    /// header in EDNS. Fails if those bits are set and the message has no
+    /// EDNS.
+    pub fn set_rcode(&mut self, rcode: ResponseCode) -> Result<(), DnsError> {
+        let value = u16::from(rcode);
+        if value > 0x0fff {
+            return Err(DnsError::MarshalError(format!(
+                "set rcode: {} does not fit in 12 bits",
+                rcode
+            )));
         }
+        match &mut self.edns {
+            Some(edns) => edns.extended_rcode = (value >> 4) as u8,
+            None if value > 0x0f => {
+                return Err(DnsError::MarshalError(format!(
+                    "set rcode: {} requires EDNS",
+                    rcode
+                )))
+            }
+            None => {}
+        }
+        self.hdr.rcode = (value & 0x0f).into();
+        Ok(())
+    }
 
-        for _ in 0..hdr.nscount {
-            let (read, r) = rr::ResourceRecord::parse(&b[offset..])?;
-            offset += read;
-            ns.push(r);
+    /// Writes the message into `dest`, compressing repeated names. Returns
+    /// the number of bytes written. A TSIG record is written last, after the
+    /// OPT record.
+    pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
+        let mut table = CompressionTable::new();
+        self.hdr.write(dest)?;
+        let mut offset = HEADER_LENGTH;
+
+        for q in &self.qd {
+            let w = q.write_compressed(dest, offset, &mut table)?;
+            offset += w;
         }
 
-        for q in &mut qd {
-            resolve_labels(b, &mut q.qname)?;
+        let (tsig, ar): (Vec<_>, Vec<_>) = self.ar.iter().partition(|r| r.t == RecordType::TSIG);
+        for r in self.an.iter().chain(&self.ns).chain(ar) {
+            let w = r.write_compressed(dest, offset, &mut table)?;
+            offset += w;
         }
 
-        for r in &mut an {
-            resolve_labels(b, &mut r.name)?;
+        if let Some(edns) = &self.edns {
+            let w = edns
+                .to_record()
+                .write_compressed(dest, offset, &mut table)?;
+            offset += w;
         }
 
-        for r in &mut ar {
-            resolve_labels(b, &mut r.name)?;
+        for r in tsig {
+            let w = r.write_compressed(dest, offset, &mut table)?;
+            offset += w;
         }
 
-        for r in &mut ns {
-            resolve_labels(b, &mut r.name)?;
+        Ok(offset)
+    }
+
+    /// A query with a single question. Use `MessageBuilder` for anything
+    /// else.
+    pub fn new_query(
+        domain: &Name,
+        t: RecordType,
+        class: DnsClass,
+        recursion: bool,
+        edns: Option<Edns>,
+    ) -> Result<Self, DnsError> {
+        let mut builder =
+            MessageBuilder::new()
+                .recursion_desired(recursion)
+                .question(domain.clone(), t, class);
+        if let Some(edns) = edns {
+            builder = builder.edns(edns);
         }
+        builder.build()
+    }
 
-        let message = Message {
+    /// An empty response to `query` with its ID, opcode, questions and RD
+    /// flag, RFC 1035 4.1.1.
+    pub fn response_to(query: &Message) -> Self {
+        let hdr = header::Header {
+            id: query.hdr.id,
+            qr: true,
+            opcode: query.hdr.opcode,
+            rd: query.hdr.rd,
+            qdcount: query.qd.len() as u16,
+            ..Default::default()
+        };
+        Message {
             hdr,
-            qd,
-            an,
-            ar,
-            ns,
+            qd: query.qd.clone(),
+            an: vec![],
+            ns: vec![],
+            ar: vec![],
+            edns: None,
+        }
+    }
+}
+
+/// Parses `count` entries of a section starting at `offset`. Running out of
+/// data between entries means the header count is wrong, rather than an
+/// entry being truncated.
+fn parse_section<T, F>(
+    b: &[u8],
+    offset: &mut usize,
+    count: u16,
+    section: &'static str,
+    parse: F,
+) -> Result<Vec<T>, DnsError>
+where
+    F: Fn(&[u8], usize) -> Result<(usize, T), DnsError>,
+{
+    let mut entries = vec![];
+    for found in 0..count {
+        if *offset == b.len() {
+            return Err(DnsError::CountMismatch {
+                section,
+                count,
+                found,
+            });
+        }
+        let (read, entry) = parse(b, *offset)?;
+        *offset += read;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Renders the message the way dig prints a response.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hdr = header::Header {
+            rcode: self.rcode(),
+            ..self.hdr
         };
-        Ok((offset, message))
+        writeln!(f, "{}", hdr)?;
+        if let Some(edns) = &self.edns {
+            writeln!(f, "\n;; OPT PSEUDOSECTION:\n{}", edns)?;
+        }
+        if !self.qd.is_empty() {
+            writeln!(f, "\n;; QUESTION SECTION:")?;
+            for q in &self.qd {
+                writeln!(f, ";{}", q)?;
+            }
+        }
+        for (title, records) in [
+            ("ANSWER", &self.an),
+            ("AUTHORITY", &self.ns),
+            ("ADDITIONAL", &self.ar),
+        ] {
+            if records.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n;; {} SECTION:", title)?;
+            for r in records {
+                writeln!(f, "{}", r)?;
+            }
+        }
+        Ok(())
     }
+}
 
-    pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
-        let mut offset = 0;
-        self.hdr.write(&mut dest[offset..])?;
-        offset += HEADER_LENGTH;
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
 
-        for q in &self.qd {
-            let w = q.write(&mut dest[offset..])?;
-            offset += w;
+    use super::{
+        builder::MessageBuilder,
+        edns::{Edns, FLAG_DNSSEC_OK},
+        header::{DnsError, ResponseCode},
+        rdata::{Mx, RData},
+        rr::ResourceRecord,
+        types::{DnsClass, RecordType},
+        Message,
+    };
+
+    fn record(name: &str, t: RecordType, rdata: RData) -> ResourceRecord {
+        ResourceRecord {
+            name: name.parse().unwrap(),
+            t,
+            class: DnsClass::IN,
+            ttl: 3600,
+            rdata,
         }
+    }
 
-        Ok(offset)
+    #[test]
+    fn test_write_and_parse_message() {
+        let msg = MessageBuilder::new()
+            .response(true)
+            .recursion_desired(true)
+            .rcode(ResponseCode::NoError)
+            .question(
+                "www.example.com".parse().unwrap(),
+                RecordType::A,
+                DnsClass::IN,
+            )
+            .answer(record(
+                "www.example.com",
+                RecordType::A,
+                RData::A(Ipv4Addr::new(93, 184, 216, 34)),
+            ))
+            .authority(record(
+                "example.com",
+                RecordType::NS,
+                RData::NS("a.iana-servers.net".parse().unwrap()),
+            ))
+            .additional(record(
+                "a.iana-servers.net",
+                RecordType::A,
+                RData::A(Ipv4Addr::new(199, 43, 135, 53)),
+            ))
+            .edns(Edns {
+                udp_payload_size: 4096,
+                flags: FLAG_DNSSEC_OK,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(msg.hdr.arcount, 2);
+
+        let mut buf = [0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        let (read, parsed) = Message::parse(&buf[..n]).unwrap();
+        assert_eq!(read, n);
+        assert_eq!(msg, parsed);
+
+        assert!(matches!(
+            msg.write(&mut buf[..n - 1]),
+            Err(DnsError::BufferTooSmall { .. })
+        ));
+        assert!(matches!(
+            Message::parse(&buf[..n - 1]),
+            Err(DnsError::Truncated { .. })
+        ));
     }
 
-    pub fn new_query(domain: &str, t: u16, class: u16, recursion: bool) -> Result<Self, DnsError> {
-        let mut hdr = header::Header::default();
-        hdr.id = rand::random();
-        hdr.qr = false;
-        hdr.qdcount = 1;
-        hdr.rd = recursion;
-        hdr.opcode = header::Opcode::StandardQuery;
+    #[test]
+    fn test_parse_section_counts() {
+        let query = Message::new_query(
+            &"example.com".parse().unwrap(),
+            RecordType::A,
+            DnsClass::IN,
+            true,
+            None,
+        )
+        .unwrap();
+        let mut buf = [0u8; 64];
+        let n = query.write(&mut buf).unwrap();
+        assert_eq!(Message::parse(&buf[..n]).unwrap().0, n);
 
-        let qname = domain_to_labels(domain)?;
+        assert_eq!(
+            Message::parse(&buf[..n + 2]),
+            Err(DnsError::TrailingBytes { len: 2, offset: n })
+        );
 
-        Ok(Message {
-            hdr: hdr,
-            qd: vec![question::Question {
-                qname: qname,
-                qtype: t,
-                qclass: class,
-            }],
-            an: vec![],
-            ar: vec![],
-            ns: vec![],
-        })
+        // an answer count with no answers after the question
+        buf[7] = 1;
+        assert_eq!(
+            Message::parse(&buf[..n]),
+            Err(DnsError::CountMismatch {
+                section: "answer",
+                count: 1,
+                found: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_display_message() {
+        let msg = MessageBuilder::new()
+            .id(4660)
+            .response(true)
+            .recursion_desired(true)
+            .recursion_available(true)
+            .question("example.com".parse().unwrap(), RecordType::MX, DnsClass::IN)
+            .answer(record(
+                "example.com",
+                RecordType::MX,
+                RData::MX(Mx {
+                    preference: 0,
+                    exchange: ".".parse().unwrap(),
+                }),
+            ))
+            .build()
+            .unwrap();
+
+        let expected = "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;example.com.\t\tIN\tMX
+
+;; ANSWER SECTION:
+example.com.\t3600\tIN\tMX\t0 .
+";
+        assert_eq!(msg.to_string(), expected);
     }
 }
@@ -0,0 +1,376 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::errors::DnsError;
+
+use super::{
+    label::{domain_to_labels, parse_label_bytes, resolve_labels, write_labels, Label},
+    text::write_label,
+};
+
+pub const MAX_LABEL_LENGTH: usize = 63;
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// A fully qualified domain name held as its uncompressed labels, leftmost
+/// label first. The root name has no labels. Labels are arbitrary octets;
+/// in text they use the escapes of RFC 1035 5.1.
+///
+/// Names compare and hash case-insensitively, and are ordered in the DNSSEC
+/// canonical order of RFC 4034 6.1.
+#[derive(Debug, Clone, Default)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// builds a name from resolved labels, checking label and name lengths.
+    pub fn from_labels(labels: &[Label]) -> Result<Self, DnsError> {
+        let mut result = vec![];
+        for label in labels {
+            match label {
+                Label::L(s) => result.push(s.clone()),
+                Label::P(_) => {
+                    return Err(DnsError::Generic(
+                        "name: labels contain an unresolved pointer".to_string(),
+                    ))
+                }
+            }
+        }
+        Self::from_raw(result)
+    }
+
+    fn from_raw(labels: Vec<Vec<u8>>) -> Result<Self, DnsError> {
+        for label in &labels {
+            if label.is_empty() {
+                return Err(DnsError::Generic(
+                    "name: only the root label may be empty".to_string(),
+                ));
+            }
+            if label.len() > MAX_LABEL_LENGTH {
+                return Err(DnsError::Generic(format!(
+                    "name: label cannot have more than {} octets",
+                    MAX_LABEL_LENGTH
+                )));
+            }
+        }
+        let name = Self { labels };
+        if name.wire_len() > MAX_NAME_LENGTH {
+            return Err(DnsError::Generic(format!(
+                "name: cannot have more than {} octets",
+                MAX_NAME_LENGTH
+            )));
+        }
+        Ok(name)
+    }
+
+    /// the labels of the name, leftmost first.
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// length of the uncompressed wire form, including the root octet.
+    pub fn wire_len(&self) -> usize {
+        self.labels.iter().map(|l| l.len() + 1).sum::<usize>() + 1
+    }
+
+    /// the name with its leftmost label removed, or `None` for the root.
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        Some(Self {
+            labels: self.labels[1..].to_vec(),
+        })
+    }
+
+    /// the name with `label` prepended.
+    pub fn child(&self, label: impl AsRef<[u8]>) -> Result<Self, DnsError> {
+        let mut labels = Vec::with_capacity(self.labels.len() + 1);
+        labels.push(label.as_ref().to_vec());
+        labels.extend(self.labels.iter().cloned());
+        Self::from_raw(labels)
+    }
+
+    /// true if `self` is `other` or lies below it.
+    pub fn is_subdomain_of(&self, other: &Self) -> bool {
+        if other.labels.len() > self.labels.len() {
+            return false;
+        }
+        let skip = self.labels.len() - other.labels.len();
+        self.labels[skip..]
+            .iter()
+            .zip(&other.labels)
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// the name with ASCII letters lowercased, as used for DNSSEC canonical
+    /// form.
+    pub fn to_lowercase(&self) -> Self {
+        Self {
+            labels: self.labels.iter().map(|l| l.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    /// The name for display to users, with punycode A-labels converted back
+    /// to Unicode, RFC 5891 5.5. Other labels are escaped as in `Display`.
+    pub fn to_unicode(&self) -> String {
+        if self.is_root() {
+            return ".".to_string();
+        }
+        let mut result = String::new();
+        for label in &self.labels {
+            let a_label = std::str::from_utf8(label)
+                .ok()
+                .filter(|l| l.len() > 4 && l[..4].eq_ignore_ascii_case("xn--"));
+            match a_label.map(idna::domain_to_unicode) {
+                Some((unicode, Ok(()))) => result.push_str(&unicode),
+                _ => {
+                    let _ = write_label(&mut result, label);
+                }
+            }
+            result.push('.');
+        }
+        result
+    }
+
+    pub fn to_labels(&self) -> Vec<Label> {
+        self.labels.iter().cloned().map(Label::L).collect()
+    }
+
+    /// Parses the name at `offset` in `msg`, following compression pointers.
+    /// Returns the number of bytes the name occupies at `offset`.
+    pub fn parse(msg: &[u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let (read, mut labels) = parse_label_bytes(msg, offset)?;
+        resolve_labels(msg, offset, &mut labels)?;
+        let name = Self::from_labels(&labels)?;
+        Ok((read, name))
+    }
+
+    /// Writes the uncompressed wire form into `dest`.
+    pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
+        write_labels(&self.to_labels(), dest, 0)
+    }
+}
+
+impl FromStr for Name {
+    type Err = DnsError;
+
+    /// parses a dotted name, see `domain_to_labels`. A trailing dot is
+    /// optional, all names are treated as fully qualified.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_labels(&domain_to_labels(s)?)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for label in &self.labels {
+            write_label(f, label)?;
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(&other.labels)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in &self.labels {
+            state.write_usize(label.len());
+            for b in label {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
+impl Ord for Name {
+    /// RFC 4034 6.1: compare labels right to left as lowercased octet
+    /// strings, a name sorting before any of its subdomains.
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+            let a = a.iter().map(|c| c.to_ascii_lowercase());
+            let b = b.iter().map(|c| c.to_ascii_lowercase());
+            match a.cmp(b) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+        self.labels.len().cmp(&other.labels.len())
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{Label, Name};
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(name("www.Example.com").to_string(), "www.Example.com.");
+        assert_eq!(name("www.example.com."), name("WWW.EXAMPLE.COM"));
+        assert_eq!(name(".").to_string(), ".");
+        assert!(name("").is_root());
+
+        "a..b".parse::<Name>().unwrap_err();
+        format!("{}.com", "a".repeat(64))
+            .parse::<Name>()
+            .unwrap_err();
+        let long = vec!["a".repeat(63); 4].join(".");
+        long.parse::<Name>().unwrap_err();
+    }
+
+    #[test]
+    fn test_internationalized_names() {
+        assert_eq!(name("bücher.de").to_string(), "xn--bcher-kva.de.");
+        assert_eq!(name("BÜCHER.de"), name("xn--bcher-kva.de"));
+        assert_eq!(name("例え.テスト").to_string(), "xn--r8jz45g.xn--zckzah.");
+        // UTS 46 maps full width characters and ideographic full stops
+        assert_eq!(name("ｂüｃｈｅｒ。de。").to_string(), "xn--bcher-kva.de.");
+        assert_eq!(
+            name("_sip._tcp.Example.com").to_string(),
+            "_sip._tcp.Example.com."
+        );
+        // a label cannot start with a combining mark
+        "\u{0301}a.com".parse::<Name>().unwrap_err();
+        format!("{}ü.com", "a".repeat(60))
+            .parse::<Name>()
+            .unwrap_err();
+        // the bidi rule covers every label of a name with a right to left
+        // label, and A-labels must be valid punycode
+        "א.1com".parse::<Name>().unwrap_err();
+        "xn--zz.example".parse::<Name>().unwrap_err();
+        "xn--.de".parse::<Name>().unwrap_err();
+        assert_eq!(name("XN--BCHER-KVA.de").to_string(), "XN--BCHER-KVA.de.");
+        assert_eq!(name(r"bücher.a\.b").labels()[1], b"a.b");
+        assert_eq!(name("_sip.Bücher.de").to_string(), "_sip.xn--bcher-kva.de.");
+
+        assert_eq!(name("www.xn--bcher-kva.de").to_unicode(), "www.bücher.de.");
+        assert_eq!(name("xn--r8jz45g.xn--zckzah").to_unicode(), "例え.テスト.");
+        // invalid A-labels can still arrive off the wire
+        let invalid =
+            Name::from_labels(&[Label::L(b"xn--zz".to_vec()), Label::L(b"Example".to_vec())])
+                .unwrap();
+        assert_eq!(invalid.to_unicode(), "xn--zz.Example.");
+        assert_eq!(Name::root().to_unicode(), ".");
+    }
+
+    #[test]
+    fn test_escaped_labels() {
+        let dotted = name(r"a\.b.example");
+        assert_eq!(dotted.num_labels(), 2);
+        assert_eq!(dotted.labels()[0], b"a.b");
+        assert_eq!(dotted.to_string(), r"a\.b.example.");
+        assert_eq!(name(r"\065\\\ x.").labels()[0], b"A\\ x");
+        r"a\".parse::<Name>().unwrap_err();
+        r"\256.example".parse::<Name>().unwrap_err();
+
+        // arbitrary octets off the wire survive display and parsing
+        let wire = [4u8, 0x80, 0, b' ', b'@', 3, b'c', b'o', b'm', 0];
+        let (_, binary) = Name::parse(&wire, 0).unwrap();
+        assert_eq!(binary.labels()[0], [0x80, 0, b' ', b'@']);
+        let text = binary.to_string();
+        assert_eq!(text, r"\128\000\032\@.com.");
+        assert_eq!(name(&text).labels(), binary.labels());
+        assert_eq!(binary.to_unicode(), text);
+    }
+
+    #[test]
+    fn test_case_insensitive_hash() {
+        let mut set = HashSet::new();
+        set.insert(name("ns1.Google.com"));
+        assert!(set.contains(&name("NS1.google.COM.")));
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let www = name("www.example.com");
+        assert_eq!(www.parent(), Some(name("example.com")));
+        assert_eq!(name("example.com").child("www").unwrap(), www);
+        assert!(www.is_subdomain_of(&name("EXAMPLE.com")));
+        assert!(www.is_subdomain_of(&www));
+        assert!(www.is_subdomain_of(&Name::root()));
+        assert!(!name("example.com").is_subdomain_of(&www));
+        assert!(!name("wwwexample.com").is_subdomain_of(&name("example.com")));
+        assert_eq!(Name::root().parent(), None);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // example from RFC 4034 6.1
+        let expected = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\u{1}.z.example",
+            "*.z.example",
+        ]
+        .iter()
+        .map(|s| name(s))
+        // a raw label, which would be rejected as an internationalized name
+        .chain([name("z.example").child("\u{80}").unwrap()])
+        .collect::<Vec<_>>();
+        let mut names = expected.iter().rev().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_wire_round_trip() {
+        let mut buf = [0u8; 64];
+        let www = name("www.example.com");
+        let n = www.write(&mut buf).unwrap();
+        assert_eq!(n, www.wire_len());
+        let (read, parsed) = Name::parse(&buf[..n], 0).unwrap();
+        assert_eq!(read, n);
+        assert_eq!(parsed, www);
+
+        let n = Name::root().write(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &[0]);
+    }
+}
@@ -1,46 +1,67 @@
+use std::fmt;
+
 use crate::errors::DnsError;
 
-use super::label::{self, Label};
+use super::{
+    label::CompressionTable,
+    name::Name,
+    types::{DnsClass, RecordType},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Question {
-    pub qname: Vec<Label>,
-    pub qtype: u16,
-    pub qclass: u16,
+    pub qname: Name,
+    pub qtype: RecordType,
+    pub qclass: DnsClass,
 }
 
 impl Question {
-    pub fn parse(b: &[u8]) -> Result<(usize, Self), DnsError> {
-        let (read, labels) = label::parse_label_bytes(b)?;
-        if b.len() <= read + 3 {
-            return Err(DnsError::ParseError(format!(
-                "parse: require {} bytes for parsing question, found {}",
-                read + 3,
-                b.len(),
-            )));
+    /// Parses the question starting at `offset` in `msg`, returning the number
+    /// of bytes read.
+    pub fn parse(msg: &[u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let (read, qname) = Name::parse(msg, offset)?;
+        let b = &msg[offset..];
+        if b.len() < read + 4 {
+            return Err(DnsError::Truncated {
+                needed: 4,
+                available: b.len() - read,
+                offset: offset + read,
+            });
         }
 
         let qtype_b = [b[read], b[read + 1]];
         let qclass_b = [b[read + 2], b[read + 3]];
         let q = Question {
-            qname: labels,
-            qtype: u16::from_be_bytes(qtype_b),
-            qclass: u16::from_be_bytes(qclass_b),
+            qname,
+            qtype: u16::from_be_bytes(qtype_b).into(),
+            qclass: u16::from_be_bytes(qclass_b).into(),
         };
-        return Ok((read + 4, q));
+        Ok((read + 4, q))
     }
 
     pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
-        let written = label::write_labels(&self.qname, dest)?;
-        if dest.len() <= written + 3 {
-            return Err(DnsError::MarshalError(format!(
-                "write: require {} bytes for writing question, found {}",
-                written + 3,
-                dest.len(),
-            )));
+        self.write_compressed(dest, 0, &mut CompressionTable::disabled())
+    }
+
+    /// Writes the question at `offset` in `msg`, compressing the name against
+    /// `table`. Returns the number of bytes written.
+    pub fn write_compressed(
+        &self,
+        msg: &mut [u8],
+        offset: usize,
+        table: &mut CompressionTable,
+    ) -> Result<usize, DnsError> {
+        let written = table.write_name(&self.qname, msg, offset)?;
+        let dest = &mut msg[offset..];
+        if dest.len() < written + 4 {
+            return Err(DnsError::BufferTooSmall {
+                needed: 4,
+                available: dest.len() - written,
+                offset: offset + written,
+            });
         }
-        let qtype_b = self.qtype.to_be_bytes();
-        let qclass_b = self.qclass.to_be_bytes();
+        let qtype_b = u16::from(self.qtype).to_be_bytes();
+        let qclass_b = u16::from(self.qclass).to_be_bytes();
         dest[written] = qtype_b[0];
         dest[written + 1] = qtype_b[1];
         dest[written + 2] = qclass_b[0];
@@ -49,3 +70,9 @@ impl Question {
         Ok(written + 4)
     }
 }
+
+impl fmt::Display for Question {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t\t{}\t{}", self.qname, self.qclass, self.qtype)
+    }
+}
@@ -0,0 +1,1013 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use data_encoding::{BASE32HEX_NOPAD, BASE64, HEXUPPER, HEXUPPER_PERMISSIVE};
+
+use crate::errors::DnsError;
+
+use super::{
+    dnssec::{
+        decode_type_bitmap, encode_type_bitmap, parse_timestamp, Dnskey, Ds, Nsec, Nsec3,
+        Nsec3Param, Rrsig,
+    },
+    edns::parse_options,
+    label::{parse_label_bytes, resolve_labels, write_labels, CompressionTable},
+    name::Name,
+    text::{Fields, Lexer},
+    tsig::{tsig_error_from_code, Tsig},
+    types::{DnsClass, RecordType},
+};
+
+/// Start of authority, RFC 1035 3.3.13
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Soa {
+    pub mname: Name,
+    pub rname: Name,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+/// Mail exchange, RFC 1035 3.3.9
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Mx {
+    pub preference: u16,
+    pub exchange: Name,
+}
+
+/// Service location, RFC 2782
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: Name,
+}
+
+/// Decoded resource record data. Names are fully resolved against the
+/// message they were read from.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    NS(Name),
+    CNAME(Name),
+    PTR(Name),
+    SOA(Soa),
+    MX(Mx),
+    TXT(Vec<Vec<u8>>),
+    SRV(Srv),
+    DS(Ds),
+    RRSIG(Rrsig),
+    NSEC(Nsec),
+    DNSKEY(Dnskey),
+    NSEC3(Nsec3),
+    NSEC3PARAM(Nsec3Param),
+    TSIG(Tsig),
+    Unknown(Vec<u8>),
+}
+
+impl Default for RData {
+    fn default() -> Self {
+        Self::Unknown(vec![])
+    }
+}
+
+/// Cursor over the rdata of a single record. Reads are bounded by the end of
+/// the rdata, while names may follow pointers anywhere in the message.
+struct Reader<'a> {
+    msg: &'a [u8],
+    offset: usize,
+    end: usize,
+    t: RecordType,
+}
+
+impl<'a> Reader<'a> {
+    fn invalid(&self, offset: usize, reason: String) -> DnsError {
+        DnsError::InvalidRdata {
+            rtype: self.t,
+            offset,
+            reason,
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DnsError> {
+        if self.offset + n > self.end {
+            return Err(self.invalid(
+                self.offset,
+                format!("require {} bytes, found {}", n, self.end - self.offset),
+            ));
+        }
+        let b = &self.msg[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, DnsError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DnsError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DnsError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn name(&mut self) -> Result<Name, DnsError> {
+        let offset = self.offset;
+        let (read, mut labels) =
+            parse_label_bytes(&self.msg[..self.end], offset).map_err(|e| match e {
+                DnsError::Truncated { .. } => {
+                    self.invalid(offset, "name overruns the rdata".to_string())
+                }
+                e => e,
+            })?;
+        self.offset += read;
+        resolve_labels(self.msg, offset, &mut labels)?;
+        Name::from_labels(&labels).map_err(|e| self.invalid(offset, e.to_string()))
+    }
+
+    /// a byte string prefixed by its length octet.
+    fn sized(&mut self) -> Result<&'a [u8], DnsError> {
+        let n = self.u8()? as usize;
+        self.take(n)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let b = &self.msg[self.offset..self.end];
+        self.offset = self.end;
+        b
+    }
+
+    fn types(&mut self) -> Result<Vec<RecordType>, DnsError> {
+        let offset = self.offset;
+        decode_type_bitmap(self.rest(), self.t, offset)
+    }
+
+    fn finish(self) -> Result<(), DnsError> {
+        if self.offset != self.end {
+            return Err(self.invalid(
+                self.offset,
+                format!("{} trailing bytes", self.end - self.offset),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Bounded cursor for writing rdata into a message buffer.
+struct Writer<'a> {
+    dest: &'a mut [u8],
+    offset: usize,
+    table: &'a mut CompressionTable,
+}
+
+impl<'a> Writer<'a> {
+    fn put(&mut self, b: &[u8]) -> Result<(), DnsError> {
+        let available = self.dest.len().saturating_sub(self.offset);
+        if b.len() > available {
+            return Err(DnsError::BufferTooSmall {
+                needed: b.len(),
+                available,
+                offset: self.offset,
+            });
+        }
+        self.dest[self.offset..self.offset + b.len()].copy_from_slice(b);
+        self.offset += b.len();
+        Ok(())
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), DnsError> {
+        self.put(&v.to_be_bytes())
+    }
+
+    fn u32(&mut self, v: u32) -> Result<(), DnsError> {
+        self.put(&v.to_be_bytes())
+    }
+
+    fn sized(&mut self, b: &[u8]) -> Result<(), DnsError> {
+        if b.len() > 255 {
+            return Err(DnsError::MarshalError(
+                "write rdata: character string cannot exceed 255 octets".to_string(),
+            ));
+        }
+        self.put(&[b.len() as u8])?;
+        self.put(b)
+    }
+
+    /// `b` preceded by its 16 bit length.
+    fn sized16(&mut self, b: &[u8], field: &str) -> Result<(), DnsError> {
+        let len = u16::try_from(b.len()).map_err(|_| {
+            DnsError::MarshalError(format!("write rdata: {} cannot exceed 65535 octets", field))
+        })?;
+        self.u16(len)?;
+        self.put(b)
+    }
+
+    /// names in the rdata of the RFC 1035 types may be compressed.
+    fn name(&mut self, name: &Name) -> Result<(), DnsError> {
+        self.offset += self.table.write_name(name, self.dest, self.offset)?;
+        Ok(())
+    }
+
+    /// newer types must be written uncompressed, RFC 3597 4.
+    fn uncompressed_name(&mut self, name: &Name) -> Result<(), DnsError> {
+        self.offset += write_labels(&name.to_labels(), self.dest, self.offset)?;
+        Ok(())
+    }
+}
+
+impl RData {
+    /// Parses `rdlength` bytes of rdata for a record of type `t` starting at
+    /// `offset` in `msg`. `msg` must be the whole message so that compressed
+    /// names can be resolved.
+    pub fn parse(
+        msg: &[u8],
+        offset: usize,
+        rdlength: usize,
+        t: RecordType,
+    ) -> Result<Self, DnsError> {
+        let available = msg.len().saturating_sub(offset);
+        if rdlength > available {
+            return Err(DnsError::Truncated {
+                needed: rdlength,
+                available,
+                offset,
+            });
+        }
+        let mut r = Reader {
+            msg,
+            offset,
+            end: offset + rdlength,
+            t,
+        };
+        let rdata = match t {
+            RecordType::A => {
+                let b = r.take(4)?;
+                Self::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            RecordType::AAAA => {
+                let mut b = [0u8; 16];
+                b.copy_from_slice(r.take(16)?);
+                Self::AAAA(Ipv6Addr::from(b))
+            }
+            RecordType::NS => Self::NS(r.name()?),
+            RecordType::CNAME => Self::CNAME(r.name()?),
+            RecordType::PTR => Self::PTR(r.name()?),
+            RecordType::SOA => Self::SOA(Soa {
+                mname: r.name()?,
+                rname: r.name()?,
+                serial: r.u32()?,
+                refresh: r.u32()?,
+                retry: r.u32()?,
+                expire: r.u32()?,
+                minimum: r.u32()?,
+            }),
+            RecordType::MX => Self::MX(Mx {
+                preference: r.u16()?,
+                exchange: r.name()?,
+            }),
+            RecordType::TXT => {
+                let mut strings = vec![];
+                while r.offset < r.end {
+                    let n = r.u8()? as usize;
+                    strings.push(r.take(n)?.to_vec());
+                }
+                Self::TXT(strings)
+            }
+            RecordType::SRV => Self::SRV(Srv {
+                priority: r.u16()?,
+                weight: r.u16()?,
+                port: r.u16()?,
+                target: r.name()?,
+            }),
+            RecordType::DS => Self::DS(Ds {
+                key_tag: r.u16()?,
+                algorithm: r.u8()?,
+                digest_type: r.u8()?,
+                digest: r.rest().to_vec(),
+            }),
+            RecordType::RRSIG => Self::RRSIG(Rrsig {
+                type_covered: r.u16()?.into(),
+                algorithm: r.u8()?,
+                labels: r.u8()?,
+                original_ttl: r.u32()?,
+                expiration: r.u32()?,
+                inception: r.u32()?,
+                key_tag: r.u16()?,
+                signer_name: r.name()?,
+                signature: r.rest().to_vec(),
+            }),
+            RecordType::NSEC => Self::NSEC(Nsec {
+                next_domain: r.name()?,
+                types: r.types()?,
+            }),
+            RecordType::DNSKEY => Self::DNSKEY(Dnskey {
+                flags: r.u16()?,
+                protocol: r.u8()?,
+                algorithm: r.u8()?,
+                public_key: r.rest().to_vec(),
+            }),
+            RecordType::NSEC3 => Self::NSEC3(Nsec3 {
+                hash_algorithm: r.u8()?,
+                flags: r.u8()?,
+                iterations: r.u16()?,
+                salt: r.sized()?.to_vec(),
+                next_hashed_owner: r.sized()?.to_vec(),
+                types: r.types()?,
+            }),
+            RecordType::NSEC3PARAM => Self::NSEC3PARAM(Nsec3Param {
+                hash_algorithm: r.u8()?,
+                flags: r.u8()?,
+                iterations: r.u16()?,
+                salt: r.sized()?.to_vec(),
+            }),
+            RecordType::TSIG => Self::TSIG(Tsig {
+                algorithm: r.name()?,
+                time_signed: (r.u16()? as u64) << 32 | r.u32()? as u64,
+                fudge: r.u16()?,
+                mac: {
+                    let n = r.u16()? as usize;
+                    r.take(n)?.to_vec()
+                },
+                original_id: r.u16()?,
+                error: tsig_error_from_code(r.u16()?),
+                other: {
+                    let n = r.u16()? as usize;
+                    r.take(n)?.to_vec()
+                },
+            }),
+            RecordType::OPT => {
+                // checked here, where errors can point into the message
+                let b = r.rest();
+                parse_options(b, offset)?;
+                Self::Unknown(b.to_vec())
+            }
+            _ => Self::Unknown(r.rest().to_vec()),
+        };
+        r.finish()?;
+        Ok(rdata)
+    }
+
+    /// Parses rdata like `parse` for a record of class `class`. Records of the
+    /// ANY and NONE classes may have empty rdata whatever their type, as the
+    /// prerequisites and deletions of UPDATE messages do, RFC 2136 2.4 and
+    /// 2.5.
+    pub fn parse_for_class(
+        msg: &[u8],
+        offset: usize,
+        rdlength: usize,
+        t: RecordType,
+        class: DnsClass,
+    ) -> Result<Self, DnsError> {
+        if rdlength == 0 && matches!(class, DnsClass::ANY | DnsClass::NONE) {
+            return Ok(Self::Unknown(vec![]));
+        }
+        Self::parse(msg, offset, rdlength, t)
+    }
+
+    /// Writes the wire form of the rdata into `dest`, returning the number of
+    /// bytes written. Names are written uncompressed.
+    pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
+        self.write_compressed(dest, 0, &mut CompressionTable::disabled())
+    }
+
+    /// Writes the rdata at `offset` in `msg`, compressing names against
+    /// `table` where the record type allows it. Returns the number of bytes
+    /// written.
+    pub fn write_compressed(
+        &self,
+        msg: &mut [u8],
+        offset: usize,
+        table: &mut CompressionTable,
+    ) -> Result<usize, DnsError> {
+        let mut w = Writer {
+            dest: msg,
+            offset,
+            table,
+        };
+        match self {
+            Self::A(addr) => w.put(&addr.octets())?,
+            Self::AAAA(addr) => w.put(&addr.octets())?,
+            Self::NS(name) | Self::CNAME(name) | Self::PTR(name) => w.name(name)?,
+            Self::SOA(soa) => {
+                w.name(&soa.mname)?;
+                w.name(&soa.rname)?;
+                w.u32(soa.serial)?;
+                w.u32(soa.refresh)?;
+                w.u32(soa.retry)?;
+                w.u32(soa.expire)?;
+                w.u32(soa.minimum)?;
+            }
+            Self::MX(mx) => {
+                w.u16(mx.preference)?;
+                w.name(&mx.exchange)?;
+            }
+            Self::TXT(strings) => {
+                for s in strings {
+                    w.sized(s)?;
+                }
+            }
+            Self::SRV(srv) => {
+                w.u16(srv.priority)?;
+                w.u16(srv.weight)?;
+                w.u16(srv.port)?;
+                w.uncompressed_name(&srv.target)?;
+            }
+            Self::DS(ds) => {
+                w.u16(ds.key_tag)?;
+                w.put(&[ds.algorithm, ds.digest_type])?;
+                w.put(&ds.digest)?;
+            }
+            Self::RRSIG(sig) => {
+                w.u16(sig.type_covered.into())?;
+                w.put(&[sig.algorithm, sig.labels])?;
+                w.u32(sig.original_ttl)?;
+                w.u32(sig.expiration)?;
+                w.u32(sig.inception)?;
+                w.u16(sig.key_tag)?;
+                w.uncompressed_name(&sig.signer_name)?;
+                w.put(&sig.signature)?;
+            }
+            Self::NSEC(nsec) => {
+                w.uncompressed_name(&nsec.next_domain)?;
+                w.put(&encode_type_bitmap(&nsec.types))?;
+            }
+            Self::DNSKEY(key) => w.put(&key.to_bytes())?,
+            Self::NSEC3(nsec3) => {
+                w.put(&[nsec3.hash_algorithm, nsec3.flags])?;
+                w.u16(nsec3.iterations)?;
+                w.sized(&nsec3.salt)?;
+                w.sized(&nsec3.next_hashed_owner)?;
+                w.put(&encode_type_bitmap(&nsec3.types))?;
+            }
+            Self::NSEC3PARAM(param) => {
+                w.put(&[param.hash_algorithm, param.flags])?;
+                w.u16(param.iterations)?;
+                w.sized(&param.salt)?;
+            }
+            Self::TSIG(tsig) => {
+                if tsig.time_signed >> 48 != 0 {
+                    return Err(DnsError::MarshalError(
+                        "write rdata: TSIG time does not fit in 48 bits".to_string(),
+                    ));
+                }
+                w.uncompressed_name(&tsig.algorithm)?;
+                w.put(&tsig.time_signed.to_be_bytes()[2..])?;
+                w.u16(tsig.fudge)?;
+                w.sized16(&tsig.mac, "TSIG MAC")?;
+                w.u16(tsig.original_id)?;
+                w.u16(tsig.error.into())?;
+                w.sized16(&tsig.other, "TSIG other data")?;
+            }
+            Self::Unknown(b) => w.put(b)?,
+        }
+        Ok(w.offset - offset)
+    }
+
+    /// The rdata with embedded names lowercased, RFC 4034 6.2 as amended by
+    /// RFC 6840 5.1.
+    pub fn to_canonical(&self) -> Self {
+        match self {
+            Self::NS(name) => Self::NS(name.to_lowercase()),
+            Self::CNAME(name) => Self::CNAME(name.to_lowercase()),
+            Self::PTR(name) => Self::PTR(name.to_lowercase()),
+            Self::SOA(soa) => Self::SOA(Soa {
+                mname: soa.mname.to_lowercase(),
+                rname: soa.rname.to_lowercase(),
+                ..soa.clone()
+            }),
+            Self::MX(mx) => Self::MX(Mx {
+                preference: mx.preference,
+                exchange: mx.exchange.to_lowercase(),
+            }),
+            Self::SRV(srv) => Self::SRV(Srv {
+                target: srv.target.to_lowercase(),
+                ..srv.clone()
+            }),
+            Self::RRSIG(sig) => Self::RRSIG(Rrsig {
+                signer_name: sig.signer_name.to_lowercase(),
+                ..sig.clone()
+            }),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Writes a character string in quoted master file form, RFC 1035 5.1.
+fn write_character_string(f: &mut fmt::Formatter<'_>, b: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in b {
+        match c {
+            b'"' | b'\\' => write!(f, "\\{}", *c as char)?,
+            0x20..=0x7E => write!(f, "{}", *c as char)?,
+            _ => write!(f, "\\{:03}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Soa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.mname,
+            self.rname,
+            self.serial,
+            self.refresh,
+            self.retry,
+            self.expire,
+            self.minimum
+        )
+    }
+}
+
+impl fmt::Display for Mx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange)
+    }
+}
+
+impl fmt::Display for Srv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.priority, self.weight, self.port, self.target
+        )
+    }
+}
+
+/// Renders the rdata in master file syntax. Unknown rdata uses the generic
+/// form of RFC 3597 5.
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A(addr) => write!(f, "{}", addr),
+            Self::AAAA(addr) => write!(f, "{}", addr),
+            Self::NS(name) | Self::CNAME(name) | Self::PTR(name) => write!(f, "{}", name),
+            Self::SOA(soa) => write!(f, "{}", soa),
+            Self::MX(mx) => write!(f, "{}", mx),
+            Self::TXT(strings) => {
+                for (i, s) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_character_string(f, s)?;
+                }
+                Ok(())
+            }
+            Self::SRV(srv) => write!(f, "{}", srv),
+            Self::DS(ds) => write!(f, "{}", ds),
+            Self::RRSIG(sig) => write!(f, "{}", sig),
+            Self::NSEC(nsec) => write!(f, "{}", nsec),
+            Self::DNSKEY(key) => write!(f, "{}", key),
+            Self::NSEC3(nsec3) => write!(f, "{}", nsec3),
+            Self::NSEC3PARAM(param) => write!(f, "{}", param),
+            Self::TSIG(tsig) => write!(f, "{}", tsig),
+            Self::Unknown(b) if b.is_empty() => write!(f, "\\# 0"),
+            Self::Unknown(b) => write!(f, "\\# {} {}", b.len(), HEXUPPER.encode(b)),
+        }
+    }
+}
+
+fn parse_salt(fields: &mut Fields<'_>) -> Result<Vec<u8>, DnsError> {
+    let token = fields.next("salt")?;
+    if token.is("-") {
+        return Ok(vec![]);
+    }
+    HEXUPPER_PERMISSIVE
+        .decode(token.text.as_bytes())
+        .map_err(|_| token.error(format!("invalid salt: {}", token.text)))
+}
+
+fn parse_types(fields: &mut Fields<'_>) -> Result<Vec<RecordType>, DnsError> {
+    let mut types = vec![];
+    for token in fields.rest() {
+        types.push(
+            token
+                .text
+                .parse()
+                .map_err(|_| token.error(format!("invalid type: {}", token.text)))?,
+        );
+    }
+    types.sort();
+    types.dedup();
+    Ok(types)
+}
+
+/// decodes the remaining fields with `encoding`, for binary data that may be
+/// split by whitespace.
+fn parse_binary(
+    fields: &mut Fields<'_>,
+    what: &str,
+    encoding: &data_encoding::Encoding,
+) -> Result<Vec<u8>, DnsError> {
+    let err = fields.error(format!("invalid {}", what));
+    let text = fields.joined(what)?;
+    encoding.decode(text.as_bytes()).map_err(|_| err)
+}
+
+impl RData {
+    /// Parses rdata of type `t` from its presentation format, the inverse of
+    /// the `Display` implementation. Relative names are completed with
+    /// `origin`.
+    pub fn from_text(t: RecordType, s: &str, origin: &Name) -> Result<Self, DnsError> {
+        let mut lexer = Lexer::new(s);
+        let tokens = lexer
+            .next_entry()?
+            .map(|entry| entry.tokens)
+            .unwrap_or_default();
+        if let Some(entry) = lexer.next_entry()? {
+            return Err(entry.tokens[0].error("rdata must be a single entry".to_string()));
+        }
+        Self::from_fields(t, &mut Fields::new(&tokens, 1, 1), origin)
+    }
+
+    /// Parses rdata of type `t` from all remaining `fields`.
+    pub fn from_fields(
+        t: RecordType,
+        fields: &mut Fields<'_>,
+        origin: &Name,
+    ) -> Result<Self, DnsError> {
+        if fields.peek().is_some_and(|token| token.is("\\#")) {
+            return Self::from_generic(t, fields);
+        }
+        let rdata = match t {
+            RecordType::A => Self::A(fields.parse("IPv4 address")?),
+            RecordType::AAAA => Self::AAAA(fields.parse("IPv6 address")?),
+            RecordType::NS => Self::NS(fields.name("name server", origin)?),
+            RecordType::CNAME => Self::CNAME(fields.name("canonical name", origin)?),
+            RecordType::PTR => Self::PTR(fields.name("pointer", origin)?),
+            RecordType::SOA => Self::SOA(Soa {
+                mname: fields.name("primary name server", origin)?,
+                rname: fields.name("mailbox", origin)?,
+                serial: fields.parse("serial")?,
+                refresh: fields.ttl("refresh")?,
+                retry: fields.ttl("retry")?,
+                expire: fields.ttl("expire")?,
+                minimum: fields.ttl("minimum")?,
+            }),
+            RecordType::MX => Self::MX(Mx {
+                preference: fields.parse("preference")?,
+                exchange: fields.name("exchange", origin)?,
+            }),
+            RecordType::TXT => {
+                let mut strings = vec![];
+                while !fields.is_empty() {
+                    strings.push(fields.character_string("text")?);
+                }
+                Self::TXT(strings)
+            }
+            RecordType::SRV => Self::SRV(Srv {
+                priority: fields.parse("priority")?,
+                weight: fields.parse("weight")?,
+                port: fields.parse("port")?,
+                target: fields.name("target", origin)?,
+            }),
+            RecordType::DS => Self::DS(Ds {
+                key_tag: fields.parse("key tag")?,
+                algorithm: fields.parse("algorithm")?,
+                digest_type: fields.parse("digest type")?,
+                digest: parse_binary(fields, "digest", &HEXUPPER_PERMISSIVE)?,
+            }),
+            RecordType::RRSIG => Self::RRSIG(Rrsig {
+                type_covered: fields.parse("type covered")?,
+                algorithm: fields.parse("algorithm")?,
+                labels: fields.parse("labels")?,
+                original_ttl: fields.parse("original TTL")?,
+                expiration: {
+                    let token = fields.next("expiration")?;
+                    parse_timestamp(&token.text)
+                        .ok_or_else(|| token.error(format!("invalid expiration: {}", token.text)))?
+                },
+                inception: {
+                    let token = fields.next("inception")?;
+                    parse_timestamp(&token.text)
+                        .ok_or_else(|| token.error(format!("invalid inception: {}", token.text)))?
+                },
+                key_tag: fields.parse("key tag")?,
+                signer_name: fields.name("signer name", origin)?,
+                signature: parse_binary(fields, "signature", &BASE64)?,
+            }),
+            RecordType::NSEC => Self::NSEC(Nsec {
+                next_domain: fields.name("next domain", origin)?,
+                types: parse_types(fields)?,
+            }),
+            RecordType::DNSKEY => Self::DNSKEY(Dnskey {
+                flags: fields.parse("flags")?,
+                protocol: fields.parse("protocol")?,
+                algorithm: fields.parse("algorithm")?,
+                public_key: parse_binary(fields, "public key", &BASE64)?,
+            }),
+            RecordType::NSEC3 => Self::NSEC3(Nsec3 {
+                hash_algorithm: fields.parse("hash algorithm")?,
+                flags: fields.parse("flags")?,
+                iterations: fields.parse("iterations")?,
+                salt: parse_salt(fields)?,
+                next_hashed_owner: {
+                    let token = fields.next("next hashed owner")?;
+                    BASE32HEX_NOPAD
+                        .decode(token.text.to_ascii_uppercase().as_bytes())
+                        .map_err(|_| token.error(format!("invalid hash: {}", token.text)))?
+                },
+                types: parse_types(fields)?,
+            }),
+            RecordType::NSEC3PARAM => Self::NSEC3PARAM(Nsec3Param {
+                hash_algorithm: fields.parse("hash algorithm")?,
+                flags: fields.parse("flags")?,
+                iterations: fields.parse("iterations")?,
+                salt: parse_salt(fields)?,
+            }),
+            _ => return Err(fields.error(format!("{} rdata must use the generic \\# form", t))),
+        };
+        fields.finish()?;
+        Ok(rdata)
+    }
+
+    /// Parses the generic `\# length hex` form of RFC 3597 5, decoding the
+    /// data for the types we know.
+    fn from_generic(t: RecordType, fields: &mut Fields<'_>) -> Result<Self, DnsError> {
+        fields.next("\\#")?;
+        let len: usize = fields.parse::<u16>("rdata length")? as usize;
+        let b = if len == 0 {
+            vec![]
+        } else {
+            parse_binary(fields, "rdata", &HEXUPPER_PERMISSIVE)?
+        };
+        if b.len() != len {
+            return Err(fields.error(format!(
+                "rdata length {} does not match {} octets of data",
+                len,
+                b.len()
+            )));
+        }
+        fields.finish()?;
+        Self::parse(&b, 0, len, t).map_err(|e| fields.error(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::{Mx, RData, Soa, Srv};
+    use crate::errors::DnsError;
+    use crate::message::dnssec::{Nsec, Nsec3, Rrsig};
+    use crate::message::header::ResponseCode;
+    use crate::message::name::Name;
+    use crate::message::tsig::Tsig;
+    use crate::message::types::RecordType;
+
+    #[test]
+    fn test_parse_a() {
+        let b = [10u8, 0, 0, 1];
+        let rdata = RData::parse(&b, 0, 4, RecordType::A).unwrap();
+        assert_eq!(rdata, RData::A(Ipv4Addr::new(10, 0, 0, 1)));
+
+        assert_eq!(
+            RData::parse(&b, 0, 3, RecordType::A),
+            Err(DnsError::InvalidRdata {
+                rtype: RecordType::A,
+                offset: 0,
+                reason: "require 4 bytes, found 3".to_string(),
+            })
+        );
+        assert_eq!(
+            RData::parse(&b, 2, 4, RecordType::A),
+            Err(DnsError::Truncated {
+                needed: 4,
+                available: 2,
+                offset: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_compressed_names() {
+        // "google.com" after the header, followed by NS and MX rdata pointing
+        // into it
+        let b = [
+            vec![0u8; 12],
+            vec![6u8],
+            "google".as_bytes().to_vec(),
+            vec![3u8],
+            "com".as_bytes().to_vec(),
+            vec![0u8],
+            vec![3u8],
+            "ns1".as_bytes().to_vec(),
+            vec![0xC0, 0x0C],
+            vec![0, 10, 0xC0, 0x0C],
+        ]
+        .concat();
+
+        let rdata = RData::parse(&b, 24, 6, RecordType::NS).unwrap();
+        assert_eq!(rdata, RData::NS("ns1.google.com".parse::<Name>().unwrap()));
+
+        let rdata = RData::parse(&b, 30, 4, RecordType::MX).unwrap();
+        assert_eq!(
+            rdata,
+            RData::MX(Mx {
+                preference: 10,
+                exchange: "google.com".parse().unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_txt_and_unknown() {
+        let b = [vec![2u8], "hi".as_bytes().to_vec(), vec![0u8]].concat();
+        let rdata = RData::parse(&b, 0, b.len(), RecordType::TXT).unwrap();
+        assert_eq!(rdata, RData::TXT(vec![b"hi".to_vec(), vec![]]));
+
+        let rdata = RData::parse(&b, 0, b.len(), RecordType::Unknown(65280)).unwrap();
+        assert_eq!(rdata, RData::Unknown(b.clone()));
+    }
+
+    #[test]
+    fn test_write_and_parse_rdata() {
+        let name = |s: &str| s.parse::<Name>().unwrap();
+        let cases = [
+            (
+                RecordType::SOA,
+                RData::SOA(Soa {
+                    mname: name("ns1.example.com"),
+                    rname: name("hostmaster.example.com"),
+                    serial: 2024010101,
+                    refresh: 7200,
+                    retry: 3600,
+                    expire: 1209600,
+                    minimum: 300,
+                }),
+            ),
+            (
+                RecordType::SRV,
+                RData::SRV(Srv {
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    target: name("sip.example.com"),
+                }),
+            ),
+            (RecordType::TXT, RData::TXT(vec![b"v=spf1 -all".to_vec()])),
+            (
+                RecordType::RRSIG,
+                RData::RRSIG(Rrsig {
+                    type_covered: RecordType::A,
+                    algorithm: 13,
+                    labels: 2,
+                    original_ttl: 3600,
+                    expiration: 1700000000,
+                    inception: 1690000000,
+                    key_tag: 12345,
+                    signer_name: name("example.com"),
+                    signature: vec![1, 2, 3, 4],
+                }),
+            ),
+            (
+                RecordType::NSEC,
+                RData::NSEC(Nsec {
+                    next_domain: name("b.example.com"),
+                    types: vec![RecordType::A, RecordType::RRSIG, RecordType::NSEC],
+                }),
+            ),
+            (
+                RecordType::NSEC3,
+                RData::NSEC3(Nsec3 {
+                    hash_algorithm: 1,
+                    flags: 1,
+                    iterations: 0,
+                    salt: vec![0xAA, 0xBB],
+                    next_hashed_owner: vec![7; 20],
+                    types: vec![RecordType::NS, RecordType::DS, RecordType::CAA],
+                }),
+            ),
+        ];
+
+        for (t, rdata) in cases {
+            let mut dest = vec![0u8; 100];
+            let n = rdata.write(&mut dest).unwrap();
+            let parsed = RData::parse(&dest[..n], 0, n, t).unwrap();
+            assert_eq!(rdata, parsed);
+
+            rdata.write(&mut dest[..n - 1]).unwrap_err();
+        }
+
+        // TSIG lengths are 16 bits
+        let tsig = RData::TSIG(Tsig {
+            algorithm: name("hmac-sha256"),
+            time_signed: 0,
+            fudge: 300,
+            mac: vec![],
+            original_id: 0,
+            error: ResponseCode::NoError,
+            other: vec![0; 65536],
+        });
+        assert_eq!(
+            tsig.write(&mut vec![0u8; 70000]),
+            Err(DnsError::MarshalError(
+                "write rdata: TSIG other data cannot exceed 65535 octets".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_display_rdata() {
+        let name = |s: &str| s.parse::<Name>().unwrap();
+        let cases = [
+            (RData::A(Ipv4Addr::new(10, 0, 0, 1)), "10.0.0.1"),
+            (
+                RData::MX(Mx {
+                    preference: 10,
+                    exchange: name("mail.example.com"),
+                }),
+                "10 mail.example.com.",
+            ),
+            (
+                RData::TXT(vec![b"say \"hi\"".to_vec(), vec![b'a', 0x7f, b'\\']]),
+                r#""say \"hi\"" "a\127\\""#,
+            ),
+            (RData::Unknown(vec![]), r"\# 0"),
+            (RData::Unknown(vec![0xde, 0xad]), r"\# 2 DEAD"),
+        ];
+        for (rdata, expected) in cases {
+            assert_eq!(rdata.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_rdata_text_round_trip() {
+        let name = |s: &str| s.parse::<Name>().unwrap();
+        let cases = [
+            (RecordType::A, RData::A(Ipv4Addr::new(10, 0, 0, 1))),
+            (
+                RecordType::SRV,
+                RData::SRV(Srv {
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    target: name("sip.example.com"),
+                }),
+            ),
+            (
+                RecordType::TXT,
+                RData::TXT(vec![b"say \"hi\"".to_vec(), vec![0, b' ', 0xff]]),
+            ),
+            (
+                RecordType::RRSIG,
+                RData::RRSIG(Rrsig {
+                    type_covered: RecordType::A,
+                    algorithm: 13,
+                    labels: 2,
+                    original_ttl: 3600,
+                    expiration: 1700000000,
+                    inception: 1690000000,
+                    key_tag: 12345,
+                    signer_name: name("example.com"),
+                    signature: vec![1, 2, 3, 4],
+                }),
+            ),
+            (
+                RecordType::NSEC3,
+                RData::NSEC3(Nsec3 {
+                    hash_algorithm: 1,
+                    flags: 1,
+                    iterations: 0,
+                    salt: vec![],
+                    next_hashed_owner: vec![7; 20],
+                    types: vec![RecordType::NS, RecordType::DS],
+                }),
+            ),
+            (RecordType::CAA, RData::Unknown(vec![0, 5, b'i'])),
+        ];
+        for (t, rdata) in cases {
+            let text = rdata.to_string();
+            assert_eq!(RData::from_text(t, &text, &Name::root()).unwrap(), rdata);
+        }
+
+        // the generic form is accepted for known types too
+        assert_eq!(
+            RData::from_text(RecordType::A, r"\# 4 0A000001", &Name::root()).unwrap(),
+            RData::A(Ipv4Addr::new(10, 0, 0, 1))
+        );
+        assert_eq!(
+            RData::from_text(RecordType::MX, "( 10\n mail )", &name("example.com")).unwrap(),
+            RData::MX(Mx {
+                preference: 10,
+                exchange: name("mail.example.com"),
+            })
+        );
+        RData::from_text(RecordType::A, "10.0.0.1 extra", &Name::root()).unwrap_err();
+        RData::from_text(RecordType::CAA, "0 issue ca.example", &Name::root()).unwrap_err();
+    }
+}
@@ -1,58 +1,107 @@
+use std::fmt;
+
 use crate::errors::DnsError;
 
-use super::label::{parse_label_bytes, Label};
+use super::label::CompressionTable;
+use super::name::Name;
+use super::rdata::RData;
+use super::types::{DnsClass, RecordType};
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct ResourceRecord {
-    pub name: Vec<Label>,
-    pub t: u16,
-    pub class: u16,
+    pub name: Name,
+    pub t: RecordType,
+    pub class: DnsClass,
     pub ttl: u32,
-    pub rdlength: u16,
-    pub rdata: Vec<u8>,
+    pub rdata: RData,
 }
 
 impl ResourceRecord {
-    pub fn parse(b: &[u8]) -> Result<(usize, Self), DnsError> {
-        let (offset, name) = parse_label_bytes(b)?;
-        if offset + 9 >= b.len() {
-            return Err(DnsError::ParseError(format!(
-                "parse: not enough bytes to parse resource record"
-            )));
-        }
-        let t = u16::from_be_bytes([b[offset], b[offset + 1]]);
-        let class = u16::from_be_bytes([b[offset + 2], b[offset + 3]]);
-        let ttl = u32::from_be_bytes([b[offset + 4], b[offset + 5], b[offset + 6], b[offset + 7]]);
-        let rdlength = u16::from_be_bytes([b[offset + 8], b[offset + 9]]);
-        if b.len() < offset + 9 + rdlength as usize {
-            return Err(DnsError::ParseError(format!(
-                "parse: not enough bytes to parse resource record"
-            )));
+    /// Parses the resource record starting at `offset` in `msg`, returning the
+    /// number of bytes read. Names in the owner and rdata are resolved against
+    /// `msg`.
+    pub fn parse(msg: &[u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let (read, name) = Name::parse(msg, offset)?;
+        let b = &msg[offset + read..];
+        if b.len() < 10 {
+            return Err(DnsError::Truncated {
+                needed: 10,
+                available: b.len(),
+                offset: offset + read,
+            });
         }
-        if rdlength == 0 {
-            return Ok((
-                offset + 9 + 1,
-                ResourceRecord {
-                    name: name,
-                    t: t,
-                    class: class,
-                    ttl: ttl,
-                    rdlength: rdlength,
-                    rdata: vec![],
-                },
-            ));
+        let t = RecordType::from(u16::from_be_bytes([b[0], b[1]]));
+        if t == RecordType::OPT && !name.is_root() {
+            return Err(DnsError::InvalidRdata {
+                rtype: t,
+                offset,
+                reason: "OPT record must be owned by the root".to_string(),
+            });
         }
-        let rdata = b[offset + 10..offset + 10 + rdlength as usize].to_owned();
-        return Ok((
-            offset + 9 + rdlength as usize + 1,
+        let class = DnsClass::from(u16::from_be_bytes([b[2], b[3]]));
+        let ttl = u32::from_be_bytes([b[4], b[5], b[6], b[7]]);
+        let rdlength = u16::from_be_bytes([b[8], b[9]]) as usize;
+        let rdata = RData::parse_for_class(msg, offset + read + 10, rdlength, t, class)?;
+        Ok((
+            read + 10 + rdlength,
             ResourceRecord {
-                name: name,
-                t: t,
-                class: class,
-                ttl: ttl,
-                rdlength: rdlength,
-                rdata: rdata,
+                name,
+                t,
+                class,
+                ttl,
+                rdata,
             },
-        ));
+        ))
+    }
+
+    /// Writes the record into `dest`, returning the number of bytes written.
+    pub fn write(&self, dest: &mut [u8]) -> Result<usize, DnsError> {
+        self.write_compressed(dest, 0, &mut CompressionTable::disabled())
+    }
+
+    /// Writes the record at `offset` in `msg`, compressing names against
+    /// `table`. Returns the number of bytes written.
+    pub fn write_compressed(
+        &self,
+        msg: &mut [u8],
+        offset: usize,
+        table: &mut CompressionTable,
+    ) -> Result<usize, DnsError> {
+        let written = table.write_name(&self.name, msg, offset)?;
+        let dest = &mut msg[offset..];
+        if dest.len() < written + 10 {
+            return Err(DnsError::BufferTooSmall {
+                needed: 10,
+                available: dest.len() - written,
+                offset: offset + written,
+            });
+        }
+        dest[written..written + 2].copy_from_slice(&u16::from(self.t).to_be_bytes());
+        dest[written + 2..written + 4].copy_from_slice(&u16::from(self.class).to_be_bytes());
+        dest[written + 4..written + 8].copy_from_slice(&self.ttl.to_be_bytes());
+
+        let rdlength = self
+            .rdata
+            .write_compressed(msg, offset + written + 10, table)?;
+        let dest = &mut msg[offset..];
+        if rdlength > u16::MAX as usize {
+            return Err(DnsError::MarshalError(
+                "write: rdata cannot exceed 65535 bytes".to_string(),
+            ));
+        }
+        dest[written + 8..written + 10].copy_from_slice(&(rdlength as u16).to_be_bytes());
+
+        Ok(written + 10 + rdlength)
+    }
+}
+
+/// Renders the record in master file syntax, RFC 1035 5.1.
+impl fmt::Display for ResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.name, self.ttl, self.class, self.t, self.rdata
+        )
     }
 }
@@ -0,0 +1,523 @@
+// presentation format text, as used in zone files, RFC 1035 5.1
+
+use std::{fmt, iter::Peekable, str::Chars, str::FromStr};
+
+use crate::errors::DnsError;
+
+use super::{label::Label, name::Name};
+
+pub fn syntax_error(line: usize, column: usize, message: String) -> DnsError {
+    DnsError::SyntaxError {
+        line,
+        column,
+        message,
+    }
+}
+
+/// A whitespace separated field of presentation text. Escapes are kept as
+/// written so that each field can be decoded by its own rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub quoted: bool,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token {
+    pub fn error(&self, message: String) -> DnsError {
+        syntax_error(self.line, self.column, message)
+    }
+
+    /// true for an unquoted token reading exactly `s`.
+    pub fn is(&self, s: &str) -> bool {
+        !self.quoted && self.text == s
+    }
+}
+
+/// One logical line of presentation text, with parenthesized continuation
+/// lines joined and comments removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub tokens: Vec<Token>,
+    /// the line started with whitespace, so the owner name was omitted.
+    pub blank_owner: bool,
+}
+
+/// Splits presentation text into entries.
+pub struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Reads the next entry, or `None` at the end of the text.
+    pub fn next_entry(&mut self) -> Result<Option<Entry>, DnsError> {
+        let mut tokens = vec![];
+        let mut blank_owner = false;
+        let mut line_start = true;
+        // position of the outermost open parenthesis
+        let mut open = (0, 0);
+        let mut depth = 0usize;
+
+        while let Some(&c) = self.chars.peek() {
+            if line_start && tokens.is_empty() {
+                blank_owner = c == ' ' || c == '\t';
+            }
+            line_start = false;
+            match c {
+                '\n' => {
+                    self.bump();
+                    if depth == 0 && !tokens.is_empty() {
+                        break;
+                    }
+                    line_start = true;
+                }
+                ' ' | '\t' | '\r' => {
+                    self.bump();
+                }
+                ';' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                '(' => {
+                    if depth == 0 {
+                        open = (self.line, self.column);
+                    }
+                    depth += 1;
+                    self.bump();
+                }
+                ')' => {
+                    if depth == 0 {
+                        return Err(syntax_error(
+                            self.line,
+                            self.column,
+                            "unbalanced ')'".to_string(),
+                        ));
+                    }
+                    depth -= 1;
+                    self.bump();
+                }
+                '"' => tokens.push(self.quoted()?),
+                _ => tokens.push(self.unquoted()?),
+            }
+        }
+
+        if depth > 0 {
+            return Err(syntax_error(open.0, open.1, "unclosed '('".to_string()));
+        }
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Entry {
+            tokens,
+            blank_owner,
+        }))
+    }
+
+    /// reads a backslash and the character it escapes into `text`.
+    fn escape(&mut self, text: &mut String) -> Result<(), DnsError> {
+        let (line, column) = (self.line, self.column);
+        text.push('\\');
+        self.bump();
+        match self.bump() {
+            Some('\n') | None => Err(syntax_error(
+                line,
+                column,
+                "unterminated escape".to_string(),
+            )),
+            Some(c) => {
+                text.push(c);
+                Ok(())
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<Token, DnsError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        self.bump();
+        loop {
+            match self.chars.peek() {
+                Some('"') => {
+                    self.bump();
+                    break;
+                }
+                Some('\\') => self.escape(&mut text)?,
+                Some('\n') | None => {
+                    return Err(syntax_error(
+                        line,
+                        column,
+                        "unterminated quoted string".to_string(),
+                    ))
+                }
+                Some(&c) => {
+                    text.push(c);
+                    self.bump();
+                }
+            }
+        }
+        Ok(Token {
+            text,
+            quoted: true,
+            line,
+            column,
+        })
+    }
+
+    fn unquoted(&mut self) -> Result<Token, DnsError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | ';' | '(' | ')' | '"' => break,
+                '\\' => self.escape(&mut text)?,
+                _ => {
+                    text.push(c);
+                    self.bump();
+                }
+            }
+        }
+        Ok(Token {
+            text,
+            quoted: false,
+            line,
+            column,
+        })
+    }
+}
+
+/// Decodes the escapes of RFC 1035 5.1, where `\X` stands for X and `\DDD`
+/// for the octet with decimal value DDD.
+pub fn decode_escapes(s: &str) -> Result<Vec<u8>, DnsError> {
+    let b = s.as_bytes();
+    let mut result = Vec::with_capacity(b.len());
+    let mut idx = 0;
+    while idx < b.len() {
+        if b[idx] != b'\\' {
+            result.push(b[idx]);
+            idx += 1;
+            continue;
+        }
+        if idx + 1 == b.len() {
+            return Err(DnsError::Generic("unterminated escape".to_string()));
+        }
+        let digits = &b[idx + 1..(idx + 4).min(b.len())];
+        if !digits.is_empty() && digits[0].is_ascii_digit() {
+            if digits.len() < 3 || !digits.iter().all(u8::is_ascii_digit) {
+                return Err(DnsError::Generic(
+                    "escape must have three digits".to_string(),
+                ));
+            }
+            let value = digits.iter().fold(0u16, |v, d| v * 10 + (d - b'0') as u16);
+            if value > 255 {
+                return Err(DnsError::Generic(format!("escape \\{} exceeds 255", value)));
+            }
+            result.push(value as u8);
+            idx += 4;
+        } else {
+            result.push(b[idx + 1]);
+            idx += 2;
+        }
+    }
+    Ok(result)
+}
+
+/// places a `Generic` error at `token`.
+fn at(token: &Token, e: DnsError) -> DnsError {
+    match e {
+        DnsError::Generic(message) => token.error(message),
+        e => e,
+    }
+}
+
+/// `decode_escapes` for the text of `token`.
+pub fn unescape(token: &Token) -> Result<Vec<u8>, DnsError> {
+    decode_escapes(&token.text).map_err(|e| at(token, e))
+}
+
+/// Splits the text of a name on unescaped dots, leaving escapes in the
+/// parts. Also returns whether the name ended with a dot, making it
+/// absolute. `.` alone is the root.
+pub fn split_name(s: &str) -> Result<(Vec<&str>, bool), DnsError> {
+    if s == "." {
+        return Ok((vec![], true));
+    }
+    let b = s.as_bytes();
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < b.len() {
+        match b[idx] {
+            b'\\' => idx += 2,
+            b'.' => {
+                parts.push(&s[start..idx]);
+                idx += 1;
+                start = idx;
+            }
+            _ => idx += 1,
+        }
+    }
+    let absolute = start == b.len() && !parts.is_empty();
+    if !absolute && !s.is_empty() {
+        parts.push(&s[start..]);
+    }
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(DnsError::Generic(format!("empty label in {}", s)));
+    }
+    Ok((parts, absolute))
+}
+
+/// Writes a label in presentation format, escaping dots, backslashes and the
+/// characters special to zone files, and writing other octets outside of
+/// printable ASCII as `\DDD`, RFC 4343 2.1.
+pub fn write_label<W: fmt::Write>(w: &mut W, label: &[u8]) -> fmt::Result {
+    for &c in label {
+        match c {
+            b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => write!(w, "\\{}", c as char)?,
+            0x21..=0x7E => w.write_char(c as char)?,
+            _ => write!(w, "\\{:03}", c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Parses a domain name, completing relative names with `origin`. `@`
+/// stands for the origin itself. Labels are taken as raw octets, without
+/// IDNA processing.
+pub fn parse_name(token: &Token, origin: &Name) -> Result<Name, DnsError> {
+    if token.is("@") {
+        return Ok(origin.clone());
+    }
+    let (parts, absolute) = split_name(&token.text).map_err(|e| at(token, e))?;
+    if parts.is_empty() && !absolute {
+        return Err(token.error("empty name".to_string()));
+    }
+    let mut labels = vec![];
+    for part in parts {
+        labels.push(Label::L(decode_escapes(part).map_err(|e| at(token, e))?));
+    }
+    if !absolute {
+        labels.extend(origin.to_labels());
+    }
+    Name::from_labels(&labels).map_err(|e| token.error(format!("invalid name: {}", e)))
+}
+
+/// Parses a TTL, either in seconds or with BIND style units such as `1h30m`.
+pub fn parse_ttl(s: &str) -> Option<u32> {
+    if s.is_empty() {
+        return None;
+    }
+    if let Ok(ttl) = s.parse() {
+        return Some(ttl);
+    }
+    let mut total = 0u32;
+    let mut value: Option<u32> = None;
+    for c in s.chars() {
+        if let Some(d) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0).checked_mul(10)?.checked_add(d)?);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return None,
+        };
+        total = total.checked_add(value.take()?.checked_mul(unit)?)?;
+    }
+    if value.is_some() {
+        // a trailing number without a unit
+        return None;
+    }
+    Some(total)
+}
+
+/// Consumes the fields of an entry in order, reporting missing fields at the
+/// position of the last field read.
+pub struct Fields<'a> {
+    tokens: &'a [Token],
+    idx: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Fields<'a> {
+    /// `line` and `column` locate whatever precedes the fields.
+    pub fn new(tokens: &'a [Token], line: usize, column: usize) -> Self {
+        Self {
+            tokens,
+            idx: 0,
+            line,
+            column,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.idx == self.tokens.len()
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.idx)
+    }
+
+    pub fn error(&self, message: String) -> DnsError {
+        match self.peek() {
+            Some(token) => token.error(message),
+            None => syntax_error(self.line, self.column, message),
+        }
+    }
+
+    pub fn next(&mut self, what: &str) -> Result<&'a Token, DnsError> {
+        let token = self
+            .peek()
+            .ok_or_else(|| self.error(format!("missing {}", what)))?;
+        self.idx += 1;
+        self.line = token.line;
+        self.column = token.column;
+        Ok(token)
+    }
+
+    /// the remaining fields.
+    pub fn rest(&mut self) -> &'a [Token] {
+        let rest = &self.tokens[self.idx..];
+        self.idx = self.tokens.len();
+        rest
+    }
+
+    /// a field parsed with `FromStr`, such as a number or address.
+    pub fn parse<T: FromStr>(&mut self, what: &str) -> Result<T, DnsError> {
+        let token = self.next(what)?;
+        if token.quoted {
+            return Err(token.error(format!("{} cannot be quoted", what)));
+        }
+        token
+            .text
+            .parse()
+            .map_err(|_| token.error(format!("invalid {}: {}", what, token.text)))
+    }
+
+    pub fn ttl(&mut self, what: &str) -> Result<u32, DnsError> {
+        let token = self.next(what)?;
+        parse_ttl(&token.text)
+            .ok_or_else(|| token.error(format!("invalid {}: {}", what, token.text)))
+    }
+
+    pub fn name(&mut self, what: &str, origin: &Name) -> Result<Name, DnsError> {
+        parse_name(self.next(what)?, origin)
+    }
+
+    /// a character string of at most 255 octets, quoted or not.
+    pub fn character_string(&mut self, what: &str) -> Result<Vec<u8>, DnsError> {
+        let token = self.next(what)?;
+        let b = unescape(token)?;
+        if b.len() > 255 {
+            return Err(token.error(format!("{} cannot exceed 255 octets", what)));
+        }
+        Ok(b)
+    }
+
+    /// the remaining fields joined together, for base64 and hex data that
+    /// may be split by whitespace.
+    pub fn joined(&mut self, what: &str) -> Result<String, DnsError> {
+        if self.is_empty() {
+            return Err(self.error(format!("missing {}", what)));
+        }
+        Ok(self.rest().iter().map(|t| t.text.as_str()).collect())
+    }
+
+    /// fails if any fields are left.
+    pub fn finish(&self) -> Result<(), DnsError> {
+        match self.peek() {
+            Some(token) => Err(token.error(format!("unexpected {}", token.text))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_name, parse_ttl, unescape, Lexer};
+    use crate::errors::DnsError;
+    use crate::message::name::Name;
+
+    #[test]
+    fn test_lexer() {
+        let text = "a  IN ( 1 ; comment\n  \"two words\" \\( )\n\n  b ; c\n";
+        let mut lexer = Lexer::new(text);
+        let entry = lexer.next_entry().unwrap().unwrap();
+        assert!(!entry.blank_owner);
+        let texts: Vec<_> = entry.tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, ["a", "IN", "1", "two words", "\\("]);
+        assert!(entry.tokens[3].quoted);
+        assert_eq!((entry.tokens[3].line, entry.tokens[3].column), (2, 3));
+
+        let entry = lexer.next_entry().unwrap().unwrap();
+        assert!(entry.blank_owner);
+        assert_eq!(entry.tokens[0].text, "b");
+        assert_eq!(lexer.next_entry().unwrap(), None);
+
+        assert_eq!(
+            Lexer::new("a (\nb").next_entry().unwrap_err(),
+            DnsError::SyntaxError {
+                line: 1,
+                column: 3,
+                message: "unclosed '('".to_string()
+            }
+        );
+        Lexer::new("\"abc\n\"").next_entry().unwrap_err();
+    }
+
+    #[test]
+    fn test_names_and_escapes() {
+        let origin: Name = "example.com".parse().unwrap();
+        let token = |s: &str| Lexer::new(s).next_entry().unwrap().unwrap().tokens[0].clone();
+        assert_eq!(
+            unescape(&token(r#""a\"b\\c\065""#)).unwrap(),
+            b"a\"b\\cA".to_vec()
+        );
+        unescape(&token(r"\256")).unwrap_err();
+        unescape(&token(r"\12")).unwrap_err();
+
+        let name = |s: &str| parse_name(&token(s), &origin).unwrap().to_string();
+        assert_eq!(name("@"), "example.com.");
+        assert_eq!(name("www"), "www.example.com.");
+        assert_eq!(name("www.example.org."), "www.example.org.");
+        assert_eq!(name("."), ".");
+        assert_eq!(name(r"\097b"), "ab.example.com.");
+        parse_name(&token("a..b"), &origin).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("3600"), Some(3600));
+        assert_eq!(parse_ttl("1h30m"), Some(5400));
+        assert_eq!(parse_ttl("1W"), Some(604800));
+        assert_eq!(parse_ttl("1h30"), None);
+        assert_eq!(parse_ttl("h"), None);
+        assert_eq!(parse_ttl("99999999999"), None);
+    }
+}
@@ -0,0 +1,497 @@
+// transaction signatures, RFC 8945
+
+use std::fmt;
+
+use data_encoding::BASE64;
+use ring::hmac;
+
+use crate::errors::DnsError;
+
+use super::{
+    header::{ResponseCode, HEADER_LENGTH},
+    name::Name,
+    rdata::RData,
+    rr::ResourceRecord,
+    types::{DnsClass, RecordType},
+    view::{skip_questions, skip_records},
+    Message,
+};
+
+/// time difference allowed between signer and verifier, RFC 8945 10.
+pub const DEFAULT_FUDGE: u16 = 300;
+
+/// unsigned messages allowed in a row within a zone transfer, RFC 8945
+/// 5.3.1.
+const MAX_UNSIGNED_MESSAGES: usize = 99;
+
+/// Transaction signature, RFC 8945 4.2
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Tsig {
+    pub algorithm: Name,
+    /// seconds since the epoch, 48 bits on the wire.
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: ResponseCode,
+    pub other: Vec<u8>,
+}
+
+/// MAC algorithms from RFC 8945 6 that are required or recommended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    /// the name identifying the algorithm in TSIG records.
+    pub fn name(&self) -> Name {
+        let name = match self {
+            Self::HmacSha256 => "hmac-sha256.",
+            Self::HmacSha384 => "hmac-sha384.",
+            Self::HmacSha512 => "hmac-sha512.",
+        };
+        name.parse().unwrap()
+    }
+
+    pub fn from_name(name: &Name) -> Option<Self> {
+        [Self::HmacSha256, Self::HmacSha384, Self::HmacSha512]
+            .into_iter()
+            .find(|alg| alg.name() == *name)
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            Self::HmacSha256 => hmac::HMAC_SHA256,
+            Self::HmacSha384 => hmac::HMAC_SHA384,
+            Self::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+/// A shared secret known to both ends under the same name.
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    name: Name,
+    algorithm: TsigAlgorithm,
+    key: hmac::Key,
+    fudge: u16,
+}
+
+impl TsigKey {
+    pub fn new(name: Name, algorithm: TsigAlgorithm, secret: &[u8]) -> Self {
+        Self {
+            name,
+            algorithm,
+            key: hmac::Key::new(algorithm.hmac(), secret),
+            fudge: DEFAULT_FUDGE,
+        }
+    }
+
+    /// sets the fudge of signed messages, `DEFAULT_FUDGE` by default.
+    pub fn fudge(mut self, fudge: u16) -> Self {
+        self.fudge = fudge;
+        self
+    }
+
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    pub fn algorithm(&self) -> TsigAlgorithm {
+        self.algorithm
+    }
+
+    /// starts signing or verifying the messages of one exchange.
+    pub fn session(&self) -> TsigSession {
+        TsigSession {
+            key: self.clone(),
+            prior_mac: None,
+            signed: 0,
+            unsigned: vec![],
+            unsigned_count: 0,
+        }
+    }
+}
+
+/// Signs and verifies the messages of one exchange with a key: a request
+/// followed by its response, or by the many responses of a zone transfer.
+/// Each MAC covers the one before it, RFC 8945 5.3, so the messages must be
+/// passed in the order they are sent and received.
+#[derive(Debug)]
+pub struct TsigSession {
+    key: TsigKey,
+    prior_mac: Option<Vec<u8>>,
+    /// messages signed or verified so far.
+    signed: usize,
+    /// messages received without a TSIG since the last signed one.
+    unsigned: Vec<u8>,
+    unsigned_count: usize,
+}
+
+impl TsigSession {
+    /// Signs `msg` as of `now` seconds since the epoch, returning it with
+    /// the TSIG record appended to the additional section.
+    pub fn sign(&mut self, msg: &Message, now: u64) -> Result<Message, DnsError> {
+        if msg.ar.iter().any(|r| r.t == RecordType::TSIG) {
+            return Err(DnsError::MarshalError(
+                "sign: message already has a TSIG record".to_string(),
+            ));
+        }
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let n = msg.write(&mut buf)?;
+        let mut tsig = Tsig {
+            algorithm: self.key.algorithm.name(),
+            time_signed: now,
+            fudge: self.key.fudge,
+            mac: vec![],
+            original_id: msg.hdr.id,
+            error: ResponseCode::NoError,
+            other: vec![],
+        };
+        tsig.mac = hmac::sign(&self.key.key, &self.digest_input(&buf[..n], &tsig))
+            .as_ref()
+            .to_vec();
+        self.prior_mac = Some(tsig.mac.clone());
+        self.signed += 1;
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+
+        let mut signed = msg.clone();
+        signed.ar.push(ResourceRecord {
+            name: self.key.name.clone(),
+            t: RecordType::TSIG,
+            class: DnsClass::ANY,
+            ttl: 0,
+            rdata: RData::TSIG(tsig),
+        });
+        signed.hdr.arcount += 1;
+        Ok(signed)
+    }
+
+    /// Records `msg` as sent without a TSIG, for the next signed message to
+    /// cover. Only messages after the first response of a zone transfer may
+    /// be left unsigned, no more than 99 in a row, RFC 8945 5.3.1.
+    pub fn defer(&mut self, msg: &Message) -> Result<(), DnsError> {
+        if self.signed < 2 || self.unsigned_count >= MAX_UNSIGNED_MESSAGES {
+            return Err(DnsError::MarshalError(
+                "defer: message must be signed".to_string(),
+            ));
+        }
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let n = msg.write(&mut buf)?;
+        self.unsigned.extend_from_slice(&buf[..n]);
+        self.unsigned_count += 1;
+        Ok(())
+    }
+
+    /// Parses `wire` and checks its TSIG as of `now` seconds since the
+    /// epoch, RFC 8945 5.2. After the first response of a zone transfer,
+    /// messages may be unsigned as long as a later one is signed; see
+    /// `finish`. The TSIG record is left in the additional section.
+    pub fn verify(&mut self, wire: &[u8], now: u64) -> Result<Message, DnsError> {
+        let (_, msg) = Message::parse(wire)?;
+        let tsig = match msg.ar.last() {
+            Some(ResourceRecord {
+                rdata: RData::TSIG(tsig),
+                ..
+            }) => tsig,
+            _ if self.signed >= 2 && self.unsigned_count < MAX_UNSIGNED_MESSAGES => {
+                self.unsigned.extend_from_slice(wire);
+                self.unsigned_count += 1;
+                return Ok(msg);
+            }
+            _ => {
+                return Err(DnsError::TsigFailed {
+                    error: ResponseCode::NotAuth,
+                })
+            }
+        };
+        let owner = &msg.ar[msg.ar.len() - 1].name;
+        if *owner != self.key.name || tsig.algorithm != self.key.algorithm.name() {
+            return Err(DnsError::TsigFailed {
+                error: ResponseCode::BadKey,
+            });
+        }
+        // the other end could not verify our message and did not sign its
+        // response, RFC 8945 5.3.2
+        if tsig.error != ResponseCode::NoError {
+            return Err(DnsError::TsigFailed { error: tsig.error });
+        }
+        // truncated MACs are not accepted, RFC 8945 5.2.2.1
+        if tsig.mac.len() != self.key.algorithm.hmac().digest_algorithm().output_len() {
+            return Err(DnsError::TsigFailed {
+                error: ResponseCode::BadTrunc,
+            });
+        }
+
+        // the message as it was signed: without the TSIG record and with
+        // the ID it had before any forwarder changed it
+        let hdr = &msg.hdr;
+        let offset = skip_questions(wire, HEADER_LENGTH, hdr.qdcount)
+            .and_then(|offset| skip_records(wire, offset, hdr.ancount))
+            .and_then(|offset| skip_records(wire, offset, hdr.nscount))
+            .and_then(|offset| skip_records(wire, offset, hdr.arcount - 1))?;
+        let mut unsigned = wire[..offset].to_vec();
+        unsigned[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        unsigned[10..12].copy_from_slice(&(hdr.arcount - 1).to_be_bytes());
+
+        let input = self.digest_input(&unsigned, tsig);
+        hmac::verify(&self.key.key, &input, &tsig.mac).map_err(|_| DnsError::TsigFailed {
+            error: ResponseCode::BadSig,
+        })?;
+        if now.abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+            return Err(DnsError::TsigFailed {
+                error: ResponseCode::BadTime,
+            });
+        }
+
+        self.prior_mac = Some(tsig.mac.clone());
+        self.signed += 1;
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+        Ok(msg)
+    }
+
+    /// Checks that the last message verified was signed, as the final
+    /// message of a zone transfer must be, RFC 8945 5.3.1.
+    pub fn finish(self) -> Result<(), DnsError> {
+        if self.unsigned_count > 0 {
+            return Err(DnsError::TsigFailed {
+                error: ResponseCode::NotAuth,
+            });
+        }
+        Ok(())
+    }
+
+    /// The data covered by the MAC of `msg`, RFC 8945 4.3: the prior MAC,
+    /// any unsigned messages since it, the message and the TSIG variables.
+    /// After the first response of an exchange only the timers are covered,
+    /// RFC 8945 5.3.1.
+    fn digest_input(&self, msg: &[u8], tsig: &Tsig) -> Vec<u8> {
+        let mut data = vec![];
+        if let Some(mac) = &self.prior_mac {
+            data.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+            data.extend_from_slice(mac);
+        }
+        data.extend_from_slice(&self.unsigned);
+        data.extend_from_slice(msg);
+        if self.signed < 2 {
+            data.extend_from_slice(&name_bytes(&self.key.name));
+            data.extend_from_slice(&u16::from(DnsClass::ANY).to_be_bytes());
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&name_bytes(&tsig.algorithm));
+        }
+        data.extend_from_slice(&tsig.time_signed.to_be_bytes()[2..]);
+        data.extend_from_slice(&tsig.fudge.to_be_bytes());
+        if self.signed < 2 {
+            data.extend_from_slice(&u16::from(tsig.error).to_be_bytes());
+            data.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+            data.extend_from_slice(&tsig.other);
+        }
+        data
+    }
+}
+
+/// the canonical wire form of `name`, lowercased and uncompressed.
+fn name_bytes(name: &Name) -> Vec<u8> {
+    let mut b = vec![0u8; name.wire_len()];
+    name.to_lowercase().write(&mut b).unwrap();
+    b
+}
+
+/// Reads the error field of a TSIG record. BADSIG shares 16 with BADVERS,
+/// which is the meaning `ResponseCode::from` picks.
+pub(crate) fn tsig_error_from_code(code: u16) -> ResponseCode {
+    match code {
+        16 => ResponseCode::BadSig,
+        code => code.into(),
+    }
+}
+
+impl fmt::Display for Tsig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {}",
+            self.algorithm,
+            self.time_signed,
+            self.fudge,
+            self.mac.len(),
+            BASE64.encode(&self.mac),
+            self.original_id,
+            self.error,
+            self.other.len()
+        )?;
+        if !self.other.is_empty() {
+            write!(f, " {}", BASE64.encode(&self.other))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::{TsigAlgorithm, TsigKey};
+    use crate::{
+        errors::DnsError,
+        message::{
+            builder::MessageBuilder,
+            edns::Edns,
+            header::ResponseCode,
+            name::Name,
+            rdata::RData,
+            rr::ResourceRecord,
+            types::{DnsClass, RecordType},
+            Message,
+        },
+    };
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn key(algorithm: TsigAlgorithm) -> TsigKey {
+        TsigKey::new(
+            "transfer-key".parse().unwrap(),
+            algorithm,
+            b"0123456789abcdef",
+        )
+    }
+
+    fn query() -> Message {
+        MessageBuilder::new()
+            .question(
+                "example.com".parse().unwrap(),
+                RecordType::AXFR,
+                DnsClass::IN,
+            )
+            .edns(Edns::default())
+            .build()
+            .unwrap()
+    }
+
+    fn wire(msg: &Message) -> Vec<u8> {
+        let mut buf = vec![0u8; 4096];
+        let n = msg.write(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    fn failed(error: ResponseCode) -> Result<Message, DnsError> {
+        Err(DnsError::TsigFailed { error })
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        for algorithm in [
+            TsigAlgorithm::HmacSha256,
+            TsigAlgorithm::HmacSha384,
+            TsigAlgorithm::HmacSha512,
+        ] {
+            let mut client = key(algorithm).session();
+            let mut server = key(algorithm).session();
+            let request = client.sign(&query(), NOW).unwrap();
+            let b = wire(&request);
+            // the TSIG follows the OPT record
+            assert_eq!(Message::parse(&b).unwrap().1, request);
+            let received = server.verify(&b, NOW + 10).unwrap();
+
+            let response = MessageBuilder::response_to(&received).build().unwrap();
+            let response = server.sign(&response, NOW + 10).unwrap();
+            client.verify(&wire(&response), NOW + 20).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_failures() {
+        let request = key(TsigAlgorithm::HmacSha256)
+            .session()
+            .sign(&query(), NOW)
+            .unwrap();
+        let b = wire(&request);
+
+        let mut tampered = b.clone();
+        tampered[3] ^= 0x01;
+        assert_eq!(
+            key(TsigAlgorithm::HmacSha256)
+                .session()
+                .verify(&tampered, NOW),
+            failed(ResponseCode::BadSig)
+        );
+        assert_eq!(
+            key(TsigAlgorithm::HmacSha512).session().verify(&b, NOW),
+            failed(ResponseCode::BadKey)
+        );
+        assert_eq!(
+            key(TsigAlgorithm::HmacSha256)
+                .session()
+                .verify(&b, NOW + 301),
+            failed(ResponseCode::BadTime)
+        );
+        assert_eq!(
+            key(TsigAlgorithm::HmacSha256)
+                .session()
+                .verify(&wire(&query()), NOW),
+            failed(ResponseCode::NotAuth)
+        );
+    }
+
+    #[test]
+    fn test_zone_transfer_stream() {
+        let mut client = key(TsigAlgorithm::HmacSha256).session();
+        let mut server = key(TsigAlgorithm::HmacSha256).session();
+        let query = query();
+        let request = server
+            .verify(&wire(&client.sign(&query, NOW).unwrap()), NOW)
+            .unwrap();
+        let response = |i: u8| {
+            MessageBuilder::response_to(&request)
+                .answer(ResourceRecord {
+                    name: "example.com".parse::<Name>().unwrap(),
+                    t: RecordType::A,
+                    class: DnsClass::IN,
+                    ttl: 300,
+                    rdata: RData::A(Ipv4Addr::new(192, 0, 2, i)),
+                })
+                .build()
+                .unwrap()
+        };
+
+        // every other message after the first is left unsigned
+        let stream = (0..5)
+            .map(|i| match i % 2 {
+                0 => wire(&server.sign(&response(i), NOW).unwrap()),
+                _ => {
+                    server.defer(&response(i)).unwrap();
+                    wire(&response(i))
+                }
+            })
+            .collect::<Vec<_>>();
+        for b in &stream {
+            client.verify(b, NOW).unwrap();
+        }
+        client.finish().unwrap();
+
+        // the MAC of a message covers the unsigned ones before it
+        let mut client = key(TsigAlgorithm::HmacSha256).session();
+        client.sign(&query, NOW).unwrap();
+        client.verify(&stream[0], NOW).unwrap();
+        client.verify(&wire(&response(9)), NOW).unwrap();
+        assert_eq!(client.verify(&stream[2], NOW), failed(ResponseCode::BadSig));
+
+        // a stream must end with a signed message
+        let mut client = key(TsigAlgorithm::HmacSha256).session();
+        client.sign(&query, NOW).unwrap();
+        client.verify(&stream[0], NOW).unwrap();
+        client.verify(&stream[1], NOW).unwrap();
+        assert_eq!(
+            client.finish(),
+            Err(DnsError::TsigFailed {
+                error: ResponseCode::NotAuth
+            })
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// record types and classes
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::errors::DnsError;
+
+/// Declares a u16 code enum with an `Unknown` fallback, conversions to and
+/// from u16, and mnemonic `Display`/`FromStr`. Unknown values use the generic
+/// RFC 3597 form, e.g. `TYPE65280`. Equality, hashing and ordering go by the
+/// u16 value, so `Unknown(1)` built by hand equals the variant for 1.
+macro_rules! code_enum {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $generic:literal {
+            $($(#[$vmeta:meta])* $variant:ident = $value:literal, $mnemonic:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+        #[derive(Default, Debug, Clone, Copy)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)+
+            Unknown(u16),
+        }
+
+        impl From<u16> for $name {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => Self::$variant,)+
+                    _ => Self::Unknown(value),
+                }
+            }
+        }
+
+        impl From<$name> for u16 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)+
+                    $name::Unknown(v) => v,
+                }
+            }
+        }
+
+        impl $name {
+            /// the registered mnemonic, or `None` for unknown values.
+            pub fn mnemonic(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($mnemonic),)+
+                    Self::Unknown(_) => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.mnemonic() {
+                    Some(m) => write!(f, "{}", m),
+                    None => write!(f, "{}{}", $generic, u16::from(*self)),
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = DnsError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let upper = s.to_ascii_uppercase();
+                match upper.as_str() {
+                    $($mnemonic => Ok(Self::$variant),)+
+                    _ => upper
+                        .strip_prefix($generic)
+                        .and_then(|v| v.parse::<u16>().ok())
+                        .map(Self::from)
+                        .ok_or_else(|| {
+                            DnsError::Generic(format!(
+                                "unknown {} mnemonic: {}",
+                                stringify!($name),
+                                s
+                            ))
+                        }),
+                }
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                u16::from(*self) == u16::from(*other)
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                u16::from(*self).hash(state);
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                u16::from(*self).cmp(&u16::from(*other))
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+    };
+}
+
+code_enum! {
+    /// Resource record types from the IANA DNS parameters registry.
+    RecordType, "TYPE" {
+        #[default]
+        A = 1, "A",
+        NS = 2, "NS",
+        MD = 3, "MD",
+        MF = 4, "MF",
+        CNAME = 5, "CNAME",
+        SOA = 6, "SOA",
+        MB = 7, "MB",
+        MG = 8, "MG",
+        MR = 9, "MR",
+        NULL = 10, "NULL",
+        WKS = 11, "WKS",
+        PTR = 12, "PTR",
+        HINFO = 13, "HINFO",
+        MINFO = 14, "MINFO",
+        MX = 15, "MX",
+        TXT = 16, "TXT",
+        RP = 17, "RP",
+        AFSDB = 18, "AFSDB",
+        X25 = 19, "X25",
+        ISDN = 20, "ISDN",
+        RT = 21, "RT",
+        NSAP = 22, "NSAP",
+        NSAP_PTR = 23, "NSAP-PTR",
+        SIG = 24, "SIG",
+        KEY = 25, "KEY",
+        PX = 26, "PX",
+        GPOS = 27, "GPOS",
+        AAAA = 28, "AAAA",
+        LOC = 29, "LOC",
+        NXT = 30, "NXT",
+        EID = 31, "EID",
+        NIMLOC = 32, "NIMLOC",
+        SRV = 33, "SRV",
+        ATMA = 34, "ATMA",
+        NAPTR = 35, "NAPTR",
+        KX = 36, "KX",
+        CERT = 37, "CERT",
+        A6 = 38, "A6",
+        DNAME = 39, "DNAME",
+        SINK = 40, "SINK",
+        OPT = 41, "OPT",
+        APL = 42, "APL",
+        DS = 43, "DS",
+        SSHFP = 44, "SSHFP",
+        IPSECKEY = 45, "IPSECKEY",
+        RRSIG = 46, "RRSIG",
+        NSEC = 47, "NSEC",
+        DNSKEY = 48, "DNSKEY",
+        DHCID = 49, "DHCID",
+        NSEC3 = 50, "NSEC3",
+        NSEC3PARAM = 51, "NSEC3PARAM",
+        TLSA = 52, "TLSA",
+        SMIMEA = 53, "SMIMEA",
+        HIP = 55, "HIP",
+        NINFO = 56, "NINFO",
+        RKEY = 57, "RKEY",
+        TALINK = 58, "TALINK",
+        CDS = 59, "CDS",
+        CDNSKEY = 60, "CDNSKEY",
+        OPENPGPKEY = 61, "OPENPGPKEY",
+        CSYNC = 62, "CSYNC",
+        ZONEMD = 63, "ZONEMD",
+        SVCB = 64, "SVCB",
+        HTTPS = 65, "HTTPS",
+        SPF = 99, "SPF",
+        UINFO = 100, "UINFO",
+        UID = 101, "UID",
+        GID = 102, "GID",
+        UNSPEC = 103, "UNSPEC",
+        NID = 104, "NID",
+        L32 = 105, "L32",
+        L64 = 106, "L64",
+        LP = 107, "LP",
+        EUI48 = 108, "EUI48",
+        EUI64 = 109, "EUI64",
+        TKEY = 249, "TKEY",
+        TSIG = 250, "TSIG",
+        IXFR = 251, "IXFR",
+        AXFR = 252, "AXFR",
+        MAILB = 253, "MAILB",
+        MAILA = 254, "MAILA",
+        ANY = 255, "ANY",
+        URI = 256, "URI",
+        CAA = 257, "CAA",
+        AVC = 258, "AVC",
+        DOA = 259, "DOA",
+        AMTRELAY = 260, "AMTRELAY",
+        TA = 32768, "TA",
+        DLV = 32769, "DLV",
+    }
+}
+
+code_enum! {
+    /// Resource record classes from the IANA DNS parameters registry.
+    DnsClass, "CLASS" {
+        #[default]
+        IN = 1, "IN",
+        CH = 3, "CH",
+        HS = 4, "HS",
+        NONE = 254, "NONE",
+        ANY = 255, "ANY",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{DnsClass, RecordType};
+
+    #[test]
+    fn test_record_type_conversions() {
+        assert_eq!(RecordType::from(28), RecordType::AAAA);
+        assert_eq!(u16::from(RecordType::HTTPS), 65);
+        assert_eq!(RecordType::from(65280), RecordType::Unknown(65280));
+        assert_eq!(u16::from(RecordType::Unknown(65280)), 65280);
+
+        assert_eq!("aaaa".parse::<RecordType>().unwrap(), RecordType::AAAA);
+        assert_eq!(
+            "NSAP-PTR".parse::<RecordType>().unwrap(),
+            RecordType::NSAP_PTR
+        );
+        assert_eq!("TYPE1".parse::<RecordType>().unwrap(), RecordType::A);
+        assert_eq!(
+            "type65280".parse::<RecordType>().unwrap(),
+            RecordType::Unknown(65280)
+        );
+        "BOGUS".parse::<RecordType>().unwrap_err();
+
+        assert_eq!(RecordType::CAA.to_string(), "CAA");
+        assert_eq!(RecordType::Unknown(65280).to_string(), "TYPE65280");
+        // a hand built Unknown for a registered code is the same type
+        assert_eq!(RecordType::Unknown(1), RecordType::A);
+        assert_eq!(
+            HashSet::from([RecordType::Unknown(1), RecordType::A]).len(),
+            1
+        );
+        assert!(RecordType::A < RecordType::NS);
+        assert!(RecordType::CAA < RecordType::Unknown(65280));
+    }
+
+    #[test]
+    fn test_class_conversions() {
+        assert_eq!(DnsClass::from(3), DnsClass::CH);
+        assert_eq!(u16::from(DnsClass::NONE), 254);
+        assert_eq!("in".parse::<DnsClass>().unwrap(), DnsClass::IN);
+        assert_eq!("CLASS2".parse::<DnsClass>().unwrap(), DnsClass::Unknown(2));
+        assert_eq!(DnsClass::Unknown(2).to_string(), "CLASS2");
+    }
+}
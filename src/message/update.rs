@@ -0,0 +1,216 @@
+// dynamic update messages, RFC 2136
+
+use crate::errors::DnsError;
+
+use super::{
+    builder::MessageBuilder,
+    header::Opcode,
+    name::Name,
+    rdata::RData,
+    rr::ResourceRecord,
+    types::{DnsClass, RecordType},
+    Message,
+};
+
+/// Builds an UPDATE message for a zone. The sections of a query are reused:
+/// the zone is the question, the prerequisites are the answer section and
+/// the updates are the authority section, RFC 2136 2.
+///
+/// Prerequisites and deletions are encoded as records of the ANY and NONE
+/// classes with empty rdata, which `ResourceRecord::parse` reads back as
+/// `RData::Unknown`.
+#[derive(Debug, Clone)]
+pub struct Update {
+    zone: Name,
+    class: DnsClass,
+    prerequisites: Vec<ResourceRecord>,
+    updates: Vec<ResourceRecord>,
+}
+
+impl Update {
+    /// starts an update of the IN class `zone`.
+    pub fn new(zone: Name) -> Self {
+        Self {
+            zone,
+            class: DnsClass::IN,
+            prerequisites: vec![],
+            updates: vec![],
+        }
+    }
+
+    /// sets the class of the zone, IN by default.
+    pub fn class(mut self, class: DnsClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// requires an RRset of type `t` at `name`, whatever its records,
+    /// RFC 2136 2.4.1.
+    pub fn rrset_exists(mut self, name: Name, t: RecordType) -> Self {
+        self.prerequisites.push(meta(name, t, DnsClass::ANY));
+        self
+    }
+
+    /// requires the RRset of `rrset` to hold exactly those records,
+    /// RFC 2136 2.4.2.
+    pub fn rrset_equals(mut self, rrset: impl IntoIterator<Item = ResourceRecord>) -> Self {
+        let class = self.class;
+        self.prerequisites
+            .extend(rrset.into_iter().map(|rr| ResourceRecord {
+                class,
+                ttl: 0,
+                ..rr
+            }));
+        self
+    }
+
+    /// requires that `name` has no RRset of type `t`, RFC 2136 2.4.3.
+    pub fn rrset_does_not_exist(mut self, name: Name, t: RecordType) -> Self {
+        self.prerequisites.push(meta(name, t, DnsClass::NONE));
+        self
+    }
+
+    /// requires `name` to own at least one record, RFC 2136 2.4.4.
+    pub fn name_in_use(mut self, name: Name) -> Self {
+        self.prerequisites
+            .push(meta(name, RecordType::ANY, DnsClass::ANY));
+        self
+    }
+
+    /// requires `name` to own no records, RFC 2136 2.4.5.
+    pub fn name_not_in_use(mut self, name: Name) -> Self {
+        self.prerequisites
+            .push(meta(name, RecordType::ANY, DnsClass::NONE));
+        self
+    }
+
+    /// adds `rr` to its RRset, RFC 2136 2.5.1. The class is set to that of
+    /// the zone.
+    pub fn add_record(mut self, rr: ResourceRecord) -> Self {
+        self.updates.push(ResourceRecord {
+            class: self.class,
+            ..rr
+        });
+        self
+    }
+
+    /// deletes the RRset of type `t` at `name`, RFC 2136 2.5.2.
+    pub fn delete_rrset(mut self, name: Name, t: RecordType) -> Self {
+        self.updates.push(meta(name, t, DnsClass::ANY));
+        self
+    }
+
+    /// deletes every RRset at `name`, RFC 2136 2.5.3.
+    pub fn delete_all(mut self, name: Name) -> Self {
+        self.updates
+            .push(meta(name, RecordType::ANY, DnsClass::ANY));
+        self
+    }
+
+    /// deletes the record matching the name, type and rdata of `rr`,
+    /// RFC 2136 2.5.4.
+    pub fn delete_record(mut self, rr: ResourceRecord) -> Self {
+        self.updates.push(ResourceRecord {
+            class: DnsClass::NONE,
+            ttl: 0,
+            ..rr
+        });
+        self
+    }
+
+    /// Builds the UPDATE message with a random ID.
+    pub fn build(self) -> Result<Message, DnsError> {
+        let mut builder = MessageBuilder::new().opcode(Opcode::Update).question(
+            self.zone,
+            RecordType::SOA,
+            self.class,
+        );
+        for rr in self.prerequisites {
+            builder = builder.answer(rr);
+        }
+        for rr in self.updates {
+            builder = builder.authority(rr);
+        }
+        builder.build()
+    }
+}
+
+/// a record with no TTL or rdata, matching or deleting by name and type.
+fn meta(name: Name, t: RecordType, class: DnsClass) -> ResourceRecord {
+    ResourceRecord {
+        name,
+        t,
+        class,
+        ttl: 0,
+        rdata: RData::Unknown(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::Update;
+    use crate::message::{
+        header::Opcode,
+        name::Name,
+        rdata::RData,
+        rr::ResourceRecord,
+        types::{DnsClass, RecordType},
+        Message,
+    };
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_build_update() {
+        let a = ResourceRecord {
+            name: name("www.example.com"),
+            t: RecordType::A,
+            class: DnsClass::IN,
+            ttl: 300,
+            rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        };
+        let msg = Update::new(name("example.com"))
+            .name_in_use(name("example.com"))
+            .rrset_does_not_exist(name("www.example.com"), RecordType::CNAME)
+            .rrset_equals([a.clone()])
+            .delete_rrset(name("www.example.com"), RecordType::AAAA)
+            .delete_record(a.clone())
+            .add_record(a.clone())
+            .build()
+            .unwrap();
+        assert_eq!(msg.hdr.opcode, Opcode::Update);
+        assert_eq!(msg.qd[0].qname, name("example.com"));
+        assert_eq!(msg.qd[0].qtype, RecordType::SOA);
+
+        let classes = |rrs: &[ResourceRecord]| {
+            rrs.iter()
+                .map(|r| (r.t, r.class, r.ttl))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            classes(&msg.an),
+            [
+                (RecordType::ANY, DnsClass::ANY, 0),
+                (RecordType::CNAME, DnsClass::NONE, 0),
+                (RecordType::A, DnsClass::IN, 0),
+            ]
+        );
+        assert_eq!(
+            classes(&msg.ns),
+            [
+                (RecordType::AAAA, DnsClass::ANY, 0),
+                (RecordType::A, DnsClass::NONE, 0),
+                (RecordType::A, DnsClass::IN, 300),
+            ]
+        );
+
+        // the empty rdata of the meta records survives the wire
+        let mut buf = [0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        assert_eq!(Message::parse(&buf[..n]).unwrap().1, msg);
+    }
+}
@@ -0,0 +1,528 @@
+// borrowing views over a message buffer
+
+use std::fmt;
+
+use crate::errors::DnsError;
+
+use super::{
+    header::{Header, HEADER_LENGTH},
+    label::MAX_LABEL_RESOLVE_DEPTH,
+    name::{Name, MAX_LABEL_LENGTH, MAX_NAME_LENGTH},
+    question::Question,
+    rdata::RData,
+    rr::ResourceRecord,
+    text::write_label,
+    types::{DnsClass, RecordType},
+    Message,
+};
+
+/// A parsed header over a borrowed message buffer. Sections are only walked
+/// when iterated, and nothing is copied out of the buffer until one of the
+/// `to_*` methods is called.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageRef<'a> {
+    buf: &'a [u8],
+    pub hdr: Header,
+}
+
+/// A name inside a message buffer, possibly compressed. The name has been
+/// checked to be well formed when the view is created.
+#[derive(Debug, Clone, Copy)]
+pub struct NameRef<'a> {
+    msg: &'a [u8],
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionRef<'a> {
+    pub qname: NameRef<'a>,
+    pub qtype: RecordType,
+    pub qclass: DnsClass,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+    msg: &'a [u8],
+    rdata_offset: usize,
+    pub name: NameRef<'a>,
+    pub t: RecordType,
+    pub class: DnsClass,
+    pub ttl: u32,
+    pub rdata: &'a [u8],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Parses the header of `buf`. The sections are not checked until they
+    /// are iterated.
+    pub fn parse(buf: &'a [u8]) -> Result<Self, DnsError> {
+        let hdr = Header::parse(buf)?;
+        Ok(Self { buf, hdr })
+    }
+
+    pub fn buf(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    pub fn questions(&self) -> Questions<'a> {
+        Questions {
+            msg: self.buf,
+            offset: HEADER_LENGTH,
+            remaining: self.hdr.qdcount,
+        }
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(0, self.hdr.ancount)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(1, self.hdr.nscount)
+    }
+
+    pub fn additionals(&self) -> Records<'a> {
+        self.records(2, self.hdr.arcount)
+    }
+
+    /// iterates the records of the `section`th record section, skipping over
+    /// the sections before it.
+    fn records(&self, section: usize, count: u16) -> Records<'a> {
+        let counts = [self.hdr.ancount, self.hdr.nscount];
+        let start = skip_questions(self.buf, HEADER_LENGTH, self.hdr.qdcount).and_then(|offset| {
+            counts[..section]
+                .iter()
+                .try_fold(offset, |offset, n| skip_records(self.buf, offset, *n))
+        });
+        match start {
+            Ok(offset) => Records {
+                msg: self.buf,
+                offset,
+                remaining: count,
+                error: None,
+            },
+            Err(e) => Records {
+                msg: self.buf,
+                offset: self.buf.len(),
+                remaining: 0,
+                error: Some(e),
+            },
+        }
+    }
+
+    /// Builds the owned message.
+    pub fn to_message(self) -> Result<Message, DnsError> {
+        Message::from_sections(
+            self.hdr,
+            self.questions()
+                .map(|q| q?.to_question())
+                .collect::<Result<_, _>>()?,
+            self.answers()
+                .map(|r| r?.to_record())
+                .collect::<Result<_, _>>()?,
+            self.authorities()
+                .map(|r| r?.to_record())
+                .collect::<Result<_, _>>()?,
+            self.additionals()
+                .map(|r| r?.to_record())
+                .collect::<Result<_, _>>()?,
+        )
+    }
+}
+
+impl TryFrom<MessageRef<'_>> for Message {
+    type Error = DnsError;
+
+    fn try_from(value: MessageRef<'_>) -> Result<Self, Self::Error> {
+        value.to_message()
+    }
+}
+
+pub(crate) fn skip_questions(msg: &[u8], mut offset: usize, count: u16) -> Result<usize, DnsError> {
+    for _ in 0..count {
+        let (read, _) = QuestionRef::parse(msg, offset)?;
+        offset += read;
+    }
+    Ok(offset)
+}
+
+pub(crate) fn skip_records(msg: &[u8], mut offset: usize, count: u16) -> Result<usize, DnsError> {
+    for _ in 0..count {
+        let (read, _) = RecordRef::parse(msg, offset)?;
+        offset += read;
+    }
+    Ok(offset)
+}
+
+pub struct Questions<'a> {
+    msg: &'a [u8],
+    offset: usize,
+    remaining: u16,
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = Result<QuestionRef<'a>, DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        match QuestionRef::parse(self.msg, self.offset) {
+            Ok((read, q)) => {
+                self.offset += read;
+                self.remaining -= 1;
+                Some(Ok(q))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+pub struct Records<'a> {
+    msg: &'a [u8],
+    offset: usize,
+    remaining: u16,
+    error: Option<DnsError>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<RecordRef<'a>, DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        if self.remaining == 0 {
+            return None;
+        }
+        match RecordRef::parse(self.msg, self.offset) {
+            Ok((read, r)) => {
+                self.offset += read;
+                self.remaining -= 1;
+                Some(Ok(r))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a> NameRef<'a> {
+    /// Checks the name at `offset` in `msg`, returning the number of bytes it
+    /// occupies at `offset`.
+    pub fn parse(msg: &'a [u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let mut pos = offset;
+        let mut start = offset;
+        let mut read = None;
+        let mut hops = 0;
+        let mut length = 1;
+        loop {
+            let truncated = |needed| DnsError::Truncated {
+                needed,
+                available: msg.len().saturating_sub(pos),
+                offset: pos,
+            };
+            let len = *msg.get(pos).ok_or_else(|| truncated(1))? as usize;
+            if len & 0xC0 == 0xC0 {
+                let low = *msg.get(pos + 1).ok_or_else(|| truncated(2))? as usize;
+                let target = (len & 0x3F) << 8 | low;
+                if target < HEADER_LENGTH || target >= pos {
+                    return Err(DnsError::InvalidPointer {
+                        target,
+                        offset: pos,
+                    });
+                }
+                if read.is_none() {
+                    read = Some(pos + 2 - offset);
+                }
+                hops += 1;
+                // each pointer must lead before the labels read so far
+                if hops > MAX_LABEL_RESOLVE_DEPTH || target >= start {
+                    return Err(DnsError::PointerLoop { offset });
+                }
+                start = target;
+                pos = target;
+                continue;
+            }
+            // the 0x40 and 0x80 prefixes would make the length exceed 63 octets
+            if len > MAX_LABEL_LENGTH {
+                return Err(DnsError::LabelTooLong { len, offset: pos });
+            }
+            if len == 0 {
+                if read.is_none() {
+                    read = Some(pos + 1 - offset);
+                }
+                break;
+            }
+            if pos + 1 + len > msg.len() {
+                return Err(truncated(len + 1));
+            }
+            length += len + 1;
+            if length > MAX_NAME_LENGTH {
+                return Err(DnsError::NameTooLong {
+                    len: length,
+                    offset,
+                });
+            }
+            pos += len + 1;
+        }
+        Ok((read.unwrap_or_default(), Self { msg, offset }))
+    }
+
+    /// iterates the raw label bytes, leftmost first.
+    pub fn labels(&self) -> LabelIter<'a> {
+        LabelIter {
+            msg: self.msg,
+            pos: self.offset,
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// Builds the owned name.
+    pub fn to_name(self) -> Result<Name, DnsError> {
+        Name::parse(self.msg, self.offset).map(|(_, name)| name)
+    }
+}
+
+impl PartialEq<Name> for NameRef<'_> {
+    fn eq(&self, other: &Name) -> bool {
+        let mut labels = self.labels();
+        for label in other.labels() {
+            match labels.next() {
+                Some(l) if l.eq_ignore_ascii_case(label) => continue,
+                _ => return false,
+            }
+        }
+        labels.next().is_none()
+    }
+}
+
+impl fmt::Display for NameRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for label in self.labels() {
+            write_label(f, label)?;
+            write!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the labels of a `NameRef`, following pointers.
+pub struct LabelIter<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for LabelIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the name was checked by `NameRef::parse`, so pointers terminate
+        loop {
+            let len = *self.msg.get(self.pos)? as usize;
+            if len & 0xC0 == 0xC0 {
+                let low = *self.msg.get(self.pos + 1)? as usize;
+                self.pos = (len & 0x3F) << 8 | low;
+                continue;
+            }
+            if len == 0 {
+                return None;
+            }
+            let label = self.msg.get(self.pos + 1..self.pos + 1 + len)?;
+            self.pos += len + 1;
+            return Some(label);
+        }
+    }
+}
+
+impl<'a> QuestionRef<'a> {
+    pub fn parse(msg: &'a [u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let (read, qname) = NameRef::parse(msg, offset)?;
+        let b = msg
+            .get(offset + read..offset + read + 4)
+            .ok_or_else(|| DnsError::Truncated {
+                needed: 4,
+                available: msg.len().saturating_sub(offset + read),
+                offset: offset + read,
+            })?;
+        Ok((
+            read + 4,
+            Self {
+                qname,
+                qtype: u16::from_be_bytes([b[0], b[1]]).into(),
+                qclass: u16::from_be_bytes([b[2], b[3]]).into(),
+            },
+        ))
+    }
+
+    pub fn to_question(self) -> Result<Question, DnsError> {
+        Ok(Question {
+            qname: self.qname.to_name()?,
+            qtype: self.qtype,
+            qclass: self.qclass,
+        })
+    }
+}
+
+impl<'a> RecordRef<'a> {
+    pub fn parse(msg: &'a [u8], offset: usize) -> Result<(usize, Self), DnsError> {
+        let (read, name) = NameRef::parse(msg, offset)?;
+        let truncated = |needed, offset: usize| DnsError::Truncated {
+            needed,
+            available: msg.len().saturating_sub(offset),
+            offset,
+        };
+        let b = msg
+            .get(offset + read..offset + read + 10)
+            .ok_or_else(|| truncated(10, offset + read))?;
+        let rdlength = u16::from_be_bytes([b[8], b[9]]) as usize;
+        let rdata_offset = offset + read + 10;
+        let rdata = msg
+            .get(rdata_offset..rdata_offset + rdlength)
+            .ok_or_else(|| truncated(rdlength, rdata_offset))?;
+        Ok((
+            read + 10 + rdlength,
+            Self {
+                msg,
+                rdata_offset,
+                name,
+                t: u16::from_be_bytes([b[0], b[1]]).into(),
+                class: u16::from_be_bytes([b[2], b[3]]).into(),
+                ttl: u32::from_be_bytes([b[4], b[5], b[6], b[7]]),
+                rdata,
+            },
+        ))
+    }
+
+    /// Decodes the rdata, resolving names against the whole message.
+    pub fn parse_rdata(&self) -> Result<RData, DnsError> {
+        RData::parse_for_class(
+            self.msg,
+            self.rdata_offset,
+            self.rdata.len(),
+            self.t,
+            self.class,
+        )
+    }
+
+    pub fn to_record(self) -> Result<ResourceRecord, DnsError> {
+        Ok(ResourceRecord {
+            name: self.name.to_name()?,
+            t: self.t,
+            class: self.class,
+            ttl: self.ttl,
+            rdata: self.parse_rdata()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::MessageRef;
+    use crate::errors::DnsError;
+    use crate::message::{
+        builder::MessageBuilder,
+        name::Name,
+        rdata::RData,
+        rr::ResourceRecord,
+        types::{DnsClass, RecordType},
+        Message,
+    };
+
+    fn response() -> Vec<u8> {
+        let qname: Name = "www.example.com".parse().unwrap();
+        let msg = MessageBuilder::new()
+            .response(true)
+            .recursion_desired(true)
+            .question(qname.clone(), RecordType::A, DnsClass::IN)
+            .answer(ResourceRecord {
+                name: qname,
+                t: RecordType::CNAME,
+                class: DnsClass::IN,
+                ttl: 300,
+                rdata: RData::CNAME("web.example.com".parse().unwrap()),
+            })
+            .answer(ResourceRecord {
+                name: "web.example.com".parse().unwrap(),
+                t: RecordType::A,
+                class: DnsClass::IN,
+                ttl: 300,
+                rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            })
+            .build()
+            .unwrap();
+        let mut buf = vec![0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    #[test]
+    fn test_iterate_sections() {
+        let buf = response();
+        let view = MessageRef::parse(&buf).unwrap();
+
+        let q = view.questions().next().unwrap().unwrap();
+        assert_eq!(q.qname.to_string(), "www.example.com.");
+        assert_eq!(q.qtype, RecordType::A);
+
+        let answers = view.answers().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(answers.len(), 2);
+        // the second owner is compressed against the CNAME target
+        assert!(answers[1].name == "WEB.example.com".parse::<Name>().unwrap());
+        assert_eq!(answers[1].rdata, &[192, 0, 2, 1]);
+        assert_eq!(
+            answers[0].parse_rdata().unwrap(),
+            RData::CNAME("web.example.com".parse().unwrap())
+        );
+
+        assert_eq!(view.authorities().count(), 0);
+        assert_eq!(view.additionals().count(), 0);
+    }
+
+    #[test]
+    fn test_to_message() {
+        let buf = response();
+        let (_, owned) = Message::parse(&buf).unwrap();
+        let view = MessageRef::parse(&buf).unwrap();
+        assert_eq!(Message::try_from(view).unwrap(), owned);
+    }
+
+    #[test]
+    fn test_truncated_and_looping() {
+        let buf = response();
+        let view = MessageRef::parse(&buf[..buf.len() - 2]).unwrap();
+        let answers = view.answers().collect::<Vec<_>>();
+        assert_eq!(answers.len(), 2);
+        assert!(matches!(answers[1], Err(DnsError::Truncated { .. })));
+        view.to_message().unwrap_err();
+
+        // a question whose name points at itself
+        let mut buf = response()[..12].to_vec();
+        buf[5] = 1;
+        buf.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+        let view = MessageRef::parse(&buf).unwrap();
+        assert_eq!(
+            view.questions().next().unwrap().unwrap_err(),
+            DnsError::InvalidPointer {
+                target: 12,
+                offset: 12
+            }
+        );
+        // sections after a broken one report the error as well
+        buf[7] = 1;
+        let view = MessageRef::parse(&buf).unwrap();
+        assert!(view.answers().next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,556 @@
+// zone (master) files, RFC 1035 5
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::DnsError;
+
+use super::{
+    name::Name,
+    rdata::RData,
+    rr::ResourceRecord,
+    text::{parse_name, unescape, Entry, Fields, Lexer, Token},
+    types::{DnsClass, RecordType},
+};
+
+/// how deeply $INCLUDE may nest, to stop include loops.
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// how many records one $GENERATE may expand to, so that a single line
+/// cannot exhaust memory.
+pub const MAX_GENERATE_RECORDS: u32 = 65536;
+
+/// the widest `${offset,width,base}` field, no text in a record can be
+/// longer.
+const MAX_GENERATE_WIDTH: usize = 255;
+
+/// Parses the records of a zone file held in `text`. Relative names are
+/// completed with `origin` until a `$ORIGIN` directive changes it, and
+/// relative `$INCLUDE` paths are resolved against the working directory.
+pub fn parse(text: &str, origin: &Name) -> Result<Vec<ResourceRecord>, DnsError> {
+    let mut parser = Parser::new(origin.clone(), PathBuf::new());
+    parser.parse(text)?;
+    Ok(parser.records)
+}
+
+/// Reads and parses the zone file at `path`. Relative `$INCLUDE` paths are
+/// resolved against the directory of the including file.
+pub fn load(path: &Path, origin: &Name) -> Result<Vec<ResourceRecord>, DnsError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| DnsError::Generic(format!("zone: cannot read {}: {}", path.display(), e)))?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut parser = Parser::new(origin.clone(), dir);
+    parser.parse(&text)?;
+    Ok(parser.records)
+}
+
+struct Parser {
+    origin: Name,
+    /// set by $TTL, RFC 2308 4.
+    default_ttl: Option<u32>,
+    last_owner: Option<Name>,
+    last_ttl: Option<u32>,
+    last_class: DnsClass,
+    dir: PathBuf,
+    depth: usize,
+    records: Vec<ResourceRecord>,
+}
+
+impl Parser {
+    fn new(origin: Name, dir: PathBuf) -> Self {
+        Self {
+            origin,
+            default_ttl: None,
+            last_owner: None,
+            last_ttl: None,
+            last_class: DnsClass::IN,
+            dir,
+            depth: 0,
+            records: vec![],
+        }
+    }
+
+    fn parse(&mut self, text: &str) -> Result<(), DnsError> {
+        let mut lexer = Lexer::new(text);
+        while let Some(entry) = lexer.next_entry()? {
+            let first = &entry.tokens[0];
+            if !entry.blank_owner && !first.quoted && first.text.starts_with('$') {
+                self.directive(&entry)?;
+                continue;
+            }
+            let (owner, rest) = if entry.blank_owner {
+                let owner = self
+                    .last_owner
+                    .clone()
+                    .ok_or_else(|| first.error("no previous owner name".to_string()))?;
+                (owner, &entry.tokens[..])
+            } else {
+                (parse_name(first, &self.origin)?, &entry.tokens[1..])
+            };
+            self.record(owner, first, rest)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, entry: &Entry) -> Result<(), DnsError> {
+        let directive = &entry.tokens[0];
+        let mut fields = Fields::new(&entry.tokens[1..], directive.line, directive.column);
+        match directive.text.to_ascii_uppercase().as_str() {
+            "$ORIGIN" => {
+                self.origin = fields.name("origin", &self.origin)?;
+                fields.finish()
+            }
+            "$TTL" => {
+                self.default_ttl = Some(fields.ttl("TTL")?);
+                fields.finish()
+            }
+            "$INCLUDE" => {
+                let file = fields.next("file name")?;
+                let origin = match fields.peek() {
+                    Some(_) => fields.name("origin", &self.origin)?,
+                    None => self.origin.clone(),
+                };
+                fields.finish()?;
+                self.include(file, origin)
+            }
+            "$GENERATE" => self.generate(directive, &mut fields),
+            _ => Err(directive.error(format!("unknown directive {}", directive.text))),
+        }
+    }
+
+    /// Parses the included file with its own origin. The origin and owner
+    /// name revert once it ends, RFC 1035 5.1.
+    fn include(&mut self, file: &Token, origin: Name) -> Result<(), DnsError> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(file.error("$INCLUDE nested too deeply".to_string()));
+        }
+        let name = String::from_utf8(unescape(file)?)
+            .map_err(|_| file.error("file name is not valid UTF-8".to_string()))?;
+        let path = self.dir.join(name);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| file.error(format!("cannot read {}: {}", path.display(), e)))?;
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut parser = Parser::new(origin, dir);
+        parser.default_ttl = self.default_ttl;
+        parser.last_ttl = self.last_ttl;
+        parser.last_class = self.last_class;
+        parser.depth = self.depth + 1;
+        parser.parse(&text).map_err(|e| match e {
+            DnsError::SyntaxError {
+                line,
+                column,
+                message,
+            } => DnsError::SyntaxError {
+                line,
+                column,
+                message: format!("{}: {}", path.display(), message),
+            },
+            e => e,
+        })?;
+        self.records.append(&mut parser.records);
+        Ok(())
+    }
+
+    /// Expands `$GENERATE range lhs [ttl] [class] type rhs`, the BIND
+    /// extension for runs of similar records.
+    fn generate(&mut self, directive: &Token, fields: &mut Fields<'_>) -> Result<(), DnsError> {
+        let range = fields.next("range")?;
+        let (start, stop, step) = parse_range(range)?;
+        let lhs = fields.next("owner template")?;
+        let rest = fields.rest();
+        if rest.is_empty() {
+            return Err(lhs.error("missing type".to_string()));
+        }
+
+        let mut i = start;
+        while i <= stop {
+            let owner = parse_name(&expand(lhs, i)?, &self.origin)?;
+            let rest = rest
+                .iter()
+                .map(|token| expand(token, i))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.record(owner, directive, &rest)?;
+            i = match i.checked_add(step) {
+                Some(i) => i,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Parses `[ttl] [class] type rdata` for `owner`. `at` locates the entry
+    /// for errors about missing fields.
+    fn record(&mut self, owner: Name, at: &Token, tokens: &[Token]) -> Result<(), DnsError> {
+        let mut fields = Fields::new(tokens, at.line, at.column);
+        let mut ttl = None;
+        let mut class = None;
+        // the TTL and class may appear in either order
+        for _ in 0..2 {
+            let token = match fields.peek() {
+                Some(token) if !token.quoted => token,
+                _ => break,
+            };
+            if ttl.is_none() && token.text.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(fields.ttl("TTL")?);
+            } else if let (None, Ok(c)) = (class, token.text.parse::<DnsClass>()) {
+                fields.next("class")?;
+                class = Some(c);
+            } else {
+                break;
+            }
+        }
+        let t: RecordType = fields.parse("type")?;
+        let rdata = RData::from_fields(t, &mut fields, &self.origin)?;
+
+        let ttl = match (ttl.or(self.default_ttl).or(self.last_ttl), &rdata) {
+            (Some(ttl), _) => ttl,
+            // with neither TTL, BIND falls back to the SOA minimum
+            (None, RData::SOA(soa)) => soa.minimum,
+            (None, _) => return Err(at.error("no TTL specified".to_string())),
+        };
+        let class = class.unwrap_or(self.last_class);
+
+        self.last_owner = Some(owner.clone());
+        self.last_ttl = Some(ttl);
+        self.last_class = class;
+        self.records.push(ResourceRecord {
+            name: owner,
+            t,
+            class,
+            ttl,
+            rdata,
+        });
+        Ok(())
+    }
+}
+
+/// parses `start-stop[/step]`, of at most `MAX_GENERATE_RECORDS` values.
+fn parse_range(token: &Token) -> Result<(u32, u32, u32), DnsError> {
+    let parse = |s: &str| {
+        let (range, step) = match s.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok()?),
+            None => (s, 1),
+        };
+        let (start, stop) = range.split_once('-')?;
+        let (start, stop): (u32, u32) = (start.parse().ok()?, stop.parse().ok()?);
+        if start > stop || step == 0 {
+            return None;
+        }
+        Some((start, stop, step))
+    };
+    let (start, stop, step) =
+        parse(&token.text).ok_or_else(|| token.error(format!("invalid range: {}", token.text)))?;
+    if (stop - start) / step >= MAX_GENERATE_RECORDS {
+        return Err(token.error(format!(
+            "range {} generates more than {} records",
+            token.text, MAX_GENERATE_RECORDS
+        )));
+    }
+    Ok((start, stop, step))
+}
+
+/// Substitutes `i` into a $GENERATE template. `$` is replaced by `i`, and
+/// `${offset,width,base}` by `i + offset` in base d, o, x or X padded to
+/// `width`. `\$` is a literal dollar sign.
+fn expand(token: &Token, i: u32) -> Result<Token, DnsError> {
+    let invalid = || token.error(format!("invalid template: {}", token.text));
+    let mut text = String::new();
+    let mut chars = token.text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('$') => text.push('$'),
+                Some(c) => {
+                    text.push('\\');
+                    text.push(c);
+                }
+                None => return Err(invalid()),
+            },
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(invalid()),
+                    }
+                }
+                let mut parts = spec.split(',');
+                let offset: i64 = match parts.next() {
+                    Some("") | None => 0,
+                    Some(offset) => offset.parse().map_err(|_| invalid())?,
+                };
+                let width: usize = match parts.next() {
+                    Some(width) => width.parse().map_err(|_| invalid())?,
+                    None => 0,
+                };
+                if width > MAX_GENERATE_WIDTH {
+                    return Err(token.error(format!(
+                        "template width {} is over {}",
+                        width, MAX_GENERATE_WIDTH
+                    )));
+                }
+                let value = i as i64 + offset;
+                if value < 0 {
+                    return Err(token.error(format!("template value {} is negative", value)));
+                }
+                match parts.next().unwrap_or("d") {
+                    "d" => text.push_str(&format!("{:0width$}", value, width = width)),
+                    "o" => text.push_str(&format!("{:0width$o}", value, width = width)),
+                    "x" => text.push_str(&format!("{:0width$x}", value, width = width)),
+                    "X" => text.push_str(&format!("{:0width$X}", value, width = width)),
+                    _ => return Err(invalid()),
+                }
+                if parts.next().is_some() {
+                    return Err(invalid());
+                }
+            }
+            '$' => text.push_str(&i.to_string()),
+            c => text.push(c),
+        }
+    }
+    Ok(Token {
+        text,
+        ..token.clone()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    use super::{load, parse};
+    use crate::errors::DnsError;
+    use crate::message::{
+        name::Name,
+        rdata::{Mx, RData, Soa},
+        types::{DnsClass, RecordType},
+    };
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        let text = r#"
+$ORIGIN example.com.
+$TTL 1h
+@   IN  SOA ns1 hostmaster (
+            2024010101 ; serial
+            2h 1h 2w
+            300 )
+        NS  ns1
+        MX  10 mail.example.org.
+ns1 60  A   192.0.2.1
+        AAAA 2001:db8::1
+www IN 300 CNAME @
+txt     TXT "v=spf1 -all" two\ words "a\"b\\c\065"
+$ORIGIN sub
+host    A   192.0.2.2
+"#;
+        let records = parse(text, &Name::root()).unwrap();
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| (r.name.to_string(), r.ttl, r.class, r.t))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::SOA
+                ),
+                (
+                    "example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::NS
+                ),
+                (
+                    "example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::MX
+                ),
+                (
+                    "ns1.example.com.".to_string(),
+                    60,
+                    DnsClass::IN,
+                    RecordType::A
+                ),
+                (
+                    "ns1.example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::AAAA
+                ),
+                (
+                    "www.example.com.".to_string(),
+                    300,
+                    DnsClass::IN,
+                    RecordType::CNAME
+                ),
+                (
+                    "txt.example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::TXT
+                ),
+                (
+                    "host.sub.example.com.".to_string(),
+                    3600,
+                    DnsClass::IN,
+                    RecordType::A
+                ),
+            ]
+        );
+        assert_eq!(
+            records[0].rdata,
+            RData::SOA(Soa {
+                mname: name("ns1.example.com"),
+                rname: name("hostmaster.example.com"),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            })
+        );
+        assert_eq!(
+            records[2].rdata,
+            RData::MX(Mx {
+                preference: 10,
+                exchange: name("mail.example.org"),
+            })
+        );
+        assert_eq!(
+            records[4].rdata,
+            RData::AAAA("2001:db8::1".parse::<Ipv6Addr>().unwrap())
+        );
+        assert_eq!(records[5].rdata, RData::CNAME(name("example.com")));
+        assert_eq!(
+            records[6].rdata,
+            RData::TXT(vec![
+                b"v=spf1 -all".to_vec(),
+                b"two words".to_vec(),
+                b"a\"b\\cA".to_vec()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let text = "$GENERATE 1-3 host-$ 60 A 10.0.0.${10}\n\
+                    $GENERATE 0-32/16 ${0,3,x} PTR host\\$.example.com.\n";
+        let records = parse(text, &name("example.com")).unwrap();
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| format!("{} {}", r.name, r.rdata))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "host-1.example.com. 10.0.0.11",
+                "host-2.example.com. 10.0.0.12",
+                "host-3.example.com. 10.0.0.13",
+                r"000.example.com. host\$.example.com.",
+                r"010.example.com. host\$.example.com.",
+                r"020.example.com. host\$.example.com.",
+            ]
+        );
+        assert_eq!(records[5].ttl, 60);
+
+        assert_eq!(
+            parse(
+                "$GENERATE 0-4294967295 $ A 10.0.0.1\n",
+                &name("example.com")
+            )
+            .unwrap_err(),
+            DnsError::SyntaxError {
+                line: 1,
+                column: 11,
+                message: "range 0-4294967295 generates more than 65536 records".to_string(),
+            }
+        );
+        let text = "$GENERATE 0-131070/2 $ 60 A 10.0.0.1\n";
+        assert_eq!(parse(text, &name("example.com")).unwrap().len(), 65536);
+        assert_eq!(
+            parse(
+                "$GENERATE 1-4 ${0,70000,d} A 10.0.0.1\n",
+                &name("example.com")
+            )
+            .unwrap_err(),
+            DnsError::SyntaxError {
+                line: 1,
+                column: 15,
+                message: "template width 70000 is over 255".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("zone-test-{}", rand::random::<u32>()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("main.zone"),
+            "$TTL 300\n@ NS ns\n$INCLUDE hosts.zone hosts\nafter A 192.0.2.3\n",
+        )
+        .unwrap();
+        fs::write(dir.join("hosts.zone"), "a A 192.0.2.1\n  A 192.0.2.2\n").unwrap();
+        fs::write(dir.join("bad.zone"), "\n$INCLUDE broken.zone\n").unwrap();
+        fs::write(dir.join("broken.zone"), "x 60 A 192.0.2\n").unwrap();
+
+        let records = load(&dir.join("main.zone"), &name("example.com")).unwrap();
+        let summary: Vec<_> = records.iter().map(|r| r.name.to_string()).collect();
+        assert_eq!(
+            summary,
+            [
+                "example.com.",
+                "a.hosts.example.com.",
+                "a.hosts.example.com.",
+                "after.example.com.",
+            ]
+        );
+        assert_eq!(records[3].rdata, RData::A(Ipv4Addr::new(192, 0, 2, 3)));
+
+        assert_eq!(
+            load(&dir.join("bad.zone"), &name("example.com")).unwrap_err(),
+            DnsError::SyntaxError {
+                line: 1,
+                column: 8,
+                message: format!(
+                    "{}: invalid IPv4 address: 192.0.2",
+                    dir.join("broken.zone").display()
+                )
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let origin = name("example.com");
+        let position = |text: &str| match parse(text, &origin).unwrap_err() {
+            DnsError::SyntaxError { line, column, .. } => (line, column),
+            e => panic!("unexpected error {:?}", e),
+        };
+        assert_eq!(position("  A 192.0.2.1\n"), (1, 3));
+        assert_eq!(position("a 60 A 192.0.2.1\nb 60 MX 10\n"), (2, 9));
+        assert_eq!(position("a 60 BOGUS 1\n"), (1, 6));
+        assert_eq!(position("a A 192.0.2.1\n"), (1, 1));
+        assert_eq!(position("a 60 A 192.0.2.1 (\n"), (1, 18));
+        assert_eq!(position("$TTL 60\na TXT \"abc\n"), (2, 7));
+        assert_eq!(position("$BOGUS\n"), (1, 1));
+    }
+}
@@ -0,0 +1,1010 @@
+// iterative resolution starting at a root server, or forwarding to an
+// upstream resolver
+
+use std::{
+    collections::HashMap,
+    io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    errors::{DnsError, ResolveError},
+    message::{
+        edns::Edns,
+        header::ResponseCode,
+        name::Name,
+        rdata::RData,
+        tsig::TsigKey,
+        types::{DnsClass, RecordType},
+        update::Update,
+        Message,
+    },
+    transport::{Transport, UdpTransport},
+    validator::{root_trust_anchors, Security, Validator, ZoneCuts},
+};
+
+/// a.root-servers.net
+pub const ROOT_SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4)), 53);
+/// referrals followed from the root to the servers of a name.
+const MAX_REFERRAL_DEPTH: usize = 16;
+/// how deeply the lookups of name server addresses without glue may nest.
+const MAX_NS_LOOKUP_DEPTH: usize = 4;
+/// queries sent for a single lookup, over all the referrals and name server
+/// lookups it takes.
+const MAX_QUERIES: usize = 64;
+const MAX_CNAME_CHAIN: usize = 8;
+/// how long the keys found while validating are kept, so that key rollovers
+/// are picked up.
+const ZONE_CUTS_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// An iterative resolver. Each lookup starts at the root server and follows
+/// referrals until a server answers authoritatively. Queries are sent with
+/// `T`, UDP falling back to TCP by default. Name servers are only reached
+/// over IPv4, through their A records.
+///
+/// With `forward` the resolver is a stub instead: every lookup is a single
+/// recursive query to one upstream resolver, which is how the encrypted
+/// transports are meant to be used.
+///
+/// With `dnssec` every lookup is validated, and the zone keys found along
+/// the way are shared by all lookups.
+#[derive(Debug)]
+pub struct Resolver<T = UdpTransport> {
+    transport: T,
+    root: SocketAddr,
+    upstream: Option<SocketAddr>,
+    dnssec: bool,
+    tsig: Option<TsigKey>,
+    cuts: Mutex<(Instant, ZoneCuts)>,
+}
+
+impl Resolver<UdpTransport> {
+    /// sends queries over UDP, starting lookups at `ROOT_SERVER`.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self::with_transport(UdpTransport::new()?))
+    }
+
+    /// how long to wait for each response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.transport = self.transport.timeout(timeout);
+        self
+    }
+}
+
+impl<T: Transport> Resolver<T> {
+    /// sends queries with `transport`, starting lookups at `ROOT_SERVER`.
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport,
+            root: ROOT_SERVER,
+            upstream: None,
+            dnssec: false,
+            tsig: None,
+            cuts: Mutex::new((Instant::now(), ZoneCuts::default())),
+        }
+    }
+
+    /// starts lookups at `root` instead of `ROOT_SERVER`.
+    pub fn root(mut self, root: SocketAddr) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// Sends every lookup to the recursive resolver `upstream` with RD set
+    /// and returns its response, instead of following referrals from the
+    /// root, RFC 1034 5.3.1.
+    pub fn forward(mut self, upstream: SocketAddr) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// Sets the DO bit on queries so that responses carry their DNSSEC
+    /// records, and validates every lookup against the root trust anchors.
+    /// Lookups whose answers are bogus fail with `ResolveError::Bogus`.
+    pub fn dnssec(mut self, dnssec: bool) -> Self {
+        self.dnssec = dnssec;
+        self
+    }
+
+    /// signs updates with `key` and requires their responses to be signed
+    /// with it too.
+    pub fn tsig(mut self, key: TsigKey) -> Self {
+        self.tsig = Some(key);
+        self
+    }
+
+    /// Resolves the `t` records of `name`, returning the authoritative
+    /// response. Negative responses are returned as well, with an empty
+    /// answer section and the NXDOMAIN or NOERROR rcode.
+    pub fn lookup(&self, name: &Name, t: RecordType) -> Result<Message, ResolveError> {
+        if self.dnssec {
+            return self.lookup_secure(name, t).map(|(msg, _)| msg);
+        }
+        self.lookup_with(name, t, false)
+    }
+
+    /// Resolves the `t` records of `name` with the DO bit set and validates
+    /// the response, returning it with its security status. Bogus responses
+    /// are an error.
+    pub fn lookup_secure(
+        &self,
+        name: &Name,
+        t: RecordType,
+    ) -> Result<(Message, Security), ResolveError> {
+        let msg = self.lookup_with(name, t, true)?;
+        match self.validate(&msg) {
+            Security::Bogus => Err(ResolveError::Bogus {
+                name: name.clone(),
+                rtype: t,
+            }),
+            security => Ok((msg, security)),
+        }
+    }
+
+    /// the IPv4 addresses of `name`, following CNAMEs.
+    pub fn lookup_ipv4(&self, name: &Name) -> Result<Vec<Ipv4Addr>, ResolveError> {
+        self.lookup_data(name, RecordType::A, |rdata| match rdata {
+            RData::A(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    /// the IPv6 addresses of `name`, following CNAMEs.
+    pub fn lookup_ipv6(&self, name: &Name) -> Result<Vec<Ipv6Addr>, ResolveError> {
+        self.lookup_data(name, RecordType::AAAA, |rdata| match rdata {
+            RData::AAAA(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    /// the IPv4 addresses of `name` followed by its IPv6 addresses.
+    pub fn lookup_ip(&self, name: &Name) -> Result<Vec<IpAddr>, ResolveError> {
+        let mut addrs = self
+            .lookup_ipv4(name)?
+            .into_iter()
+            .map(IpAddr::V4)
+            .collect::<Vec<_>>();
+        addrs.extend(self.lookup_ipv6(name)?.into_iter().map(IpAddr::V6));
+        Ok(addrs)
+    }
+
+    /// Checks the DNSSEC status of `response` against the root trust
+    /// anchors, looking up the keys that earlier lookups have not found.
+    pub fn validate(&self, response: &Message) -> Security {
+        // the cache is taken rather than locked for the whole validation, so
+        // that concurrent validations only lose what the other one found
+        let cuts = {
+            let mut cached = self.cuts.lock().unwrap_or_else(PoisonError::into_inner);
+            if cached.0.elapsed() > ZONE_CUTS_LIFETIME {
+                *cached = (Instant::now(), ZoneCuts::default());
+            }
+            mem::take(&mut cached.1)
+        };
+        let mut validator = Validator::new(root_trust_anchors(), |name: &Name, t| {
+            self.lookup_with(name, t, true)
+                .map_err(|e| DnsError::Generic(e.to_string()))
+        })
+        .with_cuts(cuts);
+        let security = validator.validate(response);
+        self.cuts.lock().unwrap_or_else(PoisonError::into_inner).1 = validator.into_cuts();
+        security
+    }
+
+    /// Sends `update` to `server`, which should be the primary server of the
+    /// zone, returning the response if the update was applied.
+    pub fn update(&self, server: SocketAddr, update: Update) -> Result<Message, ResolveError> {
+        let msg = update.build().map_err(ResolveError::Query)?;
+        let response = match &self.tsig {
+            Some(key) => {
+                let mut session = key.session();
+                let signed = session
+                    .sign(&msg, unix_time())
+                    .map_err(ResolveError::Query)?;
+                let mut buf = vec![0u8; u16::MAX as usize];
+                let n = signed.write(&mut buf).map_err(ResolveError::Query)?;
+                let wire = self.transport.send(&buf[..n], server)?;
+                session
+                    .verify(&wire, unix_time())
+                    .map_err(|error| ResolveError::InvalidResponse { server, error })?
+            }
+            None => self.transport.exchange(&msg, server)?,
+        };
+        match response.rcode() {
+            ResponseCode::NoError => Ok(response),
+            rcode @ (ResponseCode::YxDomain
+            | ResponseCode::YxRrset
+            | ResponseCode::NameError
+            | ResponseCode::NxRrset) => Err(ResolveError::PrerequisiteFailed { server, rcode }),
+            rcode @ (ResponseCode::Refused | ResponseCode::NotAuth | ResponseCode::NotZone) => {
+                Err(ResolveError::UpdateRefused { server, rcode })
+            }
+            ResponseCode::ServerFailure => Err(ResolveError::ServerFailure { server }),
+            rcode => Err(ResolveError::ErrorResponse { server, rcode }),
+        }
+    }
+
+    /// Looks up `name` and extracts the `t` records of the answer, restarting
+    /// at the target of a CNAME answer.
+    fn lookup_data<D>(
+        &self,
+        name: &Name,
+        t: RecordType,
+        extract: impl Fn(&RData) -> Option<D>,
+    ) -> Result<Vec<D>, ResolveError> {
+        let mut name = name.clone();
+        for _ in 0..=MAX_CNAME_CHAIN {
+            let msg = self.lookup(&name, t)?;
+            if msg.rcode() == ResponseCode::NameError {
+                return Err(ResolveError::NoSuchName { name });
+            }
+            // servers usually include the records the CNAME chain leads to
+            let (data, end) = chase(&msg, &name, t)?;
+            if !data.is_empty() || end == name {
+                return Ok(data.into_iter().filter_map(&extract).collect());
+            }
+            name = end;
+        }
+        Err(ResolveError::CnameChainTooLong { name })
+    }
+
+    /// Looks up `name` through the upstream resolver if there is one, or
+    /// iteratively from the root.
+    fn lookup_with(
+        &self,
+        name: &Name,
+        t: RecordType,
+        dnssec: bool,
+    ) -> Result<Message, ResolveError> {
+        let Some(server) = self.upstream else {
+            let mut iteration = Iteration {
+                glue: HashMap::new(),
+                queries: 0,
+                nesting: 0,
+            };
+            return self.resolve(0, name, t, self.root, &mut iteration, dnssec);
+        };
+        let msg = self.query(name, t, server, dnssec, true)?;
+        match msg.rcode() {
+            ResponseCode::NoError | ResponseCode::NameError => Ok(msg),
+            ResponseCode::ServerFailure => Err(ResolveError::ServerFailure { server }),
+            rcode => Err(ResolveError::ErrorResponse { server, rcode }),
+        }
+    }
+
+    fn query(
+        &self,
+        name: &Name,
+        t: RecordType,
+        server: SocketAddr,
+        dnssec: bool,
+        recursion: bool,
+    ) -> Result<Message, ResolveError> {
+        let mut edns = Edns::default();
+        edns.set_dnssec_ok(dnssec);
+        let query = Message::new_query(name, t, DnsClass::IN, recursion, Some(edns))
+            .map_err(ResolveError::Query)?;
+        self.transport.exchange(&query, server)
+    }
+
+    /// Follows referrals for `name` starting at `server`, returning the first
+    /// answer or authoritative negative response. When every referral fails
+    /// the last failure is returned. `depth` counts the referrals followed
+    /// so far.
+    fn resolve(
+        &self,
+        depth: usize,
+        name: &Name,
+        t: RecordType,
+        server: SocketAddr,
+        iteration: &mut Iteration,
+        dnssec: bool,
+    ) -> Result<Message, ResolveError> {
+        if depth > MAX_REFERRAL_DEPTH || iteration.queries >= MAX_QUERIES {
+            return Err(ResolveError::TooManyReferrals { name: name.clone() });
+        }
+        iteration.queries += 1;
+
+        let msg = self.query(name, t, server, dnssec, false)?;
+        match msg.rcode() {
+            ResponseCode::NoError => {}
+            ResponseCode::NameError if msg.hdr.aa => return Ok(msg),
+            ResponseCode::ServerFailure => return Err(ResolveError::ServerFailure { server }),
+            rcode => return Err(ResolveError::ErrorResponse { server, rcode }),
+        }
+        if msg.hdr.ancount > 0 || msg.hdr.aa {
+            // an answer, or no data for this type
+            return Ok(msg);
+        }
+
+        // glue is only taken for name servers inside the zone they serve,
+        // so that a server cannot supply addresses for names outside it
+        for ar in &msg.ar {
+            let RData::A(addr) = ar.rdata else {
+                continue;
+            };
+            let in_bailiwick = msg.ns.iter().any(|ns| {
+                matches!(&ns.rdata, RData::NS(ns_name) if *ns_name == ar.name)
+                    && ar.name.is_subdomain_of(&ns.name)
+                    && name.is_subdomain_of(&ns.name)
+            });
+            if ar.class == DnsClass::IN && in_bailiwick {
+                iteration.glue.insert(ar.name.clone(), addr);
+            }
+        }
+
+        let mut last_error = None;
+        for ns in &msg.ns {
+            let RData::NS(ns_name) = &ns.rdata else {
+                continue;
+            };
+            if !name.is_subdomain_of(&ns.name) {
+                continue;
+            }
+
+            if let Some(&addr) = iteration.glue.get(ns_name) {
+                let server = (addr, 53).into();
+                match self.resolve(depth + 1, name, t, server, iteration, dnssec) {
+                    Ok(result) => return Ok(result),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+
+            // no glue, or the glue led nowhere: look the name server up
+            // from the root, as a lookup of its own, unless that is this
+            // lookup
+            if name == ns_name {
+                continue;
+            }
+            if iteration.nesting >= MAX_NS_LOOKUP_DEPTH {
+                last_error = Some(ResolveError::TooManyReferrals {
+                    name: ns_name.clone(),
+                });
+                continue;
+            }
+            iteration.nesting += 1;
+            let ns_lookup = self.resolve(0, ns_name, RecordType::A, self.root, iteration, false);
+            iteration.nesting -= 1;
+            let addr = match ns_lookup {
+                Ok(msg) => first_ipv4(&msg, ns_name),
+                Err(e) => {
+                    last_error = Some(e);
+                    None
+                }
+            };
+            if let Some(addr) = addr {
+                let server = (addr, 53).into();
+                match self.resolve(depth + 1, name, t, server, iteration, dnssec) {
+                    Ok(result) => return Ok(result),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+        // a server that neither answers nor refers anywhere is lame
+        Err(last_error.unwrap_or(ResolveError::LameDelegation {
+            server,
+            name: name.clone(),
+        }))
+    }
+}
+
+/// seconds since the epoch, for signing and verifying TSIG records.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The state shared by the referrals and name server lookups of a single
+/// iterative lookup.
+struct Iteration {
+    /// name server addresses from the glue seen so far.
+    glue: HashMap<Name, Ipv4Addr>,
+    /// queries sent so far.
+    queries: usize,
+    /// how many name server lookups the current one is nested in.
+    nesting: usize,
+}
+
+/// Follows the CNAME chain from `name` through the answer section of `msg`,
+/// returning the `t` records it leads to and the name it ends at. Records
+/// owned by names off the chain are ignored.
+fn chase<'a>(
+    msg: &'a Message,
+    name: &Name,
+    t: RecordType,
+) -> Result<(Vec<&'a RData>, Name), ResolveError> {
+    let mut name = name.clone();
+    for _ in 0..=MAX_CNAME_CHAIN {
+        let data = msg
+            .an
+            .iter()
+            .filter(|r| r.t == t && r.name == name)
+            .map(|r| &r.rdata)
+            .collect::<Vec<_>>();
+        if !data.is_empty() {
+            return Ok((data, name));
+        }
+        let target = msg.an.iter().find_map(|r| match &r.rdata {
+            RData::CNAME(target) if r.name == name => Some(target.clone()),
+            _ => None,
+        });
+        match target {
+            Some(target) => name = target,
+            None => return Ok((data, name)),
+        }
+    }
+    Err(ResolveError::CnameChainTooLong { name })
+}
+
+/// the first address of the name server `ns` in `msg`. Only IPv4 is used
+/// to reach name servers.
+fn first_ipv4(msg: &Message, ns: &Name) -> Option<Ipv4Addr> {
+    let (data, _) = chase(msg, ns, RecordType::A).ok()?;
+    data.into_iter().find_map(|rdata| match rdata {
+        RData::A(addr) => Some(*addr),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket},
+        sync::atomic::Ordering,
+        thread,
+        time::Duration,
+    };
+
+    use super::Resolver;
+    use crate::{
+        errors::{DnsError, ResolveError},
+        message::{
+            builder::MessageBuilder,
+            dnssec::Dnskey,
+            header::ResponseCode,
+            name::Name,
+            rdata::RData,
+            rr::ResourceRecord,
+            tsig::{TsigAlgorithm, TsigKey},
+            types::{DnsClass, RecordType},
+            update::Update,
+            Message,
+        },
+        transport::{
+            https::{self, HttpsTransport},
+            tls::{self, TlsTransport},
+            MockTransport, TcpTransport, Transport,
+        },
+    };
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn record(owner: &str, t: RecordType, rdata: RData) -> ResourceRecord {
+        ResourceRecord {
+            name: name(owner),
+            t,
+            class: DnsClass::IN,
+            ttl: 300,
+            rdata,
+        }
+    }
+
+    /// answers for example.com on a local socket bound to `local` until the
+    /// test exits.
+    fn serve(local: &str) -> SocketAddr {
+        let socket = UdpSocket::bind(local).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let zone = [
+            record(
+                "www.example.com",
+                RecordType::CNAME,
+                RData::CNAME(name("web.example.com")),
+            ),
+            record(
+                "web.example.com",
+                RecordType::A,
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            ),
+            record(
+                "web.example.com",
+                RecordType::AAAA,
+                RData::AAAA(Ipv6Addr::LOCALHOST),
+            ),
+            record(
+                "loop.example.com",
+                RecordType::CNAME,
+                RData::CNAME(name("loop.example.com")),
+            ),
+        ];
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let (_, query) = Message::parse(&buf[..n]).unwrap();
+                let q = &query.qd[0];
+                let mut response = MessageBuilder::response_to(&query).authoritative(true);
+                let owned = zone
+                    .iter()
+                    .filter(|r| r.name == q.qname)
+                    .collect::<Vec<_>>();
+                if owned.is_empty() {
+                    response = response.rcode(ResponseCode::NameError);
+                }
+                for r in owned {
+                    if r.t == q.qtype || r.t == RecordType::CNAME {
+                        response = response.answer(r.clone());
+                    }
+                }
+                let n = response.build().unwrap().write(&mut buf).unwrap();
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_lookup() {
+        let resolver = Resolver::new().unwrap().root(serve("127.0.0.1:0"));
+        assert_eq!(
+            resolver.lookup_ip(&name("www.example.com")).unwrap(),
+            vec![
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ]
+        );
+        let msg = resolver
+            .lookup(&name("web.example.com"), RecordType::A)
+            .unwrap();
+        assert!(msg.hdr.aa);
+        assert_eq!(msg.an.len(), 1);
+
+        assert!(matches!(
+            resolver.lookup_ipv4(&name("missing.example.com")),
+            Err(ResolveError::NoSuchName { .. })
+        ));
+        assert!(matches!(
+            resolver.lookup_ipv6(&name("loop.example.com")),
+            Err(ResolveError::CnameChainTooLong { .. })
+        ));
+
+        // the same transport reaches IPv6 servers
+        let resolver = resolver.forward(serve("[::1]:0"));
+        assert_eq!(
+            resolver.lookup_ipv4(&name("www.example.com")).unwrap(),
+            vec![Ipv4Addr::new(192, 0, 2, 1)]
+        );
+    }
+
+    /// applies updates to example.com on a local socket, checking only the
+    /// name not in use prerequisite.
+    fn serve_updates() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut zone: Vec<ResourceRecord> = vec![];
+            let mut buf = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let (_, update) = Message::parse(&buf[..n]).unwrap();
+                let response = MessageBuilder::response_to(&update);
+                let response = if update.qd[0].qname != name("example.com") {
+                    response.rcode(ResponseCode::NotZone)
+                } else if update
+                    .an
+                    .iter()
+                    .any(|p| p.class == DnsClass::NONE && zone.iter().any(|r| r.name == p.name))
+                {
+                    response.rcode(ResponseCode::YxDomain)
+                } else {
+                    zone.extend(update.ns.iter().cloned());
+                    response
+                };
+                let n = response.build().unwrap().write(&mut buf).unwrap();
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_update() {
+        let server = serve_updates();
+        let resolver = Resolver::new().unwrap();
+        let update = || {
+            Update::new(name("example.com"))
+                .name_not_in_use(name("new.example.com"))
+                .add_record(record(
+                    "new.example.com",
+                    RecordType::A,
+                    RData::A(Ipv4Addr::new(192, 0, 2, 2)),
+                ))
+        };
+        assert!(resolver.update(server, update()).is_ok());
+        assert!(matches!(
+            resolver.update(server, update()),
+            Err(ResolveError::PrerequisiteFailed {
+                rcode: ResponseCode::YxDomain,
+                ..
+            })
+        ));
+        assert!(matches!(
+            resolver.update(server, Update::new(name("example.org"))),
+            Err(ResolveError::UpdateRefused {
+                rcode: ResponseCode::NotZone,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_signed_update() {
+        let key = TsigKey::new(name("update-key"), TsigAlgorithm::HmacSha256, b"secret");
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = socket.local_addr().unwrap();
+        let server_key = key.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let mut session = server_key.session();
+                let response = match session.verify(&buf[..n], super::unix_time()) {
+                    Ok(update) => {
+                        let response = MessageBuilder::response_to(&update).build().unwrap();
+                        session.sign(&response, super::unix_time()).unwrap()
+                    }
+                    // unsigned, as the key is not known
+                    Err(_) => {
+                        let (_, update) = Message::parse(&buf[..n]).unwrap();
+                        MessageBuilder::response_to(&update)
+                            .rcode(ResponseCode::NotAuth)
+                            .build()
+                            .unwrap()
+                    }
+                };
+                let n = response.write(&mut buf).unwrap();
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+        });
+
+        let update = || Update::new(name("example.com")).delete_all(name("old.example.com"));
+        let response = Resolver::new()
+            .unwrap()
+            .tsig(key)
+            .update(server, update())
+            .unwrap();
+        assert_eq!(response.ar.last().unwrap().t, RecordType::TSIG);
+
+        let wrong_key = TsigKey::new(name("other-key"), TsigAlgorithm::HmacSha256, b"secret");
+        assert!(matches!(
+            Resolver::new()
+                .unwrap()
+                .tsig(wrong_key)
+                .update(server, update()),
+            Err(ResolveError::InvalidResponse { .. })
+        ));
+    }
+
+    /// answers every query over TCP with a TXT record too large for UDP.
+    fn serve_tcp(listener: TcpListener) {
+        thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(Result::ok) {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).unwrap();
+                let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let (_, query) = Message::parse(&query).unwrap();
+                let response = MessageBuilder::response_to(&query)
+                    .authoritative(true)
+                    .answer(record(
+                        "big.example.com",
+                        RecordType::TXT,
+                        RData::TXT(vec![vec![b'x'; 255]; 10]),
+                    ))
+                    .build()
+                    .unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = response.write(&mut buf[2..]).unwrap();
+                buf[..2].copy_from_slice(&(n as u16).to_be_bytes());
+                stream.write_all(&buf[..n + 2]).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_tcp_fallback() {
+        // a UDP server that only sends truncated responses, with the TCP
+        // server on the same port
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = socket.local_addr().unwrap();
+        serve_tcp(TcpListener::bind(server).unwrap());
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let (_, query) = Message::parse(&buf[..n]).unwrap();
+                let response = MessageBuilder::response_to(&query)
+                    .authoritative(true)
+                    .truncated(true)
+                    .build()
+                    .unwrap();
+                let n = response.write(&mut buf).unwrap();
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+        });
+
+        let msg = Resolver::new()
+            .unwrap()
+            .root(server)
+            .lookup(&name("big.example.com"), RecordType::TXT)
+            .unwrap();
+        assert!(!msg.hdr.tc);
+        assert_eq!(msg.an.len(), 1);
+    }
+
+    #[test]
+    fn test_force_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap();
+        serve_tcp(listener);
+        let msg = Resolver::with_transport(TcpTransport::new())
+            .root(server)
+            .lookup(&name("big.example.com"), RecordType::TXT)
+            .unwrap();
+        assert_eq!(msg.an.len(), 1);
+
+        // too long for the length prefix
+        assert!(matches!(
+            TcpTransport::new().send(&[0; 65536], server),
+            Err(ResolveError::Query(DnsError::MarshalError(_)))
+        ));
+    }
+
+    /// a referral to `ns` for `zone`, with glue if `glue` is set.
+    fn referral(zone: &str, ns: &str, glue: Option<Ipv4Addr>) -> Message {
+        let mut builder = MessageBuilder::new().response(true).authority(record(
+            zone,
+            RecordType::NS,
+            RData::NS(name(ns)),
+        ));
+        if let Some(addr) = glue {
+            builder = builder.additional(record(ns, RecordType::A, RData::A(addr)));
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_delegation_tree() {
+        let root = super::ROOT_SERVER;
+        let gtld = SocketAddr::from(([192, 5, 6, 30], 53));
+        let example = SocketAddr::from(([192, 0, 2, 53], 53));
+        let answer = |owner: &str, addr: Ipv4Addr| {
+            MessageBuilder::new()
+                .response(true)
+                .authoritative(true)
+                .answer(record(owner, RecordType::A, RData::A(addr)))
+        };
+        let a = RecordType::A;
+        let gtld_glue = Some(Ipv4Addr::new(192, 5, 6, 30));
+        let transport = MockTransport::new()
+            // the glue for a.gtld-servers.net is outside com, so it is not
+            // used and the name server is looked up instead
+            .respond(
+                root,
+                name("www.example.com"),
+                a,
+                referral("com", "a.gtld-servers.net", gtld_glue),
+            )
+            .respond(
+                root,
+                name("a.gtld-servers.net"),
+                a,
+                referral("net", "a.gtld-servers.net", gtld_glue),
+            )
+            .respond(
+                gtld,
+                name("a.gtld-servers.net"),
+                a,
+                answer("a.gtld-servers.net", Ipv4Addr::new(192, 5, 6, 30))
+                    .build()
+                    .unwrap(),
+            )
+            .respond(
+                root,
+                name("ns1.example.net"),
+                a,
+                referral("net", "a.gtld-servers.net", gtld_glue),
+            )
+            // example.com is served by a name server in another zone, so
+            // there is no glue
+            .respond(
+                gtld,
+                name("www.example.com"),
+                a,
+                referral("example.com", "ns1.example.net", None),
+            )
+            .respond(
+                gtld,
+                name("ns1.example.net"),
+                a,
+                answer("ns1.example.net", Ipv4Addr::new(192, 0, 2, 53))
+                    .build()
+                    .unwrap(),
+            )
+            // records for other names in the answer are ignored
+            .respond(
+                example,
+                name("www.example.com"),
+                a,
+                answer("www.example.com", Ipv4Addr::new(192, 0, 2, 80))
+                    .answer(record(
+                        "www.example.org",
+                        a,
+                        RData::A(Ipv4Addr::new(203, 0, 113, 1)),
+                    ))
+                    .build()
+                    .unwrap(),
+            );
+
+        let resolver = Resolver::with_transport(transport);
+        assert_eq!(
+            resolver.lookup_ipv4(&name("www.example.com")).unwrap(),
+            vec![Ipv4Addr::new(192, 0, 2, 80)]
+        );
+        let servers = resolver
+            .transport
+            .queries()
+            .into_iter()
+            .map(|(server, query)| (server, query.qd[0].qname.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            servers,
+            [
+                (root, "www.example.com."),
+                (root, "a.gtld-servers.net."),
+                (gtld, "a.gtld-servers.net."),
+                (gtld, "www.example.com."),
+                (root, "ns1.example.net."),
+                (gtld, "ns1.example.net."),
+                (example, "www.example.com."),
+            ]
+            .map(|(server, qname)| (server, qname.to_string()))
+        );
+
+        // nothing is scripted for the AAAA records
+        assert!(matches!(
+            resolver.lookup_ipv6(&name("www.example.com")),
+            Err(ResolveError::Timeout { server }) if server == root
+        ));
+    }
+
+    #[test]
+    fn test_referral_loop() {
+        // a root that keeps referring to itself
+        let root = super::ROOT_SERVER;
+        let glue = Some(Ipv4Addr::new(198, 41, 0, 4));
+        let mut transport = MockTransport::new();
+        for qname in ["www.example.com", "ns.example.com"] {
+            transport = transport.respond(
+                root,
+                name(qname),
+                RecordType::A,
+                referral("example.com", "ns.example.com", glue),
+            );
+        }
+        let resolver = Resolver::with_transport(transport);
+        assert!(matches!(
+            resolver.lookup(&name("www.example.com"), RecordType::A),
+            Err(ResolveError::TooManyReferrals { .. })
+        ));
+        assert_eq!(resolver.transport.queries().len(), super::MAX_QUERIES);
+    }
+
+    #[test]
+    fn test_validating_lookup() {
+        let upstream = SocketAddr::from(([192, 0, 2, 53], 53));
+        let answer = MessageBuilder::new()
+            .response(true)
+            .answer(record(
+                "www.example.com",
+                RecordType::A,
+                RData::A(Ipv4Addr::new(192, 0, 2, 80)),
+            ))
+            .build()
+            .unwrap();
+        // a root key that does not match the trust anchors
+        let key = Dnskey {
+            flags: 257,
+            protocol: 3,
+            algorithm: 8,
+            public_key: vec![3, 1, 0, 1, 0xab, 0xcd],
+        };
+        let keys = MessageBuilder::new()
+            .response(true)
+            .answer(record(".", RecordType::DNSKEY, RData::DNSKEY(key)))
+            .build()
+            .unwrap();
+        let transport = MockTransport::new()
+            .respond(upstream, name("www.example.com"), RecordType::A, answer)
+            .respond(upstream, Name::root(), RecordType::DNSKEY, keys);
+        let resolver = Resolver::with_transport(transport)
+            .forward(upstream)
+            .dnssec(true);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                resolver.lookup_ipv4(&name("www.example.com")),
+                Err(ResolveError::Bogus { name: n, rtype: RecordType::A })
+                    if n == name("www.example.com")
+            ));
+        }
+        // the root keys are only fetched once
+        let queries = resolver.transport.queries();
+        assert_eq!(queries.len(), 3);
+        assert!(queries
+            .iter()
+            .all(|(_, q)| q.edns.as_ref().is_some_and(|edns| edns.dnssec_ok())));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = silent.local_addr().unwrap();
+        let resolver = Resolver::new()
+            .unwrap()
+            .root(server)
+            .timeout(Duration::from_millis(50));
+        assert!(matches!(
+            resolver.lookup(&name("example.com"), RecordType::A),
+            Err(ResolveError::Timeout { server: s }) if s == server
+        ));
+    }
+
+    #[test]
+    fn test_forward_over_tls() {
+        let (roots, config, _) = tls::test::certificates();
+        let (server, connections) = tls::test::serve(config);
+        let transport = TlsTransport::new("dns.example").unwrap().roots(roots);
+        let resolver = Resolver::with_transport(transport).forward(server);
+
+        let msg = resolver
+            .lookup(&name("www.example.com"), RecordType::A)
+            .unwrap();
+        assert!(msg.hdr.rd);
+        assert_eq!(
+            resolver.lookup_ipv4(&name("web.example.com")).unwrap(),
+            [Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        // shareable across threads like the UDP resolver
+        thread::scope(|s| {
+            s.spawn(|| resolver.lookup(&name("a.example"), RecordType::A).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_forward_over_https() {
+        let (roots, config, _) = tls::test::certificates();
+        let (server, connections, requests) = https::test::serve(config);
+        let transport = HttpsTransport::new("https://dns.example/dns-query")
+            .unwrap()
+            .roots(roots);
+        let resolver = Resolver::with_transport(transport).forward(server);
+
+        let msg = resolver
+            .lookup(&name("www.example.com"), RecordType::A)
+            .unwrap();
+        assert!(msg.hdr.rd);
+        assert_eq!(
+            resolver.lookup_ipv4(&name("web.example.com")).unwrap(),
+            [Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        thread::scope(|s| {
+            s.spawn(|| resolver.lookup(&name("a.example"), RecordType::A).unwrap());
+        });
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
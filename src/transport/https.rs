@@ -0,0 +1,661 @@
+// DNS over HTTPS, RFC 8484
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use data_encoding::BASE64URL_NOPAD;
+use h2::client::SendRequest;
+use http::{header, Method, Request, StatusCode, Uri};
+use rustls::{pki_types::ServerName, RootCertStore};
+use tokio::{
+    net::TcpStream,
+    runtime::{self, Handle, Runtime},
+    time::timeout,
+};
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    errors::{DnsError, ResolveError},
+    message::{header::ResponseCode, rdata::RData, Message},
+};
+
+use super::{
+    tls::{client_config, tls_error},
+    Transport, DEFAULT_TIMEOUT,
+};
+
+/// the media type of DNS messages in wire format, RFC 8484 6.
+pub const DNS_MESSAGE: &str = "application/dns-message";
+
+/// the largest DNS message, longer response bodies are rejected.
+const MAX_BODY_LENGTH: usize = u16::MAX as usize;
+
+/// responses cached at most, those expiring soonest make way for new ones.
+pub const MAX_CACHE_ENTRIES: usize = 1024;
+
+/// the longest a response is cached, whatever its max-age and TTLs.
+const MAX_CACHE_LIFETIME: Duration = Duration::from_secs(86400);
+
+/// Sends queries as HTTP/2 requests to a DoH URI template without
+/// variables, such as `https://dns.example/dns-query`. The host of the URI
+/// authenticates every server the way `TlsTransport` does, and one
+/// connection is kept per server for all requests.
+///
+/// Responses are cached for the max-age of their Cache-Control header, but
+/// no longer than the smallest TTL in them, RFC 8484 5.1. Queries are sent
+/// with ID 0 so that identical queries share a cache entry, RFC 8484 4.1,
+/// and responses are given the ID of the query. Use it with
+/// `Resolver::forward`.
+///
+/// `send` blocks on a runtime of its own and fails when called from a task
+/// of another tokio runtime. Async callers should move lookups onto a
+/// blocking thread with `tokio::task::spawn_blocking`.
+#[derive(Debug)]
+pub struct HttpsTransport {
+    uri: Uri,
+    server_name: ServerName<'static>,
+    get: bool,
+    roots: Arc<RootCertStore>,
+    pins: Vec<[u8; 32]>,
+    timeout: Duration,
+    runtime: Runtime,
+    connections: Mutex<HashMap<SocketAddr, SendRequest<Bytes>>>,
+    cache: Mutex<Cache>,
+}
+
+/// responses by server and query, with the time they expire.
+type Cache = HashMap<(SocketAddr, Vec<u8>), (Instant, Vec<u8>)>;
+
+impl HttpsTransport {
+    /// Sends queries to `uri` with POST, trusting the Mozilla root
+    /// certificates. Fails if `uri` is not an https URI with a host.
+    pub fn new(uri: &str) -> io::Result<Self> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+        let uri: Uri = uri
+            .parse()
+            .map_err(|e: http::uri::InvalidUri| invalid(e.to_string()))?;
+        if uri.scheme() != Some(&http::uri::Scheme::HTTPS) {
+            return Err(invalid(format!("{} is not an https URI", uri)));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| invalid(format!("{} has no host", uri)))?;
+        let server_name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(Self {
+            uri,
+            server_name,
+            get: false,
+            roots: Arc::new(RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            }),
+            pins: vec![],
+            timeout: DEFAULT_TIMEOUT,
+            runtime: runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+            connections: Mutex::new(HashMap::new()),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// sends queries with GET and the `dns` parameter instead of POST, RFC
+    /// 8484 4.1.
+    pub fn get(mut self, get: bool) -> Self {
+        self.get = get;
+        self
+    }
+
+    /// trusts `roots` instead of the Mozilla root certificates.
+    pub fn roots(mut self, roots: RootCertStore) -> Self {
+        self.roots = Arc::new(roots);
+        self
+    }
+
+    /// requires a public key of the server certificate chain to have the
+    /// SPKI digest `pin`, see `TlsTransport::pin_spki`.
+    pub fn pin_spki(mut self, pin: [u8; 32]) -> Self {
+        self.pins.push(pin);
+        self
+    }
+
+    /// how long to wait to connect and for each response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `query` on the connection `send` to `server`, or on a new one
+    /// that is kept for later requests. A connection that fails is dropped.
+    /// HTTP/2 multiplexes requests, so the map of connections is only
+    /// locked to update it, never for a request.
+    fn request_on(
+        &self,
+        send: Option<SendRequest<Bytes>>,
+        query: &[u8],
+        server: SocketAddr,
+    ) -> Result<(Vec<u8>, Option<Duration>), ResolveError> {
+        let result = self.runtime.block_on(async {
+            let send = match send {
+                Some(send) => send,
+                None => {
+                    let send = self.connect(server).await?;
+                    lock(&self.connections).insert(server, send.clone());
+                    send
+                }
+            };
+            timeout(self.timeout, self.request(&send, query, server))
+                .await
+                .map_err(|_| ResolveError::Timeout { server })?
+        });
+        if result.is_err() {
+            lock(&self.connections).remove(&server);
+        }
+        result
+    }
+
+    async fn connect(&self, server: SocketAddr) -> Result<SendRequest<Bytes>, ResolveError> {
+        let mut config = client_config(&self.roots, &self.pins)
+            .map_err(|error| ResolveError::Tls { server, error })?;
+        config.alpn_protocols = vec![b"h2".to_vec()];
+
+        let connect = async {
+            let tcp = TcpStream::connect(server).await?;
+            tcp.set_nodelay(true)?;
+            TlsConnector::from(Arc::new(config))
+                .connect(self.server_name.clone(), tcp)
+                .await
+        };
+        let tls = timeout(self.timeout, connect)
+            .await
+            .map_err(|_| ResolveError::Timeout { server })?
+            .map_err(|e| tls_error(e, server))?;
+        let (send, connection) = h2::client::handshake(tls)
+            .await
+            .map_err(|e| http_error(e, server))?;
+        // drives the connection whenever a request is waited on
+        self.runtime.spawn(async move {
+            let _ = connection.await;
+        });
+        Ok(send)
+    }
+
+    /// Makes the request for `query`, returning the response body and how
+    /// long it may be cached.
+    async fn request(
+        &self,
+        send: &SendRequest<Bytes>,
+        query: &[u8],
+        server: SocketAddr,
+    ) -> Result<(Vec<u8>, Option<Duration>), ResolveError> {
+        let request = Request::builder().header(header::ACCEPT, DNS_MESSAGE);
+        let request = if self.get {
+            let path = self.uri.path_and_query().map_or("/", |p| p.as_str());
+            let separator = if path.contains('?') { '&' } else { '?' };
+            let dns = BASE64URL_NOPAD.encode(query);
+            let uri = Uri::builder()
+                .scheme("https")
+                .authority(self.uri.authority().unwrap().clone())
+                .path_and_query(format!("{}{}dns={}", path, separator, dns))
+                .build()
+                .map_err(|e| http_error(e, server))?;
+            request.method(Method::GET).uri(uri)
+        } else {
+            request
+                .method(Method::POST)
+                .uri(self.uri.clone())
+                .header(header::CONTENT_TYPE, DNS_MESSAGE)
+                .header(header::CONTENT_LENGTH, query.len())
+        };
+        let request = request.body(()).map_err(|e| http_error(e, server))?;
+
+        let mut send = send
+            .clone()
+            .ready()
+            .await
+            .map_err(|e| http_error(e, server))?;
+        let (response, mut body) = send
+            .send_request(request, self.get)
+            .map_err(|e| http_error(e, server))?;
+        if !self.get {
+            body.send_data(Bytes::copy_from_slice(query), true)
+                .map_err(|e| http_error(e, server))?;
+        }
+
+        let response = response.await.map_err(|e| http_error(e, server))?;
+        if response.status() != StatusCode::OK {
+            return Err(http_error(format!("status {}", response.status()), server));
+        }
+        let content_type = response.headers().get(header::CONTENT_TYPE);
+        if content_type.and_then(|v| v.to_str().ok()) != Some(DNS_MESSAGE) {
+            return Err(http_error(
+                format!("content type {:?} is not {}", content_type, DNS_MESSAGE),
+                server,
+            ));
+        }
+        let max_age = max_age(response.headers());
+
+        let mut body = response.into_body();
+        let mut message = vec![];
+        while let Some(data) = body.data().await {
+            let data = data.map_err(|e| http_error(e, server))?;
+            let _ = body.flow_control().release_capacity(data.len());
+            if message.len() + data.len() > MAX_BODY_LENGTH {
+                return Err(http_error(
+                    format!("response body exceeds {} bytes", MAX_BODY_LENGTH),
+                    server,
+                ));
+            }
+            message.extend_from_slice(&data);
+        }
+        Ok((message, max_age))
+    }
+}
+
+impl Transport for HttpsTransport {
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError> {
+        // block_on panics inside another runtime
+        if Handle::try_current().is_ok() {
+            return Err(ResolveError::Io(io::Error::other(
+                "HttpsTransport cannot block inside a tokio runtime",
+            )));
+        }
+        let Some(&[id0, id1]) = query.get(..2) else {
+            return Err(ResolveError::Query(DnsError::Truncated {
+                needed: 2,
+                available: query.len(),
+                offset: 0,
+            }));
+        };
+        let mut query = query.to_vec();
+        query[..2].copy_from_slice(&[0, 0]);
+
+        let now = Instant::now();
+        let key = (server, query);
+        let cached = lock(&self.cache).get(&key).cloned();
+        let mut response = match cached {
+            Some((expires, response)) if expires > now => response,
+            _ => {
+                let idle = lock(&self.connections).get(&server).cloned();
+                let reused = idle.is_some();
+                let (response, max_age) = match self.request_on(idle, &key.1, server) {
+                    // the server may have closed the connection
+                    Err(ResolveError::Io(_) | ResolveError::Http { .. }) if reused => {
+                        self.request_on(None, &key.1, server)?
+                    }
+                    result => result?,
+                };
+                let expires = max_age
+                    .and_then(|max_age| lifetime(max_age, &response))
+                    .and_then(|lifetime| now.checked_add(lifetime));
+                if let Some(expires) = expires {
+                    let mut cache = lock(&self.cache);
+                    cache.retain(|_, (expires, _)| *expires > now);
+                    if cache.len() >= MAX_CACHE_ENTRIES {
+                        let soonest = cache
+                            .iter()
+                            .min_by_key(|(_, (expires, _))| *expires)
+                            .map(|(key, _)| key.clone());
+                        if let Some(soonest) = soonest {
+                            cache.remove(&soonest);
+                        }
+                    }
+                    cache.insert(key, (expires, response.clone()));
+                }
+                response
+            }
+        };
+        if response.len() >= 2 {
+            response[..2].copy_from_slice(&[id0, id1]);
+        }
+        Ok(response)
+    }
+}
+
+/// Locks `mutex` even if a thread panicked holding it. Neither map is left
+/// inconsistent by a panic.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How long a response may be cached: its max-age less its Age, RFC 9111
+/// 4.2. `None` if it must not be cached.
+fn max_age(headers: &http::HeaderMap) -> Option<Duration> {
+    let directives = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|d| d.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    if directives
+        .iter()
+        .any(|d| d == "no-store" || d == "no-cache")
+    {
+        return None;
+    }
+    let max_age = directives
+        .iter()
+        .find_map(|d| d.strip_prefix("max-age=")?.parse::<u64>().ok())?;
+    let age = headers
+        .get(header::AGE)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+        .unwrap_or(0);
+    Some(Duration::from_secs(max_age.saturating_sub(age))).filter(|d| !d.is_zero())
+}
+
+/// How long `response` may be cached given the `max_age` of the HTTP
+/// response: no longer than its smallest TTL, or the SOA minimum of a
+/// negative response, RFC 8484 5.1 and RFC 2308 5. `None` for server
+/// failures, truncated responses and anything that does not parse.
+fn lifetime(max_age: Duration, response: &[u8]) -> Option<Duration> {
+    let (_, msg) = Message::parse(response).ok()?;
+    if msg.hdr.tc || msg.rcode() == ResponseCode::ServerFailure {
+        return None;
+    }
+    let records = msg.an.iter().chain(&msg.ns).chain(&msg.ar);
+    let ttl = records
+        .flat_map(|r| match &r.rdata {
+            RData::SOA(soa) if msg.an.is_empty() => vec![r.ttl, soa.minimum],
+            _ => vec![r.ttl],
+        })
+        .min();
+    let mut lifetime = max_age.min(MAX_CACHE_LIFETIME);
+    if let Some(ttl) = ttl {
+        lifetime = lifetime.min(Duration::from_secs(ttl.into()));
+    }
+    Some(lifetime).filter(|d| !d.is_zero())
+}
+
+fn http_error(error: impl ToString, server: SocketAddr) -> ResolveError {
+    ResolveError::Http {
+        server,
+        reason: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use data_encoding::BASE64URL_NOPAD;
+    use http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode};
+    use rustls::ServerConfig;
+    use tokio::{net::TcpListener, runtime};
+    use tokio_rustls::TlsAcceptor;
+
+    use super::{lifetime, max_age, HttpsTransport, DNS_MESSAGE, MAX_CACHE_LIFETIME};
+    use crate::{
+        errors::{DnsError, ResolveError},
+        message::{
+            builder::MessageBuilder,
+            header::ResponseCode,
+            name::Name,
+            rdata::{RData, Soa},
+            rr::ResourceRecord,
+            types::{DnsClass, RecordType},
+            Message,
+        },
+        transport::{
+            tls::test::{answer, certificates},
+            Transport,
+        },
+    };
+
+    /// Serves DoH over HTTP/2 at `/dns-query`, counting connections and
+    /// recording the method of each request. Responses may be cached for a
+    /// minute. The response for `big.example` is longer than any message.
+    pub(crate) fn serve(
+        config: Arc<ServerConfig>,
+    ) -> (SocketAddr, Arc<AtomicUsize>, Arc<Mutex<Vec<Method>>>) {
+        let mut config = (*config).clone();
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let (count, methods) = (connections.clone(), requests.clone());
+        thread::spawn(move || {
+            let runtime = runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+                while let Ok((tcp, _)) = listener.accept().await {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let (acceptor, methods) = (acceptor.clone(), methods.clone());
+                    tokio::spawn(async move {
+                        let tls = acceptor.accept(tcp).await.unwrap();
+                        let mut connection = h2::server::handshake(tls).await.unwrap();
+                        while let Some(Ok((request, mut respond))) = connection.accept().await {
+                            methods.lock().unwrap().push(request.method().clone());
+                            assert_eq!(request.uri().path(), "/dns-query");
+                            assert_eq!(request.headers()[header::ACCEPT], DNS_MESSAGE);
+                            let query = if request.method() == Method::GET {
+                                let query = request.uri().query().unwrap();
+                                let dns = query
+                                    .split('&')
+                                    .find_map(|p| p.strip_prefix("dns="))
+                                    .unwrap();
+                                BASE64URL_NOPAD.decode(dns.as_bytes()).unwrap()
+                            } else {
+                                assert_eq!(request.headers()[header::CONTENT_TYPE], DNS_MESSAGE);
+                                let mut body = request.into_body();
+                                let mut query = vec![];
+                                while let Some(data) = body.data().await {
+                                    query.extend_from_slice(&data.unwrap());
+                                }
+                                query
+                            };
+                            assert_eq!(query[..2], [0, 0]);
+
+                            let response = Response::builder()
+                                .status(StatusCode::OK)
+                                .header(header::CONTENT_TYPE, DNS_MESSAGE)
+                                .header(header::CACHE_CONTROL, "max-age=60")
+                                .body(())
+                                .unwrap();
+                            let mut body = respond.send_response(response, false).unwrap();
+                            let (_, msg) = Message::parse(&query).unwrap();
+                            let response = match msg.qd[0].qname == "big.example".parse().unwrap() {
+                                true => vec![0; 70000],
+                                false => answer(&query),
+                            };
+                            let _ = body.send_data(Bytes::from(response), true);
+                        }
+                    });
+                }
+            });
+        });
+        (addr, connections, requests)
+    }
+
+    fn query(name: &str) -> Message {
+        Message::new_query(
+            &name.parse().unwrap(),
+            RecordType::A,
+            DnsClass::IN,
+            true,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_post_reuse_and_cache() {
+        let (roots, config, _) = certificates();
+        let (server, connections, requests) = serve(config);
+        let transport = HttpsTransport::new("https://dns.example/dns-query")
+            .unwrap()
+            .roots(roots);
+
+        for name in ["a.example", "b.example", "a.example"] {
+            let query = query(name);
+            let response = transport.exchange(&query, server).unwrap();
+            assert_eq!(response.hdr.id, query.hdr.id);
+            assert_eq!(response.an[0].name, name.parse::<Name>().unwrap());
+        }
+        // the second query for a.example is answered from the cache
+        assert_eq!(*requests.lock().unwrap(), [Method::POST, Method::POST]);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_get() {
+        let (roots, config, _) = certificates();
+        let (server, _, requests) = serve(config);
+        let transport = HttpsTransport::new("https://dns.example/dns-query")
+            .unwrap()
+            .roots(roots.clone())
+            .get(true);
+
+        let query = query("a.example");
+        let response = transport.exchange(&query, server).unwrap();
+        assert_eq!(response.hdr.id, query.hdr.id);
+        assert_eq!(response.an[0].name, "a.example".parse::<Name>().unwrap());
+        assert_eq!(*requests.lock().unwrap(), [Method::GET]);
+
+        // dns= joins a query string already in the URI
+        let transport = HttpsTransport::new("https://dns.example/dns-query?ct")
+            .unwrap()
+            .roots(roots)
+            .get(true);
+        let response = transport.exchange(&query, server).unwrap();
+        assert_eq!(response.an[0].name, "a.example".parse::<Name>().unwrap());
+        assert_eq!(*requests.lock().unwrap(), [Method::GET, Method::GET]);
+
+        assert!(HttpsTransport::new("http://dns.example/dns-query").is_err());
+    }
+
+    #[test]
+    fn test_invalid_use() {
+        let (roots, config, _) = certificates();
+        let (server, _, _) = serve(config);
+        let transport = HttpsTransport::new("https://dns.example/dns-query")
+            .unwrap()
+            .roots(roots);
+
+        assert!(matches!(
+            transport.send(&[0], server),
+            Err(ResolveError::Query(DnsError::Truncated { .. }))
+        ));
+        assert!(matches!(
+            transport.exchange(&query("big.example"), server),
+            Err(ResolveError::Http { .. })
+        ));
+
+        // an error rather than a panic inside another runtime
+        let runtime = runtime::Builder::new_current_thread().build().unwrap();
+        let result = runtime.block_on(async { transport.exchange(&query("a.example"), server) });
+        assert!(matches!(result, Err(ResolveError::Io(_))));
+        transport.exchange(&query("a.example"), server).unwrap();
+    }
+
+    #[test]
+    fn test_max_age() {
+        let headers = |values: &[(header::HeaderName, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in values {
+                headers.append(name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+        assert_eq!(
+            max_age(&headers(&[(header::CACHE_CONTROL, "public, max-age=60")])),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            max_age(&headers(&[
+                (header::CACHE_CONTROL, "max-age=60"),
+                (header::AGE, "20")
+            ])),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(
+            max_age(&headers(&[(header::CACHE_CONTROL, "max-age=60, no-store")])),
+            None
+        );
+        assert_eq!(
+            max_age(&headers(&[(header::CACHE_CONTROL, "max-age=0")])),
+            None
+        );
+        assert_eq!(max_age(&headers(&[])), None);
+    }
+
+    #[test]
+    fn test_lifetime() {
+        let response = |builder: MessageBuilder| {
+            let mut buf = vec![0; 512];
+            let n = builder.build().unwrap().write(&mut buf).unwrap();
+            buf.truncate(n);
+            buf
+        };
+        let name: Name = "a.example".parse().unwrap();
+        let record = |ttl, rdata| ResourceRecord {
+            name: name.clone(),
+            t: RecordType::A,
+            class: DnsClass::IN,
+            ttl,
+            rdata,
+        };
+        let a = response(
+            MessageBuilder::new()
+                .response(true)
+                .answer(record(300, RData::A(Ipv4Addr::new(192, 0, 2, 1))))
+                .answer(record(100, RData::A(Ipv4Addr::new(192, 0, 2, 2)))),
+        );
+        let secs = Duration::from_secs;
+        assert_eq!(lifetime(secs(60), &a), Some(secs(60)));
+        assert_eq!(lifetime(secs(3600), &a), Some(secs(100)));
+        assert_eq!(lifetime(secs(u64::MAX), &a), Some(secs(100)));
+
+        // a negative response lasts no longer than its SOA minimum
+        let soa = RData::SOA(Soa {
+            mname: name.clone(),
+            rname: name.clone(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 30,
+        });
+        let nxdomain = MessageBuilder::new()
+            .response(true)
+            .rcode(ResponseCode::NameError)
+            .authority(ResourceRecord {
+                t: RecordType::SOA,
+                ..record(3600, soa)
+            });
+        assert_eq!(lifetime(secs(60), &response(nxdomain)), Some(secs(30)));
+
+        let empty = response(MessageBuilder::new().response(true));
+        assert_eq!(lifetime(secs(u64::MAX), &empty), Some(MAX_CACHE_LIFETIME));
+        assert_eq!(lifetime(secs(0), &a), None);
+
+        let servfail = MessageBuilder::new()
+            .response(true)
+            .rcode(ResponseCode::ServerFailure);
+        assert_eq!(lifetime(secs(60), &response(servfail)), None);
+        let truncated = MessageBuilder::new().response(true).truncated(true);
+        assert_eq!(lifetime(secs(60), &response(truncated)), None);
+        assert_eq!(lifetime(secs(60), &[0]), None);
+    }
+}
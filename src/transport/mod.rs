@@ -0,0 +1,243 @@
+// ways of sending queries to name servers
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use crate::{
+    errors::{DnsError, ResolveError},
+    message::{header::Header, name::Name, types::RecordType, Message},
+};
+
+pub mod https;
+pub mod tls;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends queries to name servers and returns their responses.
+pub trait Transport {
+    /// Sends the wire form of a query to `server`, returning the response
+    /// with the same ID as it was received.
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError>;
+
+    /// Sends `msg` to `server` and parses the response.
+    fn exchange(&self, msg: &Message, server: SocketAddr) -> Result<Message, ResolveError> {
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let n = msg.write(&mut buf).map_err(ResolveError::Query)?;
+        let response = self.send(&buf[..n], server)?;
+        let (_, response) = Message::parse(&response)
+            .map_err(|error| ResolveError::InvalidResponse { server, error })?;
+        Ok(response)
+    }
+}
+
+/// Sends queries over UDP, retrying over TCP when the response is
+/// truncated, RFC 1035 4.2.1.
+#[derive(Debug)]
+pub struct UdpTransport {
+    /// ephemeral sockets for IPv4 and IPv6 servers, each bound when it is
+    /// first needed.
+    v4: OnceLock<UdpSocket>,
+    v6: OnceLock<UdpSocket>,
+    timeout: Duration,
+}
+
+impl UdpTransport {
+    /// Creates a transport for both IPv4 and IPv6 servers. Its sockets are
+    /// bound on first use, so binding errors are returned by `send`.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            v4: OnceLock::new(),
+            v6: OnceLock::new(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// how long to wait for each response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl UdpTransport {
+    /// the socket for the address family of `server`.
+    fn socket(&self, server: SocketAddr) -> io::Result<&UdpSocket> {
+        let (cell, local) = match server {
+            SocketAddr::V4(_) => (&self.v4, "0.0.0.0:0"),
+            SocketAddr::V6(_) => (&self.v6, "[::]:0"),
+        };
+        if let Some(socket) = cell.get() {
+            return Ok(socket);
+        }
+        // a socket bound concurrently by another thread wins
+        let socket = UdpSocket::bind(local)?;
+        Ok(cell.get_or_init(|| socket))
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError> {
+        let mut rb = [0u8; 1600];
+        let socket = self.socket(server)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.send_to(query, server)?;
+        loop {
+            let (r, from) = socket
+                .recv_from(&mut rb[..])
+                .map_err(|e| timeout_error(e, server))?;
+            // a late response to an earlier query that timed out
+            if from != server || rb[..r].get(..2) != query.get(..2) {
+                continue;
+            }
+            return match Header::parse(&rb[..r]) {
+                Ok(hdr) if hdr.tc => exchange_tcp(query, server, self.timeout),
+                _ => Ok(rb[..r].to_vec()),
+            };
+        }
+    }
+}
+
+/// Sends every query over TCP, on a new connection each time.
+#[derive(Debug)]
+pub struct TcpTransport {
+    timeout: Duration,
+}
+
+impl TcpTransport {
+    pub fn new() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// how long to wait to connect and for each response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError> {
+        exchange_tcp(query, server, self.timeout)
+    }
+}
+
+/// Sends `query` on a new connection to `server`, with messages prefixed by
+/// their two byte length, RFC 1035 4.2.2.
+fn exchange_tcp(
+    query: &[u8],
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<u8>, ResolveError> {
+    let len = tcp_length(query)?;
+    let mut stream =
+        TcpStream::connect_timeout(&server, timeout).map_err(|e| timeout_error(e, server))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut framed = Vec::with_capacity(2 + query.len());
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(query);
+    stream
+        .write_all(&framed)
+        .map_err(|e| timeout_error(e, server))?;
+
+    let mut len = [0u8; 2];
+    stream
+        .read_exact(&mut len)
+        .map_err(|e| timeout_error(e, server))?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream
+        .read_exact(&mut response)
+        .map_err(|e| timeout_error(e, server))?;
+    if response.get(..2) != query.get(..2) {
+        return Err(ResolveError::InvalidResponse {
+            server,
+            error: DnsError::ParseError("response ID does not match the query".to_string()),
+        });
+    }
+    Ok(response)
+}
+
+/// the two byte length prefix of `query` on a stream.
+fn tcp_length(query: &[u8]) -> Result<u16, ResolveError> {
+    u16::try_from(query.len()).map_err(|_| {
+        ResolveError::Query(DnsError::MarshalError(
+            "write: message cannot exceed 65535 bytes".to_string(),
+        ))
+    })
+}
+
+fn timeout_error(e: io::Error, server: SocketAddr) -> ResolveError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ResolveError::Timeout { server },
+        _ => e.into(),
+    }
+}
+
+/// Answers queries with scripted responses instead of using the network, so
+/// that whole delegation trees can be resolved deterministically. Queries
+/// without a response time out.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: HashMap<(SocketAddr, Name, RecordType), Message>,
+    queries: RefCell<Vec<(SocketAddr, Message)>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers queries for the `t` records of `name` sent to `server` with
+    /// `response`. Its ID and question are replaced by those of the query.
+    pub fn respond(
+        mut self,
+        server: SocketAddr,
+        name: Name,
+        t: RecordType,
+        response: Message,
+    ) -> Self {
+        self.responses.insert((server, name, t), response);
+        self
+    }
+
+    /// the queries sent so far, with the servers they were sent to.
+    pub fn queries(&self) -> Vec<(SocketAddr, Message)> {
+        self.queries.borrow().clone()
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError> {
+        let (_, query) = Message::parse(query).map_err(ResolveError::Query)?;
+        self.queries.borrow_mut().push((server, query.clone()));
+        let q = query.qd.first().ok_or(ResolveError::Timeout { server })?;
+        let mut response = self
+            .responses
+            .get(&(server, q.qname.clone(), q.qtype))
+            .cloned()
+            .ok_or(ResolveError::Timeout { server })?;
+        response.hdr.id = query.hdr.id;
+        response.hdr.qdcount = query.hdr.qdcount;
+        response.qd = query.qd;
+
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let n = response.write(&mut buf).map_err(ResolveError::Query)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+}
@@ -0,0 +1,579 @@
+// DNS over TLS, RFC 7858
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    iter,
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use ring::digest;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::{ring::default_provider, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    SignatureScheme, StreamOwned,
+};
+use webpki::{EndEntityCert, KeyUsage, VerifiedPath};
+
+use crate::errors::ResolveError;
+
+use super::{tcp_length, timeout_error, Transport, DEFAULT_TIMEOUT};
+
+/// the port DNS over TLS servers listen on, RFC 7858 3.1.
+pub const DOT_PORT: u16 = 853;
+
+type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Sends queries over TLS, authenticating servers by name against a set of
+/// root certificates, RFC 8310 8.1. Connections are kept open and reused
+/// for later queries, RFC 7858 3.4.
+///
+/// Every server is authenticated with the same name, so a transport is
+/// meant for one upstream resolver, which may have several addresses. Use
+/// it with `Resolver::forward`, the iterative resolver would try to reach
+/// authoritative servers over TLS on port 53. Queries from several threads
+/// that find the connection busy open connections of their own.
+#[derive(Debug)]
+pub struct TlsTransport {
+    server_name: ServerName<'static>,
+    roots: Arc<RootCertStore>,
+    pins: Vec<[u8; 32]>,
+    timeout: Duration,
+    connections: Mutex<HashMap<SocketAddr, TlsStream>>,
+}
+
+impl TlsTransport {
+    /// authenticates servers as `server_name` using the Mozilla root
+    /// certificates. Fails if `server_name` is not a valid DNS name or IP
+    /// address.
+    pub fn new(server_name: &str) -> io::Result<Self> {
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            server_name,
+            roots: Arc::new(RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            }),
+            pins: vec![],
+            timeout: DEFAULT_TIMEOUT,
+            connections: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// trusts `roots` instead of the Mozilla root certificates.
+    pub fn roots(mut self, roots: RootCertStore) -> Self {
+        self.roots = Arc::new(roots);
+        self
+    }
+
+    /// Requires the verified chain from the server certificate to the root
+    /// to include a certificate, other than the root, whose
+    /// SubjectPublicKeyInfo has the SHA-256 digest `pin`, RFC 7469 2.4. With
+    /// several pins any one of them is enough. Use `roots` to trust a
+    /// single root instead.
+    pub fn pin_spki(mut self, pin: [u8; 32]) -> Self {
+        self.pins.push(pin);
+        self
+    }
+
+    /// how long to wait to connect and for each response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends all of `queries` to `server` before reading any response,
+    /// returning the responses in the order of the queries. Servers may
+    /// answer out of order, responses are matched by ID, RFC 7766 6.2.1.1.
+    pub fn pipeline(
+        &self,
+        queries: &[&[u8]],
+        server: SocketAddr,
+    ) -> Result<Vec<Vec<u8>>, ResolveError> {
+        // the connection is taken out of the map for the exchange, so that
+        // other threads are not held up meanwhile, and only put back once
+        // the exchange succeeded
+        let idle = lock(&self.connections).remove(&server);
+        let reused = idle.is_some();
+        let mut stream = match idle {
+            Some(stream) => stream,
+            None => self.connect(server)?,
+        };
+        let mut result = exchange_pipelined(&mut stream, queries, server);
+        if reused && matches!(result, Err(ResolveError::Io(_))) {
+            // the server may have closed an idle connection, RFC 7858 3.4
+            stream = self.connect(server)?;
+            result = exchange_pipelined(&mut stream, queries, server);
+        }
+        if result.is_ok() {
+            lock(&self.connections).entry(server).or_insert(stream);
+        }
+        result
+    }
+
+    fn connect(&self, server: SocketAddr) -> Result<TlsStream, ResolveError> {
+        let config = client_config(&self.roots, &self.pins)
+            .map_err(|error| ResolveError::Tls { server, error })?;
+        let connection = ClientConnection::new(Arc::new(config), self.server_name.clone())
+            .map_err(|error| ResolveError::Tls { server, error })?;
+
+        let tcp = TcpStream::connect_timeout(&server, self.timeout)
+            .map_err(|e| timeout_error(e, server))?;
+        tcp.set_read_timeout(Some(self.timeout))?;
+        tcp.set_write_timeout(Some(self.timeout))?;
+        tcp.set_nodelay(true)?;
+        Ok(StreamOwned::new(connection, tcp))
+    }
+}
+
+impl Transport for TlsTransport {
+    fn send(&self, query: &[u8], server: SocketAddr) -> Result<Vec<u8>, ResolveError> {
+        let mut responses = self.pipeline(&[query], server)?;
+        Ok(responses.remove(0))
+    }
+}
+
+/// A client configuration trusting `roots`, with the SPKI `pins` if there
+/// are any.
+pub(super) fn client_config(
+    roots: &Arc<RootCertStore>,
+    pins: &[[u8; 32]],
+) -> Result<ClientConfig, rustls::Error> {
+    let provider = Arc::new(default_provider());
+    let verifier = WebPkiServerVerifier::builder_with_provider(roots.clone(), provider.clone())
+        .build()
+        .map_err(|e| rustls::Error::General(e.to_string()))?;
+    let algorithms = provider.signature_verification_algorithms;
+    Ok(ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinningVerifier {
+            inner: verifier,
+            roots: roots.clone(),
+            algorithms,
+            pins: pins.to_vec(),
+        }))
+        .with_no_client_auth())
+}
+
+/// Writes the length-framed `queries` in one go and reads responses until
+/// each query has one. Responses with other IDs are ignored.
+fn exchange_pipelined(
+    stream: &mut TlsStream,
+    queries: &[&[u8]],
+    server: SocketAddr,
+) -> Result<Vec<Vec<u8>>, ResolveError> {
+    let mut framed = vec![];
+    for query in queries {
+        framed.extend_from_slice(&tcp_length(query)?.to_be_bytes());
+        framed.extend_from_slice(query);
+    }
+    stream
+        .write_all(&framed)
+        .and_then(|_| stream.flush())
+        .map_err(|e| tls_error(e, server))?;
+
+    let mut responses = vec![None; queries.len()];
+    let mut pending = queries.len();
+    while pending > 0 {
+        let mut len = [0u8; 2];
+        stream
+            .read_exact(&mut len)
+            .map_err(|e| tls_error(e, server))?;
+        let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream
+            .read_exact(&mut response)
+            .map_err(|e| tls_error(e, server))?;
+        let slot = queries
+            .iter()
+            .zip(&responses)
+            .position(|(q, r)| r.is_none() && q.get(..2) == response.get(..2));
+        if let Some(i) = slot {
+            responses[i] = Some(response);
+            pending -= 1;
+        }
+    }
+    Ok(responses.into_iter().flatten().collect())
+}
+
+/// Unwraps TLS failures, which rustls reports as I/O errors.
+pub(super) fn tls_error(e: io::Error, server: SocketAddr) -> ResolveError {
+    match e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+        Some(error) => ResolveError::Tls {
+            server,
+            error: error.clone(),
+        },
+        None => timeout_error(e, server),
+    }
+}
+
+/// The SHA-256 digest of the SubjectPublicKeyInfo of a DER encoded
+/// certificate, as used by `TlsTransport::pin_spki`. `None` if the
+/// certificate cannot be parsed.
+pub fn spki_sha256(cert: &[u8]) -> Option<[u8; 32]> {
+    let cert = CertificateDer::from(cert);
+    let cert = EndEntityCert::try_from(&cert).ok()?;
+    sha256(&cert.subject_public_key_info())
+}
+
+fn sha256(b: &[u8]) -> Option<[u8; 32]> {
+    digest::digest(&digest::SHA256, b).as_ref().try_into().ok()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // a panic mid-exchange leaves at worst a connection missing from the
+    // map
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Checks certificates with the usual name and chain verification, then
+/// against the SPKI pins if there are any.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    algorithms: WebPkiSupportedAlgorithms,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinningVerifier {
+    /// true if the certificates from `end_entity` to a trusted root include
+    /// a pinned key. The chain is built again, accepting only a path with a
+    /// pin, so that certificates the server sends that are not on the path
+    /// cannot satisfy a pin.
+    fn pinned(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> bool {
+        let Ok(cert) = EndEntityCert::try_from(end_entity) else {
+            return false;
+        };
+        let has_pin = |path: &VerifiedPath<'_>| {
+            let keys = iter::once(path.end_entity().subject_public_key_info()).chain(
+                path.intermediate_certificates()
+                    .map(|cert| cert.subject_public_key_info()),
+            );
+            let mut digests = keys.filter_map(|spki| sha256(&spki));
+            if digests.any(|digest| self.pins.contains(&digest)) {
+                Ok(())
+            } else {
+                Err(webpki::Error::UnknownIssuer)
+            }
+        };
+        cert.verify_for_usage(
+            self.algorithms.all,
+            &self.roots.roots,
+            intermediates,
+            now,
+            KeyUsage::server_auth(),
+            None,
+            Some(&has_pin),
+        )
+        .is_ok()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        if !self.pins.is_empty() && !self.pinned(end_entity, intermediates, now) {
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        io::{Read, Write},
+        net::{Ipv4Addr, SocketAddr, TcpListener},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use rustls::{
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+    };
+
+    use super::{spki_sha256, TlsTransport};
+    use crate::{
+        errors::ResolveError,
+        message::{
+            builder::MessageBuilder,
+            name::Name,
+            rdata::RData,
+            rr::ResourceRecord,
+            types::{DnsClass, RecordType},
+            Message,
+        },
+        transport::Transport,
+    };
+
+    /// A CA and a certificate for `dns.example` issued through an
+    /// intermediate, returning the roots trusting the CA, the server
+    /// configuration and the server certificate.
+    pub(crate) fn certificates() -> (RootCertStore, Arc<ServerConfig>, CertificateDer<'static>) {
+        let (roots, config, chain) = chain(vec![]);
+        (roots, config, chain[0].clone())
+    }
+
+    /// a CA key and certificate, issued by `issuer` or self-signed.
+    fn authority(
+        issuer: Option<&Issuer<'_, &KeyPair>>,
+    ) -> (CertificateParams, KeyPair, CertificateDer<'static>) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let cert = match issuer {
+            Some(issuer) => params.signed_by(&key, issuer).unwrap(),
+            None => params.self_signed(&key).unwrap(),
+        };
+        (params, key, cert.der().clone())
+    }
+
+    /// Like `certificates`, with the server sending `extra` after its chain.
+    /// Returns the chain sent, starting with the server certificate and
+    /// its intermediate.
+    fn chain(
+        extra: Vec<CertificateDer<'static>>,
+    ) -> (
+        RootCertStore,
+        Arc<ServerConfig>,
+        Vec<CertificateDer<'static>>,
+    ) {
+        let (ca_params, ca_key, root) = authority(None);
+        let (sub_params, sub_key, sub) = authority(Some(&Issuer::from_params(&ca_params, &ca_key)));
+
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["dns.example".to_string()])
+            .unwrap()
+            .signed_by(&key, &Issuer::from_params(&sub_params, &sub_key))
+            .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(root).unwrap();
+        let mut chain = vec![cert.der().clone(), sub];
+        chain.extend(extra);
+        let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                chain.clone(),
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+            )
+            .unwrap();
+        (roots, Arc::new(config), chain)
+    }
+
+    /// the response to `query`, with an A record for the question.
+    pub(crate) fn answer(query: &[u8]) -> Vec<u8> {
+        let (_, query) = Message::parse(query).unwrap();
+        let response = MessageBuilder::response_to(&query)
+            .answer(ResourceRecord {
+                name: query.qd[0].qname.clone(),
+                t: RecordType::A,
+                class: DnsClass::IN,
+                ttl: 300,
+                rdata: RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            })
+            .build()
+            .unwrap();
+        let mut buf = vec![0u8; 512];
+        let n = response.write(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    /// Serves DNS over TLS, counting connections. A query for
+    /// `first.example` is answered after the query that follows it.
+    pub(crate) fn serve(config: Arc<ServerConfig>) -> (SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        thread::spawn(move || {
+            for tcp in listener.incoming().map_while(Result::ok) {
+                count.fetch_add(1, Ordering::SeqCst);
+                let connection = ServerConnection::new(config.clone()).unwrap();
+                thread::spawn(move || {
+                    let mut stream = StreamOwned::new(connection, tcp);
+                    let mut held = None;
+                    loop {
+                        let mut len = [0u8; 2];
+                        if stream.read_exact(&mut len).is_err() {
+                            break;
+                        }
+                        let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                        stream.read_exact(&mut query).unwrap();
+                        let (_, msg) = Message::parse(&query).unwrap();
+                        if msg.qd[0].qname == "first.example".parse::<Name>().unwrap() {
+                            held = Some(query);
+                            continue;
+                        }
+                        for query in [Some(query), held.take()].into_iter().flatten() {
+                            let response = answer(&query);
+                            stream
+                                .write_all(&(response.len() as u16).to_be_bytes())
+                                .unwrap();
+                            stream.write_all(&response).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (addr, connections)
+    }
+
+    fn query(name: &str) -> Message {
+        Message::new_query(
+            &name.parse().unwrap(),
+            RecordType::A,
+            DnsClass::IN,
+            true,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn wire(msg: &Message) -> Vec<u8> {
+        let mut buf = vec![0u8; 512];
+        let n = msg.write(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    #[test]
+    fn test_reuse_and_pipeline() {
+        let (roots, config, _) = certificates();
+        let (server, connections) = serve(config);
+        let transport = TlsTransport::new("dns.example").unwrap().roots(roots);
+
+        for name in ["a.example", "b.example"] {
+            let response = transport.exchange(&query(name), server).unwrap();
+            assert_eq!(response.an[0].name, name.parse::<Name>().unwrap());
+        }
+
+        // the first response arrives second
+        let queries = [query("first.example"), query("second.example")];
+        let wires = queries.iter().map(wire).collect::<Vec<_>>();
+        let responses = transport.pipeline(&[&wires[0], &wires[1]], server).unwrap();
+        for (query, response) in queries.iter().zip(responses) {
+            let (_, response) = Message::parse(&response).unwrap();
+            assert_eq!(response.hdr.id, query.hdr.id);
+            assert_eq!(response.qd, query.qd);
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_authentication() {
+        let (roots, config, cert) = certificates();
+        let (server, _) = serve(config);
+        let pin = spki_sha256(&cert).unwrap();
+
+        let pinned = TlsTransport::new("dns.example")
+            .unwrap()
+            .roots(roots.clone())
+            .pin_spki(pin);
+        pinned.exchange(&query("a.example"), server).unwrap();
+
+        let wrong_pin = TlsTransport::new("dns.example")
+            .unwrap()
+            .roots(roots.clone())
+            .pin_spki([0; 32]);
+        assert!(matches!(
+            wrong_pin.exchange(&query("a.example"), server),
+            Err(ResolveError::Tls { .. })
+        ));
+
+        let wrong_name = TlsTransport::new("other.example").unwrap().roots(roots);
+        assert!(matches!(
+            wrong_name.exchange(&query("a.example"), server),
+            Err(ResolveError::Tls { .. })
+        ));
+
+        // the Mozilla roots do not include the test CA
+        let untrusted = TlsTransport::new("dns.example").unwrap();
+        assert!(matches!(
+            untrusted.exchange(&query("a.example"), server),
+            Err(ResolveError::Tls { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pins_on_the_verified_chain() {
+        // an intermediate of an unrelated CA, sent along with the chain
+        let (other_params, other_key, _) = authority(None);
+        let (_, _, unrelated) = authority(Some(&Issuer::from_params(&other_params, &other_key)));
+        let (roots, config, chain) = chain(vec![unrelated.clone()]);
+        let (server, _) = serve(config);
+        let pinned = |cert: &CertificateDer<'_>| {
+            TlsTransport::new("dns.example")
+                .unwrap()
+                .roots(roots.clone())
+                .pin_spki(spki_sha256(cert).unwrap())
+        };
+
+        pinned(&chain[1])
+            .exchange(&query("a.example"), server)
+            .unwrap();
+        assert!(matches!(
+            pinned(&unrelated).exchange(&query("a.example"), server),
+            Err(ResolveError::Tls { .. })
+        ));
+    }
+}
@@ -0,0 +1,675 @@
+// authenticated denial of existence, RFC 4035 5.4 and RFC 5155 8
+
+use data_encoding::BASE32HEX_NOPAD;
+use ring::digest;
+
+use crate::{
+    errors::{DnsError, ValidationError},
+    message::{
+        dnssec::{Nsec, Nsec3, NSEC3_FLAG_OPT_OUT},
+        name::Name,
+        rdata::RData,
+        rr::ResourceRecord,
+        types::RecordType,
+    },
+};
+
+/// NSEC3 hash algorithm, RFC 5155 11
+pub const NSEC3_HASH_SHA1: u8 = 1;
+
+/// NSEC3 records with more iterations than this are not hashed, and the
+/// denial is treated as insecure, RFC 9276 3.2
+pub const NSEC3_MAX_ITERATIONS: u16 = 150;
+
+/// Outcome of checking a denial of existence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    /// the records prove that the name or type does not exist.
+    Proven,
+    /// the name falls in an NSEC3 opt-out span, or the NSEC3 records use
+    /// more iterations than we are willing to compute, so an unsigned
+    /// delegation may exist.
+    Insecure,
+}
+
+fn unproven(name: &Name, reason: String) -> DnsError {
+    ValidationError::DenialNotProven {
+        name: name.clone(),
+        rtype: None,
+        reason,
+    }
+    .into()
+}
+
+/// Attributes a failed proof to the denial of the `rtype` records of
+/// `name`, rather than to the name the proof got stuck at.
+fn denying(
+    name: &Name,
+    rtype: Option<RecordType>,
+    proof: Result<Denial, DnsError>,
+) -> Result<Denial, DnsError> {
+    proof.map_err(|e| match e {
+        DnsError::Validation(ValidationError::DenialNotProven { reason, .. }) => {
+            ValidationError::DenialNotProven {
+                name: name.clone(),
+                rtype,
+                reason,
+            }
+            .into()
+        }
+        e => e,
+    })
+}
+
+/// Computes the NSEC3 hash of `name`, RFC 5155 5.
+pub fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Result<Vec<u8>, DnsError> {
+    let mut wire = vec![0u8; name.wire_len()];
+    name.to_lowercase().write(&mut wire)?;
+    let mut hash = wire;
+    for _ in 0..=iterations {
+        let mut ctx = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        ctx.update(&hash);
+        ctx.update(salt);
+        hash = ctx.finish().as_ref().to_vec();
+    }
+    Ok(hash)
+}
+
+/// true if the types mark a delegation point, i.e. the parent side of a
+/// zone cut.
+fn is_delegation(types: &[RecordType]) -> bool {
+    types.contains(&RecordType::NS) && !types.contains(&RecordType::SOA)
+}
+
+/// true if `types` prove that `t` does not exist at the name.
+fn lacks_type(types: &[RecordType], t: RecordType) -> bool {
+    if types.contains(&t) || types.contains(&RecordType::CNAME) {
+        return false;
+    }
+    if t == RecordType::DS {
+        // DS lives on the parent side, so the apex NSEC of the child proves
+        // nothing
+        return !types.contains(&RecordType::SOA);
+    }
+    // the parent side of a delegation says nothing about the child's data
+    !is_delegation(types)
+}
+
+/// the closest common ancestor of `a` and `b`.
+fn common_ancestor(a: &Name, b: &Name) -> Name {
+    let mut n = a.clone();
+    while !b.is_subdomain_of(&n) {
+        n = n.parent().unwrap_or_default();
+    }
+    n
+}
+
+/// `name` shortened to its rightmost `labels` labels.
+fn truncate(name: &Name, labels: usize) -> Name {
+    let mut n = name.clone();
+    while n.num_labels() > labels {
+        n = n.parent().unwrap_or_default();
+    }
+    n
+}
+
+struct Hashed<'a> {
+    hash: Vec<u8>,
+    nsec3: &'a Nsec3,
+}
+
+impl Hashed<'_> {
+    fn hash_of(&self, name: &Name) -> Result<Vec<u8>, DnsError> {
+        nsec3_hash(name, &self.nsec3.salt, self.nsec3.iterations)
+    }
+
+    fn matches(&self, name: &Name) -> Result<bool, DnsError> {
+        Ok(self.hash_of(name)? == self.hash)
+    }
+
+    fn covers(&self, name: &Name) -> Result<bool, DnsError> {
+        let h = self.hash_of(name)?;
+        let next = &self.nsec3.next_hashed_owner;
+        Ok(if self.hash < *next {
+            self.hash < h && h < *next
+        } else {
+            // the last record in the chain wraps around to the first
+            h > self.hash || h < *next
+        })
+    }
+}
+
+/// The denial records of a response, which must have been authenticated.
+enum Chain<'a> {
+    Nsec(Vec<(&'a Name, &'a Nsec)>),
+    Nsec3(Vec<Hashed<'a>>),
+    /// NSEC3 records over the iteration limit.
+    Unhashed,
+}
+
+impl<'a> Chain<'a> {
+    fn new(zone: &Name, records: &'a [ResourceRecord]) -> Result<Self, DnsError> {
+        let mut nsecs = vec![];
+        let mut hashed = vec![];
+        for r in records {
+            match &r.rdata {
+                RData::NSEC(nsec) if r.name.is_subdomain_of(zone) => nsecs.push((&r.name, nsec)),
+                RData::NSEC3(nsec3) => {
+                    // NSEC3 records with unknown algorithms or flags are
+                    // ignored, RFC 5155 8.1 and 8.2
+                    if nsec3.hash_algorithm != NSEC3_HASH_SHA1
+                        || nsec3.flags & !NSEC3_FLAG_OPT_OUT != 0
+                        || r.name.parent().as_ref() != Some(zone)
+                    {
+                        continue;
+                    }
+                    if nsec3.iterations > NSEC3_MAX_ITERATIONS {
+                        return Ok(Self::Unhashed);
+                    }
+                    let hash = BASE32HEX_NOPAD
+                        .decode(&r.name.labels()[0].to_ascii_uppercase())
+                        .ok()
+                        .filter(|h| h.len() == nsec3.next_hashed_owner.len());
+                    if let Some(hash) = hash {
+                        hashed.push(Hashed { hash, nsec3 });
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !hashed.is_empty() {
+            Ok(Self::Nsec3(hashed))
+        } else if !nsecs.is_empty() {
+            Ok(Self::Nsec(nsecs))
+        } else {
+            Err(unproven(zone, "no NSEC or NSEC3 records".to_string()))
+        }
+    }
+}
+
+/// true if the NSEC at `owner` proves that `name` does not exist.
+fn nsec_covers(owner: &Name, nsec: &Nsec, name: &Name) -> bool {
+    // names below a delegation or DNAME are not part of the zone, so the
+    // NSEC there says nothing about them, RFC 6840 4.1
+    if name != owner
+        && name.is_subdomain_of(owner)
+        && (is_delegation(&nsec.types) || nsec.types.contains(&RecordType::DNAME))
+    {
+        return false;
+    }
+    let next = &nsec.next_domain;
+    if owner < next {
+        owner < name && name < next
+    } else {
+        // the last NSEC in the zone points back at the apex
+        name > owner || name < next
+    }
+}
+
+/// Finds the NSEC covering `name` and the closest encloser it implies.
+fn nsec_closest_encloser(nsecs: &[(&Name, &Nsec)], name: &Name) -> Result<Name, DnsError> {
+    let (owner, nsec) = nsecs
+        .iter()
+        .find(|(owner, nsec)| nsec_covers(owner, nsec, name))
+        .ok_or_else(|| unproven(name, format!("no NSEC covers {}", name)))?;
+    let a = common_ancestor(name, owner);
+    let b = common_ancestor(name, &nsec.next_domain);
+    Ok(if a.num_labels() > b.num_labels() {
+        a
+    } else {
+        b
+    })
+}
+
+/// Finds the closest encloser of `name`, RFC 5155 8.3. Returns it with the
+/// NSEC3 covering the next closer name.
+fn nsec3_closest_encloser<'a, 'b>(
+    hashed: &'b [Hashed<'a>],
+    zone: &Name,
+    name: &Name,
+) -> Result<(Name, &'b Hashed<'a>), DnsError> {
+    let mut next_closer = name.clone();
+    while let Some(candidate) = next_closer.parent() {
+        if !candidate.is_subdomain_of(zone) {
+            break;
+        }
+        if let Some(m) = find_match(hashed, &candidate)? {
+            if is_delegation(&m.nsec3.types) || m.nsec3.types.contains(&RecordType::DNAME) {
+                return Err(unproven(
+                    name,
+                    format!("closest encloser {} is a delegation or DNAME", candidate),
+                ));
+            }
+            let cover = find_cover(hashed, &next_closer)?.ok_or_else(|| {
+                unproven(
+                    name,
+                    format!("no NSEC3 covers next closer name {}", next_closer),
+                )
+            })?;
+            return Ok((candidate, cover));
+        }
+        next_closer = candidate;
+    }
+    Err(unproven(name, "no closest encloser".to_string()))
+}
+
+fn find_match<'a, 'b>(
+    hashed: &'b [Hashed<'a>],
+    name: &Name,
+) -> Result<Option<&'b Hashed<'a>>, DnsError> {
+    for h in hashed {
+        if h.matches(name)? {
+            return Ok(Some(h));
+        }
+    }
+    Ok(None)
+}
+
+fn find_cover<'a, 'b>(
+    hashed: &'b [Hashed<'a>],
+    name: &Name,
+) -> Result<Option<&'b Hashed<'a>>, DnsError> {
+    for h in hashed {
+        if h.covers(name)? {
+            return Ok(Some(h));
+        }
+    }
+    Ok(None)
+}
+
+fn check_zone(name: &Name, zone: &Name) -> Result<(), DnsError> {
+    if !name.is_subdomain_of(zone) {
+        return Err(unproven(name, format!("not in zone {}", zone)));
+    }
+    Ok(())
+}
+
+/// Checks that the authenticated NSEC or NSEC3 `records` from `zone` prove
+/// that `name` does not exist, for an NXDOMAIN response.
+pub fn prove_nxdomain(
+    name: &Name,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    denying(name, None, nxdomain_proof(name, zone, records))
+}
+
+fn nxdomain_proof(
+    name: &Name,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    check_zone(name, zone)?;
+    match Chain::new(zone, records)? {
+        Chain::Nsec(nsecs) => {
+            // RFC 4035 5.4: the name and the wildcard at its closest
+            // encloser must both be covered
+            let ce = nsec_closest_encloser(&nsecs, name)?;
+            let wildcard = ce.child("*")?;
+            if !nsecs.iter().any(|(o, n)| nsec_covers(o, n, &wildcard)) {
+                return Err(unproven(name, format!("no NSEC covers {}", wildcard)));
+            }
+            Ok(Denial::Proven)
+        }
+        Chain::Nsec3(hashed) => {
+            // RFC 5155 8.4
+            let (ce, next_closer) = nsec3_closest_encloser(&hashed, zone, name)?;
+            let wildcard = ce.child("*")?;
+            if find_cover(&hashed, &wildcard)?.is_none() {
+                return Err(unproven(name, format!("no NSEC3 covers {}", wildcard)));
+            }
+            if next_closer.nsec3.opt_out() {
+                return Ok(Denial::Insecure);
+            }
+            Ok(Denial::Proven)
+        }
+        Chain::Unhashed => Ok(Denial::Insecure),
+    }
+}
+
+/// Checks that the authenticated NSEC or NSEC3 `records` from `zone` prove
+/// that `name` has no records of type `t`, for a NODATA response.
+pub fn prove_nodata(
+    name: &Name,
+    t: RecordType,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    denying(name, Some(t), nodata_proof(name, t, zone, records))
+}
+
+fn nodata_proof(
+    name: &Name,
+    t: RecordType,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    check_zone(name, zone)?;
+    let missing = || unproven(name, "the type may exist".to_string());
+    match Chain::new(zone, records)? {
+        Chain::Nsec(nsecs) => {
+            if let Some((_, nsec)) = nsecs.iter().find(|(owner, _)| *owner == name) {
+                return if lacks_type(&nsec.types, t) {
+                    Ok(Denial::Proven)
+                } else {
+                    Err(missing())
+                };
+            }
+            // an empty non-terminal has no NSEC, but the NSEC before it
+            // points below it, RFC 4035 3.1.3.2
+            let empty_non_terminal = nsecs.iter().any(|(owner, nsec)| {
+                nsec_covers(owner, nsec, name) && nsec.next_domain.is_subdomain_of(name)
+            });
+            if empty_non_terminal {
+                return Ok(Denial::Proven);
+            }
+            // the name would have been synthesized from a wildcard that
+            // lacks the type, RFC 4035 3.1.3.4
+            let wildcard = nsec_closest_encloser(&nsecs, name)?.child("*")?;
+            match nsecs.iter().find(|(owner, _)| **owner == wildcard) {
+                Some((_, nsec)) if lacks_type(&nsec.types, t) => Ok(Denial::Proven),
+                _ => Err(missing()),
+            }
+        }
+        Chain::Nsec3(hashed) => {
+            // RFC 5155 8.5 and 8.6
+            if let Some(m) = find_match(&hashed, name)? {
+                return if lacks_type(&m.nsec3.types, t) {
+                    Ok(Denial::Proven)
+                } else {
+                    Err(missing())
+                };
+            }
+            let (ce, next_closer) = nsec3_closest_encloser(&hashed, zone, name)?;
+            if t == RecordType::DS && next_closer.nsec3.opt_out() {
+                return Ok(Denial::Insecure);
+            }
+            // RFC 5155 8.7
+            match find_match(&hashed, &ce.child("*")?)? {
+                Some(m) if lacks_type(&m.nsec3.types, t) => Ok(Denial::Proven),
+                _ => Err(missing()),
+            }
+        }
+        Chain::Unhashed => Ok(Denial::Insecure),
+    }
+}
+
+/// Checks that `name`, answered from a wildcard whose RRSIG has `labels`
+/// labels, has no closer match in `zone`, RFC 4035 5.3.4 and RFC 5155 8.8.
+pub fn prove_wildcard_answer(
+    name: &Name,
+    labels: u8,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    denying(
+        name,
+        None,
+        wildcard_answer_proof(name, labels, zone, records),
+    )
+}
+
+fn wildcard_answer_proof(
+    name: &Name,
+    labels: u8,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, DnsError> {
+    check_zone(name, zone)?;
+    let next_closer = truncate(name, labels as usize + 1);
+    match Chain::new(zone, records)? {
+        Chain::Nsec(nsecs) => {
+            if !nsecs.iter().any(|(o, n)| nsec_covers(o, n, name)) {
+                return Err(unproven(name, format!("no NSEC covers {}", name)));
+            }
+            Ok(Denial::Proven)
+        }
+        Chain::Nsec3(hashed) => match find_cover(&hashed, &next_closer)? {
+            Some(h) if h.nsec3.opt_out() => Ok(Denial::Insecure),
+            Some(_) => Ok(Denial::Proven),
+            None => Err(unproven(name, format!("no NSEC3 covers {}", next_closer))),
+        },
+        Chain::Unhashed => Ok(Denial::Insecure),
+    }
+}
+
+/// The types the authenticated `records` list for `name`, if any record
+/// matches it exactly.
+pub fn types_at(
+    name: &Name,
+    zone: &Name,
+    records: &[ResourceRecord],
+) -> Result<Option<Vec<RecordType>>, DnsError> {
+    Ok(match Chain::new(zone, records)? {
+        Chain::Nsec(nsecs) => nsecs
+            .iter()
+            .find(|(owner, _)| *owner == name)
+            .map(|(_, nsec)| nsec.types.clone()),
+        Chain::Nsec3(hashed) => find_match(&hashed, name)?.map(|m| m.nsec3.types.clone()),
+        Chain::Unhashed => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use data_encoding::BASE32HEX_NOPAD;
+
+    use super::{
+        nsec3_hash, prove_nodata, prove_nxdomain, prove_wildcard_answer, Denial,
+        NSEC3_MAX_ITERATIONS,
+    };
+    use crate::{
+        errors::{DnsError, ValidationError},
+        message::{
+            dnssec::{Nsec, Nsec3, NSEC3_FLAG_OPT_OUT},
+            name::Name,
+            rdata::RData,
+            rr::ResourceRecord,
+            types::{DnsClass, RecordType},
+        },
+    };
+
+    use RecordType::*;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    /// example.com with an empty non-terminal at c, a wildcard below w and
+    /// an unsigned delegation to sub.
+    const ZONE: &[(&str, &[RecordType])] = &[
+        ("example.com", &[NS, SOA, RRSIG, NSEC]),
+        ("a.example.com", &[A, RRSIG, NSEC]),
+        ("b.c.example.com", &[A, RRSIG, NSEC]),
+        ("sub.example.com", &[NS, RRSIG, NSEC]),
+        ("*.w.example.com", &[TXT, RRSIG, NSEC]),
+    ];
+
+    fn record(owner: Name, rdata: RData) -> ResourceRecord {
+        let t = match rdata {
+            RData::NSEC(_) => NSEC,
+            _ => NSEC3,
+        };
+        ResourceRecord {
+            name: owner,
+            t,
+            class: DnsClass::IN,
+            ttl: 3600,
+            rdata,
+        }
+    }
+
+    fn nsec_chain() -> Vec<ResourceRecord> {
+        let mut names = ZONE.iter().map(|(n, t)| (name(n), *t)).collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        (0..names.len())
+            .map(|i| {
+                let next = names[(i + 1) % names.len()].0.clone();
+                let nsec = Nsec {
+                    next_domain: next,
+                    types: names[i].1.to_vec(),
+                };
+                record(names[i].0.clone(), RData::NSEC(nsec))
+            })
+            .collect()
+    }
+
+    fn nsec3_chain(flags: u8, iterations: u16) -> Vec<ResourceRecord> {
+        let zone = name("example.com");
+        let mut names = ZONE.iter().map(|(n, t)| (name(n), *t)).collect::<Vec<_>>();
+        // empty non-terminals get their own NSEC3 records
+        names.push((name("c.example.com"), &[]));
+        names.push((name("w.example.com"), &[]));
+        let mut hashed = names
+            .iter()
+            .map(|(n, t)| (nsec3_hash(n, &[0xAB], iterations).unwrap(), *t))
+            .collect::<Vec<_>>();
+        hashed.sort();
+        (0..hashed.len())
+            .map(|i| {
+                let nsec3 = Nsec3 {
+                    hash_algorithm: 1,
+                    flags,
+                    iterations,
+                    salt: vec![0xAB],
+                    next_hashed_owner: hashed[(i + 1) % hashed.len()].0.clone(),
+                    types: hashed[i].1.to_vec(),
+                };
+                let owner = zone
+                    .child(BASE32HEX_NOPAD.encode(&hashed[i].0).to_ascii_lowercase())
+                    .unwrap();
+                record(owner, RData::NSEC3(nsec3))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nsec3_hash() {
+        // RFC 5155 Appendix A
+        let salt = [0xAA, 0xBB, 0xCC, 0xDD];
+        for (n, hash) in [
+            ("example", "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom"),
+            ("a.example", "35mthgpgcu1qg68fab165klnsnk3dpvl"),
+            ("ns1.example", "2t7b4g4vsa5smi47k61mv5bv1a22bojr"),
+            ("*.w.example", "r53bq7cc2uvmubfu5ocmm6pers9tk9en"),
+            ("x.y.w.example", "2vptu5timamqttgl4luu9kg21e0aor3s"),
+        ] {
+            let h = nsec3_hash(&name(n), &salt, 12).unwrap();
+            assert_eq!(BASE32HEX_NOPAD.encode(&h).to_ascii_lowercase(), hash);
+        }
+        // names are hashed in canonical form
+        assert_eq!(
+            nsec3_hash(&name("A.Example"), &salt, 12).unwrap(),
+            nsec3_hash(&name("a.example"), &salt, 12).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_nsec_proofs() {
+        let zone = name("example.com");
+        let records = nsec_chain();
+        let proven = Ok(Denial::Proven);
+
+        assert_eq!(
+            prove_nxdomain(&name("z.example.com"), &zone, &records),
+            proven
+        );
+        prove_nxdomain(&name("a.example.com"), &zone, &records).unwrap_err();
+        // below the delegation the parent's NSEC proves nothing
+        prove_nxdomain(&name("www.sub.example.com"), &zone, &records).unwrap_err();
+
+        assert_eq!(
+            prove_nodata(&name("a.example.com"), AAAA, &zone, &records),
+            proven
+        );
+        prove_nodata(&name("a.example.com"), A, &zone, &records).unwrap_err();
+        // empty non-terminal
+        assert_eq!(
+            prove_nodata(&name("c.example.com"), A, &zone, &records),
+            proven
+        );
+        // wildcard without the type
+        assert_eq!(
+            prove_nodata(&name("x.w.example.com"), MX, &zone, &records),
+            proven
+        );
+        prove_nodata(&name("x.w.example.com"), TXT, &zone, &records).unwrap_err();
+        // only DS can be denied at a delegation
+        assert_eq!(
+            prove_nodata(&name("sub.example.com"), DS, &zone, &records),
+            proven
+        );
+        prove_nodata(&name("sub.example.com"), A, &zone, &records).unwrap_err();
+
+        assert_eq!(
+            prove_wildcard_answer(&name("x.w.example.com"), 3, &zone, &records),
+            proven
+        );
+        prove_wildcard_answer(&name("a.example.com"), 2, &zone, &records).unwrap_err();
+    }
+
+    #[test]
+    fn test_nsec3_proofs() {
+        let zone = name("example.com");
+        let records = nsec3_chain(0, 0);
+        let proven = Ok(Denial::Proven);
+
+        assert_eq!(
+            prove_nxdomain(&name("z.example.com"), &zone, &records),
+            proven
+        );
+        prove_nxdomain(&name("a.example.com"), &zone, &records).unwrap_err();
+        prove_nxdomain(&name("z.example.com"), &name("example.net"), &records).unwrap_err();
+
+        assert_eq!(
+            prove_nodata(&name("a.example.com"), AAAA, &zone, &records),
+            proven
+        );
+        prove_nodata(&name("a.example.com"), A, &zone, &records).unwrap_err();
+        assert_eq!(
+            prove_nodata(&name("c.example.com"), A, &zone, &records),
+            proven
+        );
+        assert_eq!(
+            prove_nodata(&name("x.w.example.com"), MX, &zone, &records),
+            proven
+        );
+        prove_nodata(&name("x.w.example.com"), TXT, &zone, &records).unwrap_err();
+        // without opt-out a missing delegation is simply absent, and the
+        // failure names the denial that was asked for
+        assert!(matches!(
+            prove_nodata(&name("other.example.com"), DS, &zone, &records),
+            Err(DnsError::Validation(ValidationError::DenialNotProven {
+                name: n,
+                rtype: Some(DS),
+                ..
+            })) if n == name("other.example.com")
+        ));
+
+        assert_eq!(
+            prove_wildcard_answer(&name("x.w.example.com"), 3, &zone, &records),
+            proven
+        );
+    }
+
+    #[test]
+    fn test_nsec3_opt_out_and_iterations() {
+        let zone = name("example.com");
+        let records = nsec3_chain(NSEC3_FLAG_OPT_OUT, 0);
+        let insecure = Ok(Denial::Insecure);
+        assert_eq!(
+            prove_nodata(&name("other.example.com"), DS, &zone, &records),
+            insecure
+        );
+        assert_eq!(
+            prove_nxdomain(&name("z.example.com"), &zone, &records),
+            insecure
+        );
+
+        let records = nsec3_chain(0, NSEC3_MAX_ITERATIONS + 1);
+        assert_eq!(
+            prove_nxdomain(&name("z.example.com"), &zone, &records),
+            insecure
+        );
+    }
+}
@@ -0,0 +1,1057 @@
+// DNSSEC validation, RFC 4033, RFC 4034 and RFC 4035
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use data_encoding::HEXUPPER;
+use ring::{
+    digest,
+    signature::{self, UnparsedPublicKey},
+};
+
+use crate::{
+    errors::{DnsError, ValidationError},
+    message::{
+        dnssec::{Dnskey, Ds, Rrsig},
+        header::ResponseCode,
+        name::Name,
+        rdata::RData,
+        rr::ResourceRecord,
+        types::RecordType,
+        Message,
+    },
+};
+
+use self::denial::{prove_nodata, prove_nxdomain, prove_wildcard_answer, types_at, Denial};
+
+pub mod denial;
+
+pub const ALGORITHM_RSASHA256: u8 = 8;
+pub const ALGORITHM_RSASHA512: u8 = 10;
+pub const ALGORITHM_ECDSAP256SHA256: u8 = 13;
+pub const ALGORITHM_ECDSAP384SHA384: u8 = 14;
+pub const ALGORITHM_ED25519: u8 = 15;
+
+pub const DIGEST_SHA1: u8 = 1;
+pub const DIGEST_SHA256: u8 = 2;
+pub const DIGEST_SHA384: u8 = 4;
+
+/// DNSKEY protocol field, RFC 4034 2.1.2
+const DNSKEY_PROTOCOL: u8 = 3;
+
+/// The root zone trust anchor (KSK-2017), as published by IANA.
+pub fn root_trust_anchors() -> Vec<Ds> {
+    vec![Ds {
+        key_tag: 20326,
+        algorithm: ALGORITHM_RSASHA256,
+        digest_type: DIGEST_SHA256,
+        digest: HEXUPPER
+            .decode(b"E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D")
+            .unwrap(),
+    }]
+}
+
+/// Security status of a response, RFC 4035 4.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// a chain of signed DNSKEY and DS records leads from a trust anchor to
+    /// the data.
+    Secure,
+    /// the data lies below a delegation that is proven to be unsigned.
+    Insecure,
+    /// signatures are missing, expired or do not verify.
+    Bogus,
+    /// the chain of trust could not be followed, e.g. because a lookup
+    /// failed.
+    Indeterminate,
+}
+
+impl Security {
+    /// the weaker of two statuses, for responses made of several RRsets.
+    fn min(self, other: Self) -> Self {
+        use Security::*;
+        match (self, other) {
+            (Bogus, _) | (_, Bogus) => Bogus,
+            (Indeterminate, _) | (_, Indeterminate) => Indeterminate,
+            (Insecure, _) | (_, Insecure) => Insecure,
+            _ => Secure,
+        }
+    }
+}
+
+pub fn supported_algorithm(algorithm: u8) -> bool {
+    matches!(
+        algorithm,
+        ALGORITHM_RSASHA256
+            | ALGORITHM_RSASHA512
+            | ALGORITHM_ECDSAP256SHA256
+            | ALGORITHM_ECDSAP384SHA384
+            | ALGORITHM_ED25519
+    )
+}
+
+pub fn supported_digest(digest_type: u8) -> bool {
+    matches!(digest_type, DIGEST_SHA1 | DIGEST_SHA256 | DIGEST_SHA384)
+}
+
+/// Computes the DS digest of `key` owned by `owner`, RFC 4034 5.1.4. Returns
+/// `None` for unsupported digest types.
+pub fn ds_digest(owner: &Name, key: &Dnskey, digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        DIGEST_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        DIGEST_SHA256 => &digest::SHA256,
+        DIGEST_SHA384 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut ctx = digest::Context::new(algorithm);
+    ctx.update(&name_bytes(&owner.to_lowercase()).ok()?);
+    ctx.update(&key.to_bytes());
+    Some(ctx.finish().as_ref().to_vec())
+}
+
+fn ds_matches(ds: &Ds, owner: &Name, key: &Dnskey) -> bool {
+    ds.algorithm == key.algorithm
+        && ds.key_tag == key.key_tag()
+        && ds_digest(owner, key, ds.digest_type).is_some_and(|d| d == ds.digest)
+}
+
+fn name_bytes(name: &Name) -> Result<Vec<u8>, DnsError> {
+    let mut b = vec![0u8; name.wire_len()];
+    name.write(&mut b)?;
+    Ok(b)
+}
+
+fn rdata_bytes(rdata: &RData) -> Result<Vec<u8>, DnsError> {
+    let mut b = vec![0u8; u16::MAX as usize];
+    let n = rdata.write(&mut b)?;
+    b.truncate(n);
+    Ok(b)
+}
+
+/// Builds the data covered by `sig` over `rrset`, RFC 4034 3.1.8.1: the
+/// RRSIG rdata without the signature followed by the records in canonical
+/// form and order.
+pub fn signed_data(sig: &Rrsig, rrset: &[ResourceRecord]) -> Result<Vec<u8>, DnsError> {
+    let first = rrset.first().ok_or(ValidationError::EmptyRrset)?;
+
+    let mut data = rdata_bytes(
+        &RData::RRSIG(Rrsig {
+            signature: vec![],
+            ..sig.clone()
+        })
+        .to_canonical(),
+    )?;
+
+    // records matched by a wildcard are signed under the wildcard name,
+    // RFC 4035 5.3.2
+    let mut owner = first.name.to_lowercase();
+    let wildcard = owner.labels().first().is_some_and(|l| l == b"*");
+    if (sig.labels as usize) < owner.num_labels() - wildcard as usize {
+        while owner.num_labels() > sig.labels as usize {
+            owner = owner.parent().unwrap();
+        }
+        owner = owner.child("*")?;
+    }
+    let owner = name_bytes(&owner)?;
+
+    let mut rdatas = rrset
+        .iter()
+        .map(|r| rdata_bytes(&r.rdata.to_canonical()))
+        .collect::<Result<Vec<_>, _>>()?;
+    rdatas.sort();
+    rdatas.dedup();
+    for rdata in rdatas {
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&u16::from(first.t).to_be_bytes());
+        data.extend_from_slice(&u16::from(first.class).to_be_bytes());
+        data.extend_from_slice(&sig.original_ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+    Ok(data)
+}
+
+/// Splits an RSA public key into exponent and modulus, RFC 3110 2.
+fn rsa_public_key(b: &[u8]) -> Result<(&[u8], &[u8]), DnsError> {
+    let (len, rest) = match b {
+        [0, hi, lo, rest @ ..] => (u16::from_be_bytes([*hi, *lo]) as usize, rest),
+        [len, rest @ ..] => (*len as usize, rest),
+        [] => return Err(ValidationError::InvalidKey.into()),
+    };
+    if len == 0 || rest.len() <= len {
+        return Err(ValidationError::InvalidKey.into());
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Checks `sig` over `data` with `key`.
+pub fn verify_signature(key: &Dnskey, data: &[u8], sig: &[u8]) -> Result<(), DnsError> {
+    let result = match key.algorithm {
+        ALGORITHM_RSASHA256 | ALGORITHM_RSASHA512 => {
+            let (e, n) = rsa_public_key(&key.public_key)?;
+            let params = if key.algorithm == ALGORITHM_RSASHA256 {
+                &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY
+            } else {
+                &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY
+            };
+            signature::RsaPublicKeyComponents { n, e }.verify(params, data, sig)
+        }
+        ALGORITHM_ECDSAP256SHA256 | ALGORITHM_ECDSAP384SHA384 => {
+            let params = if key.algorithm == ALGORITHM_ECDSAP256SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            // the key holds the bare point coordinates, RFC 6605 4
+            let mut point = vec![0x04];
+            point.extend_from_slice(&key.public_key);
+            UnparsedPublicKey::new(params, point).verify(data, sig)
+        }
+        ALGORITHM_ED25519 => {
+            UnparsedPublicKey::new(&signature::ED25519, &key.public_key).verify(data, sig)
+        }
+        alg => return Err(ValidationError::UnsupportedAlgorithm(alg).into()),
+    };
+    result.map_err(|_| ValidationError::BadSignature.into())
+}
+
+/// Verifies `sig` over `rrset` with one of `keys` from the zone at `apex`,
+/// as of `now` seconds since the epoch, RFC 4035 5.3.
+pub fn verify_rrset(
+    rrset: &[ResourceRecord],
+    sig: &Rrsig,
+    apex: &Name,
+    keys: &[Dnskey],
+    now: u32,
+) -> Result<(), DnsError> {
+    let first = rrset.first().ok_or(ValidationError::EmptyRrset)?;
+    if sig.signer_name != *apex || !first.name.is_subdomain_of(apex) {
+        return Err(ValidationError::WrongSigner {
+            name: first.name.clone(),
+            zone: apex.clone(),
+        }
+        .into());
+    }
+    if sig.type_covered != first.t {
+        return Err(ValidationError::WrongType {
+            covered: sig.type_covered,
+            rtype: first.t,
+        }
+        .into());
+    }
+    if sig.labels as usize > first.name.num_labels() {
+        return Err(ValidationError::TooManyLabels {
+            labels: sig.labels,
+            name: first.name.clone(),
+        }
+        .into());
+    }
+    // timestamps use serial number arithmetic, RFC 4034 3.1.5
+    if (now.wrapping_sub(sig.inception) as i32) < 0 || (sig.expiration.wrapping_sub(now) as i32) < 0
+    {
+        return Err(ValidationError::OutsideValidity {
+            name: first.name.clone(),
+            rtype: first.t,
+        }
+        .into());
+    }
+
+    let data = signed_data(sig, rrset)?;
+    let verified = keys
+        .iter()
+        .filter(|k| {
+            k.is_zone_key()
+                && k.protocol == DNSKEY_PROTOCOL
+                && k.algorithm == sig.algorithm
+                && k.key_tag() == sig.key_tag
+        })
+        .any(|k| verify_signature(k, &data, &sig.signature).is_ok());
+    if !verified {
+        return Err(ValidationError::NoVerifyingKey {
+            zone: apex.clone(),
+            name: first.name.clone(),
+            rtype: first.t,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Groups records into RRsets, each with the signatures covering it.
+fn rrsets(records: &[ResourceRecord]) -> Vec<(Vec<ResourceRecord>, Vec<Rrsig>)> {
+    let mut sets: Vec<(Vec<ResourceRecord>, Vec<Rrsig>)> = vec![];
+    for r in records.iter().filter(|r| r.t != RecordType::RRSIG) {
+        match sets
+            .iter_mut()
+            .find(|(set, _)| set[0].t == r.t && set[0].class == r.class && set[0].name == r.name)
+        {
+            Some((set, _)) => set.push(r.clone()),
+            None => sets.push((vec![r.clone()], vec![])),
+        }
+    }
+    for r in records {
+        if let RData::RRSIG(sig) = &r.rdata {
+            if let Some((_, sigs)) = sets
+                .iter_mut()
+                .find(|(set, _)| set[0].t == sig.type_covered && set[0].name == r.name)
+            {
+                sigs.push(sig.clone());
+            }
+        }
+    }
+    sets
+}
+
+/// A zone whose keys have been authenticated from a trust anchor.
+#[derive(Debug, Clone)]
+struct SecureZone {
+    apex: Name,
+    keys: Vec<Dnskey>,
+}
+
+/// The trust established at a zone cut: its authenticated keys, or the
+/// status of everything below it.
+type Trust = Result<SecureZone, Security>;
+
+/// The trust established at each name a validator has checked, `None` if
+/// the name is not a zone cut. Passing it from one validator to the next
+/// saves fetching the same keys again for every response.
+#[derive(Debug, Default)]
+pub struct ZoneCuts(HashMap<Name, Option<Trust>>);
+
+/// Validates responses by following the chain of trust from the configured
+/// trust anchors down the delegation hierarchy.
+pub struct Validator<F> {
+    anchors: Vec<Ds>,
+    lookup: F,
+    now: u32,
+    cuts: ZoneCuts,
+}
+
+impl<F> Validator<F>
+where
+    F: FnMut(&Name, RecordType) -> Result<Message, DnsError>,
+{
+    /// Creates a validator for the root zone `anchors`. `lookup` resolves
+    /// the DNSKEY and DS queries needed to follow the chain of trust, and
+    /// should return the authoritative response with the DO bit set.
+    pub fn new(anchors: Vec<Ds>, lookup: F) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        Self {
+            anchors,
+            lookup,
+            now,
+            cuts: ZoneCuts::default(),
+        }
+    }
+
+    /// starts from the zone cuts found by an earlier validator.
+    pub fn with_cuts(mut self, cuts: ZoneCuts) -> Self {
+        self.cuts = cuts;
+        self
+    }
+
+    /// The zone cuts found so far. Indeterminate ones are left out, as they
+    /// usually come from a lookup that failed and is worth retrying.
+    pub fn into_cuts(self) -> ZoneCuts {
+        let mut cuts = self.cuts;
+        cuts.0
+            .retain(|_, cut| !matches!(cut, Some(Err(Security::Indeterminate))));
+        cuts
+    }
+
+    /// Sets the time signatures are checked against, in seconds since the
+    /// epoch.
+    pub fn set_time(&mut self, now: u32) {
+        self.now = now;
+    }
+
+    /// Determines the security status of the answer section of `response`,
+    /// using the NSEC and NSEC3 records in its authority section to check
+    /// negative and wildcard answers.
+    pub fn validate(&mut self, response: &Message) -> Security {
+        let Some(q) = response.qd.first() else {
+            return Security::Indeterminate;
+        };
+        if response.an.is_empty() {
+            return self.validate_denial(response, &q.qname, q.qtype);
+        }
+        rrsets(&response.an)
+            .iter()
+            .fold(Security::Secure, |status, (rrset, sigs)| {
+                status.min(self.validate_rrset(rrset, sigs, &response.ns))
+            })
+    }
+
+    /// the secure zone that `name` and its records of type `t` belong to.
+    fn zone_of(&mut self, name: &Name, t: RecordType) -> Trust {
+        // DS records belong to the parent side of the delegation
+        match (t, name.parent()) {
+            (RecordType::DS, Some(parent)) => self.trust(&parent),
+            _ => self.trust(name),
+        }
+    }
+
+    fn validate_rrset(
+        &mut self,
+        rrset: &[ResourceRecord],
+        sigs: &[Rrsig],
+        authority: &[ResourceRecord],
+    ) -> Security {
+        let owner = &rrset[0].name;
+        let zone = match self.zone_of(owner, rrset[0].t) {
+            Ok(zone) => zone,
+            Err(s) => return s,
+        };
+        let Some(sig) = sigs
+            .iter()
+            .find(|sig| verify_rrset(rrset, sig, &zone.apex, &zone.keys, self.now).is_ok())
+        else {
+            return Security::Bogus;
+        };
+
+        let wildcard = owner.labels().first().is_some_and(|l| l == b"*");
+        if (sig.labels as usize) < owner.num_labels() - wildcard as usize {
+            // the answer was synthesized from a wildcard, so the authority
+            // section must prove that there is no closer match
+            let records = match self.authenticated(authority, &zone) {
+                Ok(records) => records,
+                Err(s) => return s,
+            };
+            return match prove_wildcard_answer(owner, sig.labels, &zone.apex, &records) {
+                Ok(Denial::Proven) => Security::Secure,
+                Ok(Denial::Insecure) => Security::Insecure,
+                Err(_) => Security::Bogus,
+            };
+        }
+        Security::Secure
+    }
+
+    /// Checks the proof of an NXDOMAIN or NODATA response for `name` and
+    /// `t`.
+    fn validate_denial(&mut self, response: &Message, name: &Name, t: RecordType) -> Security {
+        let zone = match self.zone_of(name, t) {
+            Ok(zone) => zone,
+            Err(s) => return s,
+        };
+        let records = match self.authenticated(&response.ns, &zone) {
+            Ok(records) => records,
+            Err(s) => return s,
+        };
+        let proof = if response.hdr.rcode == ResponseCode::NameError {
+            prove_nxdomain(name, &zone.apex, &records)
+        } else {
+            prove_nodata(name, t, &zone.apex, &records)
+        };
+        match proof {
+            Ok(Denial::Proven) => Security::Secure,
+            Ok(Denial::Insecure) => Security::Insecure,
+            Err(_) => Security::Bogus,
+        }
+    }
+
+    /// The NSEC and NSEC3 records of `authority`, which must all be signed
+    /// by `zone`.
+    fn authenticated(
+        &self,
+        authority: &[ResourceRecord],
+        zone: &SecureZone,
+    ) -> Result<Vec<ResourceRecord>, Security> {
+        let mut records = vec![];
+        for (rrset, sigs) in rrsets(authority) {
+            if rrset[0].t != RecordType::NSEC && rrset[0].t != RecordType::NSEC3 {
+                continue;
+            }
+            let verified = sigs
+                .iter()
+                .any(|sig| verify_rrset(&rrset, sig, &zone.apex, &zone.keys, self.now).is_ok());
+            if !verified {
+                return Err(Security::Bogus);
+            }
+            records.extend(rrset);
+        }
+        Ok(records)
+    }
+
+    /// Follows the chain of trust from the root down to `name`, returning
+    /// the closest enclosing secure zone.
+    fn trust(&mut self, name: &Name) -> Trust {
+        let root = Name::root();
+        let mut zone = match self.cuts.0.get(&root) {
+            Some(Some(trust)) => trust.clone(),
+            _ => {
+                let anchors = self.anchors.clone();
+                let trust = self.fetch_keys(&root, &anchors);
+                self.cuts.0.insert(root, Some(trust.clone()));
+                trust
+            }
+        }?;
+
+        let mut ancestors = vec![];
+        let mut n = name.clone();
+        while let Some(parent) = n.parent() {
+            ancestors.push(n);
+            n = parent;
+        }
+        for n in ancestors.into_iter().rev() {
+            let cut = match self.cuts.0.get(&n) {
+                Some(cut) => cut.clone(),
+                None => {
+                    let cut = self.find_cut(&n, &zone);
+                    self.cuts.0.insert(n, cut.clone());
+                    cut
+                }
+            };
+            if let Some(trust) = cut {
+                zone = trust?;
+            }
+        }
+        Ok(zone)
+    }
+
+    /// Looks for a delegation at `name` below the secure zone `parent`.
+    fn find_cut(&mut self, name: &Name, parent: &SecureZone) -> Option<Trust> {
+        let response = match (self.lookup)(name, RecordType::DS) {
+            Ok(response) => response,
+            Err(_) => return Some(Err(Security::Indeterminate)),
+        };
+        let answers = rrsets(&response.an);
+        let ds_set = answers
+            .iter()
+            .find(|(rrset, _)| rrset[0].t == RecordType::DS && rrset[0].name == *name);
+        if let Some((rrset, sigs)) = ds_set {
+            let verified = sigs
+                .iter()
+                .any(|sig| verify_rrset(rrset, sig, &parent.apex, &parent.keys, self.now).is_ok());
+            if !verified {
+                return Some(Err(Security::Bogus));
+            }
+            let ds = rrset
+                .iter()
+                .filter_map(|r| match &r.rdata {
+                    RData::DS(ds) => Some(ds.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            return Some(self.fetch_keys(name, &ds));
+        }
+
+        // without a DS RRset the name is either inside the parent zone or an
+        // unsigned delegation, which the parent proves with an NSEC or NSEC3
+        // record listing NS but neither SOA nor DS
+        let records = match self.authenticated(&response.ns, parent) {
+            Ok(records) => records,
+            Err(s) => return Some(Err(s)),
+        };
+        let proof = if response.hdr.rcode == ResponseCode::NameError {
+            prove_nxdomain(name, &parent.apex, &records)
+        } else {
+            prove_nodata(name, RecordType::DS, &parent.apex, &records)
+        };
+        match proof {
+            Ok(Denial::Insecure) => Some(Err(Security::Insecure)),
+            Ok(Denial::Proven) => {
+                let types = types_at(name, &parent.apex, &records).ok().flatten();
+                let delegation = types.is_some_and(|types| {
+                    types.contains(&RecordType::NS) && !types.contains(&RecordType::SOA)
+                });
+                delegation.then_some(Err(Security::Insecure))
+            }
+            Err(_)
+                if response
+                    .ns
+                    .iter()
+                    .any(|r| r.t == RecordType::NS && r.name == *name) =>
+            {
+                // a referral without a DS RRset or a proof that there is
+                // none
+                Some(Err(Security::Bogus))
+            }
+            // not proven to be a delegation, so anything signed below it
+            // must still be signed by the parent
+            Err(_) => None,
+        }
+    }
+
+    /// Fetches and authenticates the DNSKEY RRset of the zone at `apex`
+    /// against the DS records `ds`.
+    fn fetch_keys(&mut self, apex: &Name, ds: &[Ds]) -> Trust {
+        let usable = ds
+            .iter()
+            .filter(|d| supported_algorithm(d.algorithm))
+            .filter(|d| supported_digest(d.digest_type))
+            .collect::<Vec<_>>();
+        if usable.is_empty() {
+            // a zone signed only with unsupported algorithms is treated as
+            // unsigned, RFC 4035 5.2
+            return Err(Security::Insecure);
+        }
+
+        let response =
+            (self.lookup)(apex, RecordType::DNSKEY).map_err(|_| Security::Indeterminate)?;
+        let answers = rrsets(&response.an);
+        let (rrset, sigs) = answers
+            .iter()
+            .find(|(rrset, _)| rrset[0].t == RecordType::DNSKEY && rrset[0].name == *apex)
+            .ok_or(Security::Bogus)?;
+        let keys = rrset
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::DNSKEY(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // the RRset must be signed by a key that one of the DS records
+        // refers to
+        let authenticated = keys
+            .iter()
+            .filter(|k| usable.iter().any(|d| ds_matches(d, apex, k)))
+            .any(|k| {
+                sigs.iter().any(|sig| {
+                    verify_rrset(rrset, sig, apex, std::slice::from_ref(k), self.now).is_ok()
+                })
+            });
+        if !authenticated {
+            return Err(Security::Bogus);
+        }
+        Ok(SecureZone {
+            apex: apex.clone(),
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use data_encoding::BASE64;
+    use ring::{
+        rand::SystemRandom,
+        signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair},
+    };
+
+    use super::{
+        ds_digest, root_trust_anchors, signed_data, verify_rrset, Security, Validator,
+        ALGORITHM_ECDSAP256SHA256, ALGORITHM_ECDSAP384SHA384, ALGORITHM_ED25519,
+        ALGORITHM_RSASHA256, DIGEST_SHA256,
+    };
+    use crate::{
+        errors::{DnsError, ValidationError},
+        message::{
+            builder::MessageBuilder,
+            dnssec::{Dnskey, Ds, Nsec, Rrsig},
+            header::ResponseCode,
+            name::Name,
+            rdata::RData,
+            rr::ResourceRecord,
+            types::{DnsClass, RecordType},
+            Message,
+        },
+    };
+
+    const NOW: u32 = 1_700_000_000;
+
+    /// 2048 bit RSA private key in PKCS#1 DER form, for tests only
+    const RSA_KEY: &str = "MIIEogIBAAKCAQEArkDBLG9GrsZ9mavYT9yLgIFn7R/AH7p0cW93PnBVPiSpBVnmxju5KiPeDyQzQR7Q73ewvvZJRvci+wb2kBRoct3Vf8YARaSg7TCD35zPAVQFwGD0mO+YixpHHIC2g9zfLH+Z06Ps+mFlc33NRPccYVYKSR8aWVLgAXGYgwr933FMgVpiGVuuQwhWCZOsN3P5DUX9uOxyuXJf2C2aX7xms8G9no66HsnhdpFoPDKA0dA6tMUGzUpIhzFqkZM/pJkPV3o21WpIVr7NSfRk0AwBV4Yax56KfOHpdyrtD141VLKT57NzSrqsUlEOovXkODutD9PXeTlQUteLcXW+QBzXpwIDAQABAoIBAFFo8ndKk7jTBlo1xY1mKgzDKKVJuK56I63dmFXlEIZEhWz5GA7UV0l/UV88l++cMJw4jKxjD1Am5UJneelNh5Dm4DjebkEbTY4TJQFjVvk1YqyC/7O1+MxAXinMXrkTh3bYNKuCwhQmTcob47uNQcad9pc7/0jtJbe29Tx/9aUemRNj5tKkvaq+0icBYXEIKB/J1eZYgYNyaxlP7rvxMEmHQMEDKzXutDdFXsPuJ1B9XBaczTLdc13zDPdlJ/U10e0KktxGLdQ9KbOsKWiIsyXpn+pojt0KtVHFK1BxkGDgld8WZADY2zm//grr3eIbNA/XXY73CBSFpxsUaB9q2eUCgYEA6ETc1WdkVFvbCIZkZDS/CINh7IXRzAM3ku5EdhSEEHYeLXiTfz8M1KFUNXPPaafYT6lBt9JXfVc8wWTqcUxv4avZbyBeH45y0mudpPJMkb5LbC3i2So8AIyNqJTw+OedGcm7fPVaCdplP0hKmrfI1RJLds9ySeNU/DZQ0ZKKvVsCgYEAwA5yYsxMa9zMA/mfH0F9CGJxuJ0fMUDYudyA70DByPFlHv9O81hauEdfr+B8+3m1qTxxMjI/HWFsi45jvddJckXzT9u7uu7B3JHcwQoOSE0VbVJypzr3DurNc3QNcAsAda0LFlD9OOTLs5JJdQmYzAAYkTzTNfrPWpSDsKswJKUCgYEA2IThK9p/D5i1PWQyj6sP7fqzvU4d/cV99+0/SqfjWIkTpqXBMZxv9IMTweRclIyPu+r3RUnCnyhEja/qUIXFwiR1w/7zXw1mgjspsMrij/ZJzuWTk4eoeYaMw3tci1s1+wUwTtNVBNxfB7f3O/VZNS8hw/ar/q752Uc03bhytwMCf0IEtU1A7QQm4fp71DbqTBCVxoGvgQltQRKiua+Ekxh/4kLFgF4mAQlXf4z1ngDT1Z5XKJIYs5ll4+oVCDQ5zQV7BihtYBrh98HaYgf5bO9tj/AEZ2UU39hA3l7Rhcz9+yp4UVdaf7EO9PbFUnro0Z1OayOBxRjJHn4Ej0ArmGECgYEAhxoK/Cr5iDI55jSczS3t98AkT+qSmIbMKIg/GkYDAn0uYwe9HBEFgAge9OIT7o7G8GIPXZtTi+CWi851oMS7H1C06eaQDXl1daWo57/ENAtBPEOclzfpNO3/5p/38UL9D/tYk0fn4ecpRHGA1hRJIOYA3VKkecM74bG6G8juqes=";
+
+    enum Signer {
+        Rsa(RsaKeyPair),
+        Ecdsa(EcdsaKeyPair),
+        Ed25519(Ed25519KeyPair),
+    }
+
+    impl Signer {
+        fn new(algorithm: u8) -> Self {
+            let rng = SystemRandom::new();
+            let ecdsa = |alg| {
+                let pkcs8 = EcdsaKeyPair::generate_pkcs8(alg, &rng).unwrap();
+                Self::Ecdsa(EcdsaKeyPair::from_pkcs8(alg, pkcs8.as_ref(), &rng).unwrap())
+            };
+            match algorithm {
+                ALGORITHM_RSASHA256 => Self::Rsa(
+                    RsaKeyPair::from_der(&BASE64.decode(RSA_KEY.as_bytes()).unwrap()).unwrap(),
+                ),
+                ALGORITHM_ECDSAP256SHA256 => ecdsa(&signature::ECDSA_P256_SHA256_FIXED_SIGNING),
+                ALGORITHM_ECDSAP384SHA384 => ecdsa(&signature::ECDSA_P384_SHA384_FIXED_SIGNING),
+                _ => Self::Ed25519(Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap()),
+            }
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            match self {
+                Self::Rsa(k) => {
+                    let c = signature::RsaPublicKeyComponents::<Vec<u8>>::from(k.public());
+                    let mut b = vec![c.e.len() as u8];
+                    b.extend_from_slice(&c.e);
+                    b.extend_from_slice(&c.n);
+                    b
+                }
+                Self::Ecdsa(k) => k.public_key().as_ref()[1..].to_vec(),
+                Self::Ed25519(k) => k.public_key().as_ref().to_vec(),
+            }
+        }
+
+        fn sign(&self, data: &[u8]) -> Vec<u8> {
+            let rng = SystemRandom::new();
+            match self {
+                Self::Rsa(k) => {
+                    let mut sig = vec![0; k.public().modulus_len()];
+                    k.sign(&signature::RSA_PKCS1_SHA256, &rng, data, &mut sig)
+                        .unwrap();
+                    sig
+                }
+                Self::Ecdsa(k) => k.sign(&rng, data).unwrap().as_ref().to_vec(),
+                Self::Ed25519(k) => k.sign(data).as_ref().to_vec(),
+            }
+        }
+    }
+
+    struct Zone {
+        apex: Name,
+        signer: Signer,
+        dnskey: Dnskey,
+    }
+
+    impl Zone {
+        fn new(apex: &str, algorithm: u8) -> Self {
+            let signer = Signer::new(algorithm);
+            let dnskey = Dnskey {
+                flags: 257,
+                protocol: 3,
+                algorithm,
+                public_key: signer.public_key(),
+            };
+            Self {
+                apex: apex.parse().unwrap(),
+                signer,
+                dnskey,
+            }
+        }
+
+        fn ds(&self) -> Ds {
+            Ds {
+                key_tag: self.dnskey.key_tag(),
+                algorithm: self.dnskey.algorithm,
+                digest_type: DIGEST_SHA256,
+                digest: ds_digest(&self.apex, &self.dnskey, DIGEST_SHA256).unwrap(),
+            }
+        }
+
+        fn sign(&self, rrset: &[ResourceRecord]) -> ResourceRecord {
+            let mut sig = Rrsig {
+                type_covered: rrset[0].t,
+                algorithm: self.dnskey.algorithm,
+                labels: rrset[0].name.num_labels() as u8,
+                original_ttl: rrset[0].ttl,
+                expiration: NOW + 86400,
+                inception: NOW - 86400,
+                key_tag: self.dnskey.key_tag(),
+                signer_name: self.apex.clone(),
+                signature: vec![],
+            };
+            sig.signature = self.signer.sign(&signed_data(&sig, rrset).unwrap());
+            ResourceRecord {
+                t: RecordType::RRSIG,
+                rdata: RData::RRSIG(sig),
+                ..rrset[0].clone()
+            }
+        }
+
+        /// the record and its signature
+        fn signed(&self, rr: ResourceRecord) -> Vec<ResourceRecord> {
+            let sig = self.sign(std::slice::from_ref(&rr));
+            vec![rr, sig]
+        }
+    }
+
+    fn record(name: &str, rdata: RData) -> ResourceRecord {
+        let t = match rdata {
+            RData::A(_) => RecordType::A,
+            RData::DS(_) => RecordType::DS,
+            RData::DNSKEY(_) => RecordType::DNSKEY,
+            RData::NSEC(_) => RecordType::NSEC,
+            _ => unreachable!(),
+        };
+        ResourceRecord {
+            name: name.parse().unwrap(),
+            t,
+            class: DnsClass::IN,
+            ttl: 3600,
+            rdata,
+        }
+    }
+
+    fn response(
+        qname: &Name,
+        t: RecordType,
+        an: Vec<ResourceRecord>,
+        ns: Vec<ResourceRecord>,
+    ) -> Message {
+        let query = Message::new_query(qname, t, DnsClass::IN, false, None).unwrap();
+        let mut builder = MessageBuilder::response_to(&query).authoritative(true);
+        for rr in an {
+            builder = builder.answer(rr);
+        }
+        for rr in ns {
+            builder = builder.authority(rr);
+        }
+        builder.build().unwrap()
+    }
+
+    /// root (RSA/SHA-256) delegating to com (ECDSA P-256) and org (ECDSA
+    /// P-384), with example.com (Ed25519) below com and an unsigned
+    /// delegation to insecure.com.
+    struct Hierarchy {
+        root: Zone,
+        com: Zone,
+        example: Zone,
+        responses: HashMap<(Name, RecordType), Message>,
+    }
+
+    impl Hierarchy {
+        fn new() -> Self {
+            let root = Zone::new(".", ALGORITHM_RSASHA256);
+            let com = Zone::new("com", ALGORITHM_ECDSAP256SHA256);
+            let org = Zone::new("org", ALGORITHM_ECDSAP384SHA384);
+            let example = Zone::new("example.com", ALGORITHM_ED25519);
+
+            let mut responses = HashMap::new();
+            for (parent, child) in [(&root, &com), (&root, &org), (&com, &example)] {
+                let ds = parent.signed(record(&child.apex.to_string(), RData::DS(child.ds())));
+                responses.insert(
+                    (child.apex.clone(), RecordType::DS),
+                    response(&child.apex, RecordType::DS, ds, vec![]),
+                );
+            }
+            for zone in [&root, &com, &org, &example] {
+                let keys = zone.signed(record(
+                    &zone.apex.to_string(),
+                    RData::DNSKEY(zone.dnskey.clone()),
+                ));
+                responses.insert(
+                    (zone.apex.clone(), RecordType::DNSKEY),
+                    response(&zone.apex, RecordType::DNSKEY, keys, vec![]),
+                );
+            }
+
+            let insecure: Name = "insecure.com".parse().unwrap();
+            let nsec = com.signed(record(
+                "insecure.com",
+                RData::NSEC(Nsec {
+                    next_domain: "zzz.com".parse().unwrap(),
+                    types: vec![RecordType::NS, RecordType::RRSIG, RecordType::NSEC],
+                }),
+            ));
+            responses.insert(
+                (insecure.clone(), RecordType::DS),
+                response(&insecure, RecordType::DS, vec![], nsec),
+            );
+
+            Self {
+                root,
+                com,
+                example,
+                responses,
+            }
+        }
+
+        fn validator(
+            &self,
+        ) -> Validator<impl FnMut(&Name, RecordType) -> Result<Message, DnsError> + '_> {
+            let anchors = vec![self.root.ds()];
+            let mut validator = Validator::new(anchors, |name: &Name, t| {
+                Ok(self
+                    .responses
+                    .get(&(name.clone(), t))
+                    .cloned()
+                    .unwrap_or_else(|| response(name, t, vec![], vec![])))
+            });
+            validator.set_time(NOW);
+            validator
+        }
+    }
+
+    fn a_response(an: Vec<ResourceRecord>) -> Message {
+        let qname = an[0].name.clone();
+        response(&qname, RecordType::A, an, vec![])
+    }
+
+    #[test]
+    fn test_root_trust_anchor() {
+        // the root KSK-2017 matches the published trust anchor
+        let key = Dnskey {
+            flags: 257,
+            protocol: 3,
+            algorithm: ALGORITHM_RSASHA256,
+            public_key: BASE64
+                .decode(b"AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=".as_slice())
+                .unwrap(),
+        };
+        let anchor = &root_trust_anchors()[0];
+        assert_eq!(key.key_tag(), anchor.key_tag);
+        assert_eq!(
+            ds_digest(&Name::root(), &key, DIGEST_SHA256).unwrap(),
+            anchor.digest
+        );
+    }
+
+    #[test]
+    fn test_secure_answers() {
+        let h = Hierarchy::new();
+        let mut validator = h.validator();
+
+        let www = h
+            .example
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        assert_eq!(validator.validate(&a_response(www)), Security::Secure);
+
+        // a signed answer directly below the ECDSA P-384 zone
+        let org: Name = "org".parse().unwrap();
+        let keys = h.responses[&(org.clone(), RecordType::DNSKEY)].clone();
+        assert_eq!(validator.validate(&keys), Security::Secure);
+    }
+
+    #[test]
+    fn test_bogus_answers() {
+        let h = Hierarchy::new();
+        let mut validator = h.validator();
+
+        let mut www = h
+            .example
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        www[0].rdata = RData::A([192, 0, 2, 2].into());
+        assert_eq!(validator.validate(&a_response(www)), Security::Bogus);
+
+        // missing signature
+        let www = vec![record("www.example.com", RData::A([192, 0, 2, 1].into()))];
+        assert_eq!(validator.validate(&a_response(www)), Security::Bogus);
+
+        // signed by the parent instead of the zone itself
+        let www = h
+            .com
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        assert_eq!(validator.validate(&a_response(www)), Security::Bogus);
+
+        // expired signature
+        let www = h
+            .example
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        validator.set_time(NOW + 2 * 86400);
+        assert_eq!(
+            validator.validate(&a_response(www.clone())),
+            Security::Bogus
+        );
+
+        let RData::RRSIG(sig) = &www[1].rdata else {
+            unreachable!()
+        };
+        let keys = [h.example.dnskey.clone()];
+        assert_eq!(
+            verify_rrset(&www[..1], sig, &h.example.apex, &keys, NOW + 2 * 86400),
+            Err(DnsError::Validation(ValidationError::OutsideValidity {
+                name: www[0].name.clone(),
+                rtype: RecordType::A,
+            }))
+        );
+        assert_eq!(
+            verify_rrset(&www[..1], sig, &h.com.apex, &keys, NOW),
+            Err(DnsError::Validation(ValidationError::WrongSigner {
+                name: www[0].name.clone(),
+                zone: h.com.apex.clone(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_denial_of_existence() {
+        let h = Hierarchy::new();
+        let mut validator = h.validator();
+        let nsec = |owner: &str, next: &str, types: Vec<RecordType>| {
+            let next_domain = next.parse().unwrap();
+            h.example
+                .signed(record(owner, RData::NSEC(Nsec { next_domain, types })))
+        };
+        // the zone holds only its apex and www
+        let apex = nsec(
+            "example.com",
+            "www.example.com",
+            vec![RecordType::NS, RecordType::SOA, RecordType::DNSKEY],
+        );
+        let www = nsec("www.example.com", "example.com", vec![RecordType::A]);
+
+        let missing: Name = "missing.example.com".parse().unwrap();
+        let mut nxdomain = response(&missing, RecordType::A, vec![], apex);
+        nxdomain.hdr.rcode = ResponseCode::NameError;
+        assert_eq!(validator.validate(&nxdomain), Security::Secure);
+
+        let qname: Name = "www.example.com".parse().unwrap();
+        let nodata = response(&qname, RecordType::AAAA, vec![], www.clone());
+        assert_eq!(validator.validate(&nodata), Security::Secure);
+
+        // the wrong NSEC, or none at all
+        nxdomain.ns = www;
+        assert_eq!(validator.validate(&nxdomain), Security::Bogus);
+        nxdomain.ns.clear();
+        assert_eq!(validator.validate(&nxdomain), Security::Bogus);
+    }
+
+    #[test]
+    fn test_insecure_delegation() {
+        let h = Hierarchy::new();
+        let mut validator = h.validator();
+        let www = vec![record("www.insecure.com", RData::A([192, 0, 2, 1].into()))];
+        assert_eq!(validator.validate(&a_response(www)), Security::Insecure);
+    }
+
+    #[test]
+    fn test_indeterminate() {
+        let h = Hierarchy::new();
+        let mut validator = Validator::new(vec![h.root.ds()], |name: &Name, _| {
+            Err(DnsError::Generic(format!("no response for {}", name)))
+        });
+        validator.set_time(NOW);
+        let www = h
+            .example
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        assert_eq!(
+            validator.validate(&a_response(www)),
+            Security::Indeterminate
+        );
+    }
+
+    #[test]
+    fn test_zone_cuts() {
+        let h = Hierarchy::new();
+        let www = h
+            .example
+            .signed(record("www.example.com", RData::A([192, 0, 2, 1].into())));
+        let failing = |name: &Name, _| Err(DnsError::Generic(format!("no response for {}", name)));
+
+        // indeterminate cuts are not passed on
+        let mut validator = Validator::new(vec![h.root.ds()], failing);
+        validator.set_time(NOW);
+        assert_eq!(
+            validator.validate(&a_response(www.clone())),
+            Security::Indeterminate
+        );
+        let mut validator = h.validator().with_cuts(validator.into_cuts());
+        assert_eq!(
+            validator.validate(&a_response(www.clone())),
+            Security::Secure
+        );
+
+        // the keys found by one validator are used by the next without
+        // looking them up again
+        let mut validator =
+            Validator::new(vec![h.root.ds()], failing).with_cuts(validator.into_cuts());
+        validator.set_time(NOW);
+        assert_eq!(validator.validate(&a_response(www)), Security::Secure);
+    }
+}